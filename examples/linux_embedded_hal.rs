@@ -0,0 +1,44 @@
+//! `linux-embedded-hal`でADT7410、MCP3208、ST7032を一度だけ読み書きする例
+//!
+//! ```sh
+//! $ cargo run --example linux_embedded_hal
+//! ```
+
+use linux_embedded_hal::{
+    spidev::{SpiModeFlags, SpidevOptions},
+    Delay, I2cdev, SpidevDevice,
+};
+use rpi_async::{
+    i2c::{adt7410::eh::ADT7410, st7032::eh::ST7032},
+    spi::mcp3208::eh::MCP3208,
+};
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut delay = Delay;
+
+    // 温度センサ
+    let mut adt7410 = ADT7410::new(I2cdev::new("/dev/i2c-1")?, 0x48);
+    let temp = adt7410.read()?;
+
+    // ADコンバータ
+    let mut spi = SpidevDevice::open("/dev/spidev0.0")?;
+    spi.0.configure(
+        &SpidevOptions::new()
+            .max_speed_hz(1_000_000)
+            .mode(SpiModeFlags::SPI_MODE_0)
+            .build(),
+    )?;
+    let bright = MCP3208::new(spi).read()?;
+
+    // ディスプレイ（I2Cのデバイスファイルは複数回開ける）
+    let mut display = ST7032::new(I2cdev::new("/dev/i2c-1")?, 0x3e);
+    display.init(Default::default(), &mut delay)?; // コントラスト32
+    display.print(
+        &format!("{temp:.2}°C"),
+        Some(&format!("{bright:.2} %")),
+        &mut delay,
+    )?;
+
+    Ok(())
+}
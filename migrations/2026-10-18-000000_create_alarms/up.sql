@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE alarms (
+  id SERIAL PRIMARY KEY,
+  datetime timestamp with time zone NOT NULL DEFAULT now(),
+  kind text NOT NULL,
+  raised boolean NOT NULL,
+  temperature real
+)
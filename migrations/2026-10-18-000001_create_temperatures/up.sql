@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE temperatures (
+  datetime timestamp with time zone NOT NULL,
+  sensor text NOT NULL,
+  temperature real,
+  PRIMARY KEY (datetime, sensor)
+)
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE ccs811_versions (
+  datetime timestamp with time zone NOT NULL PRIMARY KEY,
+  hw_version integer NOT NULL,
+  fw_boot_version text NOT NULL,
+  fw_app_version text NOT NULL
+)
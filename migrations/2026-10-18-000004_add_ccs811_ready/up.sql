@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE data ADD COLUMN ccs811_ready boolean
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE alarms ADD COLUMN sensor text
@@ -0,0 +1,176 @@
+//! 温度アラーム
+//!
+//! ADT7410のINT、CTピンの変化から発生したアラームを、購読しているタスクに配信する。
+//! アラームの状態はセンサごとに管理する。
+//! LED、ディスプレイ、DBはイベントを待つだけでよく、温度を周期的に確認する必要はない。
+
+use crate::broadcast::Broadcast;
+use async_std::channel::Receiver;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// アラームの種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    High,     // T_HIGHを超えた（INT）
+    Low,      // T_LOWを下回った（INT）
+    Critical, // T_CRITを超えた（CT）
+}
+
+impl Kind {
+    const ALL: [Kind; 3] = [Kind::Critical, Kind::High, Kind::Low]; // 優先度順
+
+    fn bit(self) -> u8 {
+        match self {
+            Kind::High => 0b001,
+            Kind::Low => 0b010,
+            Kind::Critical => 0b100,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Kind::High => "HIGH",
+            Kind::Low => "LOW",
+            Kind::Critical => "CRIT",
+        };
+        f.write_str(s)
+    }
+}
+
+/// アラームの発生・解除
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub sensor: String, // ADT7410の名前
+    pub kind: Kind,
+    pub raised: bool, // true: 発生、false: 解除
+    pub temp: f64,    // 変化した時点の気温
+}
+
+/// アラームの配信
+///
+/// `Clone`で状態を共有する。
+#[derive(Clone, Default)]
+pub struct Alarms {
+    active: Arc<Mutex<BTreeMap<String, u8>>>, // センサごとの発生中のアラーム
+    events: Broadcast<Event>,
+}
+
+impl Alarms {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// 以降のイベントを受信する
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// イベントを配信
+    ///
+    /// 状態が変化しない場合（発生中のアラームの再発生など）は配信しない。
+    pub fn publish(&self, event: Event) {
+        let mut active = self.active.lock().unwrap();
+        let bits = active.entry(event.sensor.clone()).or_default();
+
+        let next = if event.raised {
+            *bits | event.kind.bit()
+        } else {
+            *bits & !event.kind.bit()
+        };
+        if next == *bits {
+            return;
+        }
+        *bits = next;
+
+        let verb = if event.raised { "raised" } else { "cleared" };
+        println!(
+            "alarm: {} {} {verb} at {:.2} 度",
+            event.sensor, event.kind, event.temp
+        );
+
+        self.events.send(event); // 状態の変化と同じ順に配信
+    }
+
+    /// いずれかのセンサでアラームが発生中か
+    pub fn is_active(&self, kind: Kind) -> bool {
+        let active = self.active.lock().unwrap();
+        active.values().any(|bits| bits & kind.bit() != 0)
+    }
+
+    /// センサでアラームが発生中か
+    pub fn is_raised(&self, sensor: &str, kind: Kind) -> bool {
+        let active = self.active.lock().unwrap();
+        active
+            .get(sensor)
+            .is_some_and(|bits| bits & kind.bit() != 0)
+    }
+
+    /// いずれかのセンサで発生中のアラーム（優先度順）
+    pub fn active(&self) -> Vec<Kind> {
+        Kind::ALL
+            .into_iter()
+            .filter(|k| self.is_active(*k))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn publish_and_subscribe() {
+        let alarms = Alarms::new();
+        let rx1 = alarms.subscribe();
+        let rx2 = alarms.subscribe();
+
+        let raised = Event {
+            sensor: "living".to_string(),
+            kind: Kind::High,
+            raised: true,
+            temp: 30.5,
+        };
+        alarms.publish(raised.clone());
+        alarms.publish(raised.clone()); // 発生中なので配信しない
+        assert_eq!(alarms.active(), vec![Kind::High]);
+
+        drop(rx2);
+        let cleared = Event {
+            raised: false,
+            ..raised.clone()
+        };
+        alarms.publish(cleared.clone());
+        assert!(alarms.active().is_empty());
+
+        assert_eq!(rx1.recv().await.unwrap(), raised);
+        assert_eq!(rx1.recv().await.unwrap(), cleared);
+        assert!(rx1.try_recv().is_err());
+        assert_eq!(alarms.events.subscribers(), 1);
+    }
+
+    #[test]
+    fn per_sensor() {
+        let alarms = Alarms::new();
+        let event = |sensor: &str, raised| Event {
+            sensor: sensor.to_string(),
+            kind: Kind::High,
+            raised,
+            temp: 31.0,
+        };
+        alarms.publish(event("living", true));
+        alarms.publish(event("bedroom", true));
+        assert!(alarms.is_raised("bedroom", Kind::High));
+
+        // 他のセンサで発生中のアラームは残る
+        alarms.publish(event("bedroom", false));
+        assert!(!alarms.is_raised("bedroom", Kind::High));
+        assert!(alarms.is_raised("living", Kind::High));
+        assert_eq!(alarms.active(), vec![Kind::High]);
+    }
+}
@@ -0,0 +1,77 @@
+//! イベントの配信
+//!
+//! 購読したタスクごとに上限の無いチャネルを用意し、同じイベントを送る。
+//! 温度アラーム、CO2濃度の段階、物理ボタンの配信に用いる。
+
+use async_std::channel::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// 購読者への配信
+///
+/// `Clone`で購読者を共有する。
+pub struct Broadcast<T> {
+    subscribers: Arc<Mutex<Vec<Sender<T>>>>,
+}
+
+impl<T> Clone for Broadcast<T> {
+    fn clone(&self) -> Self {
+        Broadcast {
+            subscribers: self.subscribers.clone(),
+        }
+    }
+}
+
+impl<T> Default for Broadcast<T> {
+    fn default() -> Self {
+        Broadcast {
+            subscribers: Default::default(),
+        }
+    }
+}
+
+impl<T: Clone> Broadcast<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// 以降のイベントを受信する
+    pub fn subscribe(&self) -> Receiver<T> {
+        let (tx, rx) = channel::unbounded();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// 全ての購読者に送る
+    pub fn send(&self, event: T) {
+        // 受信側が終了したものは削除
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.try_send(event.clone()).is_ok() || !tx.is_closed());
+    }
+
+    /// 購読者の数
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn send_to_subscribers() {
+        let events = Broadcast::new();
+        let rx1 = events.subscribe();
+        let rx2 = events.clone().subscribe();
+
+        events.send(1);
+        drop(rx2);
+        events.send(2);
+
+        assert_eq!(rx1.recv().await.unwrap(), 1);
+        assert_eq!(rx1.recv().await.unwrap(), 2);
+        assert_eq!(events.subscribers(), 1);
+    }
+}
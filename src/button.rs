@@ -0,0 +1,133 @@
+//! 物理ボタン
+//!
+//! GPIO入力のレベルの変化から短押しと長押しを判定し、購読しているタスクに配信する。
+//! ボタンは押している間High。
+
+use crate::broadcast::Broadcast;
+use async_std::channel::Receiver;
+use rppal::gpio::Level;
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// 押し方
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Press {
+    Short, // 長押しの時間より前に離した
+    Long,  // 長押しの時間が経過した（離すのを待たない）
+}
+
+impl fmt::Display for Press {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Press::Short => "short",
+            Press::Long => "long",
+        };
+        f.write_str(s)
+    }
+}
+
+/// 短押し・長押しの判定
+#[derive(Debug, Default)]
+pub struct Detector {
+    pressed_at: Option<Instant>, // 押した時刻
+    long_sent: bool,             // 今回の押下で長押しを判定済み
+}
+
+impl Detector {
+    /// 現在のレベルを反映し、判定できた押し方を返す
+    ///
+    /// 変化時のレベルに加え、押している間は周期的に呼び出すと長押しを判定できる。
+    pub fn update(&mut self, level: Level, now: Instant, long: Duration) -> Option<Press> {
+        match (level, self.pressed_at) {
+            (Level::High, None) => {
+                self.pressed_at = Some(now);
+                self.long_sent = false;
+                None
+            }
+            (Level::High, Some(t)) if !self.long_sent && now - t >= long => {
+                self.long_sent = true;
+                Some(Press::Long)
+            }
+            (Level::Low, Some(_)) => {
+                self.pressed_at = None;
+                (!self.long_sent).then_some(Press::Short)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 押し方の配信
+///
+/// `Clone`で購読者を共有する。
+#[derive(Clone, Default)]
+pub struct Button {
+    events: Broadcast<Press>,
+}
+
+impl Button {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// 以降の押下を受信する
+    pub fn subscribe(&self) -> Receiver<Press> {
+        self.events.subscribe()
+    }
+
+    /// 押下を配信
+    pub fn publish(&self, press: Press) {
+        println!("button: {press} press");
+        self.events.send(press);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_press() {
+        let long = Duration::from_secs(2);
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let mut detector = Detector::default();
+
+        // 短押し
+        assert_eq!(detector.update(Level::Low, at(0), long), None);
+        assert_eq!(detector.update(Level::High, at(100), long), None);
+        assert_eq!(detector.update(Level::High, at(300), long), None);
+        assert_eq!(
+            detector.update(Level::Low, at(900), long),
+            Some(Press::Short)
+        );
+        assert_eq!(detector.update(Level::Low, at(1100), long), None);
+
+        // 長押しは押している間に一度だけ判定し、離しても短押しにしない
+        assert_eq!(detector.update(Level::High, at(2000), long), None);
+        assert_eq!(detector.update(Level::High, at(3900), long), None);
+        assert_eq!(
+            detector.update(Level::High, at(4000), long),
+            Some(Press::Long)
+        );
+        assert_eq!(detector.update(Level::High, at(4200), long), None);
+        assert_eq!(detector.update(Level::Low, at(5000), long), None);
+    }
+
+    #[async_std::test]
+    async fn publish_and_subscribe() {
+        let button = Button::new();
+        let rx1 = button.subscribe();
+        let rx2 = button.subscribe();
+
+        button.publish(Press::Short);
+        drop(rx2);
+        button.publish(Press::Long);
+
+        assert_eq!(rx1.recv().await.unwrap(), Press::Short);
+        assert_eq!(rx1.recv().await.unwrap(), Press::Long);
+        assert_eq!(button.events.subscribers(), 1);
+    }
+}
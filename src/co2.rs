@@ -0,0 +1,177 @@
+//! CO2濃度の段階
+//!
+//! CCS811のTHRESHOLDSと同じ閾値でeCO2を低・中・高の段階に分け、段階が変わると購読しているタスクに配信する。
+//! 段階は読み込んだサンプルごとにソフトウェアで判定する。
+//! nINTを閾値の割り込みにしている場合、CCS811は段階が変わった時だけnINTをアクティブにし、
+//! その時だけ読み込むため、配信は割り込みを契機とする（STATUSには閾値を示すビットが無いため、段階は読み込んだ値で判定）。
+
+#[cfg(feature = "linux")]
+use crate::{broadcast::Broadcast, config};
+#[cfg(feature = "linux")]
+use async_std::channel::Receiver;
+use core::fmt;
+#[cfg(feature = "linux")]
+use std::sync::{Arc, Mutex};
+
+/// 段階を戻す時のヒステリシス（ppm）
+///
+/// CCS811のTHRESHOLDSにも書き込み、閾値の割り込みと揃える。
+pub const HYSTERESIS: u16 = 50;
+
+/// 段階の閾値（ppm）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Thresholds {
+    pub medium: u16, // これ以上は中
+    pub high: u16,   // これ以上は高
+}
+
+#[cfg(feature = "linux")]
+impl From<&config::CCS811> for Thresholds {
+    fn from(conf: &config::CCS811) -> Self {
+        Thresholds {
+            medium: conf.co2_medium,
+            high: conf.co2_high,
+        }
+    }
+}
+
+/// CO2濃度の段階
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Band {
+    Low,
+    Medium,
+    High,
+}
+
+impl Band {
+    /// `co2`の段階
+    ///
+    /// 下がる場合は閾値を`HYSTERESIS`以上下回るまで、`prev`の段階に留まる。
+    pub fn classify(co2: u16, prev: Option<Band>, thresholds: &Thresholds) -> Band {
+        let band = Self::of(co2, thresholds);
+        match prev {
+            Some(prev) if band < prev => {
+                Self::of(co2.saturating_add(HYSTERESIS), thresholds).min(prev)
+            }
+            _ => band,
+        }
+    }
+
+    fn of(co2: u16, thresholds: &Thresholds) -> Band {
+        if co2 >= thresholds.high {
+            Band::High
+        } else if co2 >= thresholds.medium {
+            Band::Medium
+        } else {
+            Band::Low
+        }
+    }
+}
+
+impl fmt::Display for Band {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Band::Low => "low",
+            Band::Medium => "medium",
+            Band::High => "high",
+        };
+        f.write_str(s)
+    }
+}
+
+/// 段階の変化
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub from: Option<Band>, // 起動後最初の測定では`None`
+    pub to: Band,
+    pub co2: u16, // 変化した時点のeCO2（ppm）
+}
+
+/// 段階の変化の配信
+///
+/// `Clone`で状態を共有する。
+#[cfg(feature = "linux")]
+#[derive(Clone, Default)]
+pub struct Bands {
+    band: Arc<Mutex<Option<Band>>>, // 現在の段階
+    events: Broadcast<Event>,
+}
+
+#[cfg(feature = "linux")]
+impl Bands {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// 以降のイベントを受信する
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// 現在の段階（未測定の場合は`None`）
+    pub fn current(&self) -> Option<Band> {
+        *self.band.lock().unwrap()
+    }
+
+    /// 測定したeCO2を反映し、段階が変われば配信
+    pub fn update(&self, co2: u16, thresholds: &Thresholds) {
+        let mut band = self.band.lock().unwrap();
+
+        let from = *band;
+        let to = Band::classify(co2, from, thresholds);
+        if from == Some(to) {
+            return;
+        }
+        *band = Some(to);
+
+        match from {
+            Some(from) => println!("co2: {from} -> {to} at {co2} ppm"),
+            None => println!("co2: {to} at {co2} ppm"),
+        }
+
+        self.events.send(Event { from, to, co2 });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLDS: Thresholds = Thresholds {
+        medium: 1500,
+        high: 2500,
+    };
+
+    #[test]
+    fn classify() {
+        assert_eq!(Band::classify(400, None, &THRESHOLDS), Band::Low);
+        assert_eq!(Band::classify(1500, None, &THRESHOLDS), Band::Medium);
+        assert_eq!(Band::classify(2500, None, &THRESHOLDS), Band::High);
+
+        // 下がる場合はヒステリシスを超えるまで留まる
+        let high = Some(Band::High);
+        assert_eq!(Band::classify(2460, high, &THRESHOLDS), Band::High);
+        assert_eq!(Band::classify(2449, high, &THRESHOLDS), Band::Medium);
+        assert_eq!(Band::classify(1000, high, &THRESHOLDS), Band::Low);
+    }
+
+    #[cfg(feature = "linux")]
+    #[async_std::test]
+    async fn publish_band_changes() {
+        let bands = Bands::new();
+        let rx = bands.subscribe();
+
+        bands.update(800, &THRESHOLDS);
+        bands.update(900, &THRESHOLDS); // 変化なし
+        bands.update(2600, &THRESHOLDS);
+        assert_eq!(bands.current(), Some(Band::High));
+
+        let ev = |from, to, co2| Event { from, to, co2 };
+        assert_eq!(rx.recv().await.unwrap(), ev(None, Band::Low, 800));
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            ev(Some(Band::Low), Band::High, 2600)
+        );
+        assert!(rx.try_recv().is_err());
+    }
+}
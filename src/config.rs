@@ -0,0 +1,772 @@
+use crate::{perror, EResult};
+use serde::Deserialize;
+use std::{
+    env, fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+const ENV_STR: &str = "RPI_ASYNC_CONFIG";
+const DEFAULT_PATH: &str = "rpi_async.toml";
+
+/// タスク間で共有する設定
+///
+/// SIGHUPで再読み込みされるため、各タスクは必要な時に読み出す。
+pub type SharedConfig = Arc<RwLock<Config>>;
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub gpio: Gpio,
+    pub adt7410: ADT7410,
+    pub ccs811: CCS811,
+    pub st7032: ST7032,
+    pub mcp3208: MCP3208,
+    pub db: Db,
+    pub shutdown: Shutdown,
+    pub supervisor: Supervisor,
+}
+
+/// GPIOピン番号（変更には再起動が必要）
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Gpio {
+    pub input_pin: u8,
+    pub led_pin: u8,
+    pub ccs811_wake_pin: u8,
+    pub adt7410_int_pin: Option<u8>, // 先頭のADT7410のINT。未指定の場合は監視しない
+    pub adt7410_ct_pin: Option<u8>,  // 先頭のADT7410のCT。未指定の場合は監視しない
+    pub ccs811_int_pin: Option<u8>,  // CCS811のnINT。未指定の場合はステータスを周期的に確認
+    pub ccs811_reset_pin: Option<u8>, // CCS811のnRESET。未指定の場合はSW_RESETでリセット
+    pub long_press_ms: u64,          // 物理スイッチをこれ以上押し続けると長押し
+}
+
+impl Default for Gpio {
+    fn default() -> Self {
+        Gpio {
+            input_pin: 5,
+            led_pin: 6,
+            ccs811_wake_pin: 21,
+            adt7410_int_pin: None,
+            adt7410_ct_pin: None,
+            ccs811_int_pin: None,
+            ccs811_reset_pin: None,
+            long_press_ms: 2000,
+        }
+    }
+}
+
+/// 温度センサ
+///
+/// 分解能と閾値は全てのセンサに共通。
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ADT7410 {
+    pub addr: u16,              // sensorsが空の場合のアドレス、再起動が必要
+    pub sensors: Vec<Sensor>,   // 同じバスの複数のセンサ、再起動が必要
+    pub interval_ms: u64,       // 測定間隔
+    pub resolution: Resolution, // 分解能
+    pub t_high: f64,            // これより高いとINTをアクティブにする（度）
+    pub t_low: f64,             // これより低いとINTをアクティブにする（度）
+    pub t_crit: f64,            // これより高いとCTをアクティブにする（度）
+    pub t_hyst: u8,             // 解除までのヒステリシス（0 - 15度）
+}
+
+impl Default for ADT7410 {
+    fn default() -> Self {
+        ADT7410 {
+            addr: 0x48,
+            sensors: Vec::new(),
+            interval_ms: 1000,
+            resolution: Resolution::default(),
+            t_high: 30.0,
+            t_low: 15.0,
+            t_crit: 40.0,
+            t_hyst: 1,
+        }
+    }
+}
+
+impl ADT7410 {
+    /// A0、A1ピンで選択できるアドレス
+    pub const ADDRS: RangeInclusive<u16> = 0x48..=0x4b;
+
+    /// `sensors`が空の場合のセンサ名
+    pub const DEFAULT_NAME: &'static str = "room";
+
+    /// 使用するセンサ
+    ///
+    /// `sensors`が空の場合は`addr`のセンサ一つ。
+    pub fn sensors(&self) -> Vec<Sensor> {
+        if self.sensors.is_empty() {
+            vec![Sensor {
+                name: Self::DEFAULT_NAME.to_string(),
+                addr: self.addr,
+                ..Default::default()
+            }]
+        } else {
+            self.sensors.clone()
+        }
+    }
+
+    /// アドレスの範囲、名前とアドレスの重複、閾値の大小とヒステリシスを確認
+    fn validate(&self) -> EResult<()> {
+        if !(self.t_low < self.t_high && self.t_high < self.t_crit) {
+            return Err(format!(
+                "config: adt7410: limits must be t_low < t_high < t_crit ({}, {}, {})",
+                self.t_low, self.t_high, self.t_crit
+            )
+            .into());
+        }
+        if self.t_hyst > 15 {
+            return Err(format!("config: adt7410: t_hyst {} must be 0 - 15", self.t_hyst).into());
+        }
+        let sensors = self.sensors();
+        for (i, s) in sensors.iter().enumerate() {
+            if !Self::ADDRS.contains(&s.addr) {
+                return Err(format!(
+                    "config: adt7410 {}: address 0x{:02x} is out of 0x48 - 0x4b",
+                    s.name, s.addr
+                )
+                .into());
+            }
+            if sensors[..i].iter().any(|t| t.name == s.name) {
+                return Err(format!("config: adt7410 {}: duplicate name", s.name).into());
+            }
+            if sensors[..i].iter().any(|t| t.addr == s.addr) {
+                return Err(format!(
+                    "config: adt7410 {}: duplicate address 0x{:02x}",
+                    s.name, s.addr
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 温度センサ一つの名前（設置場所）とアドレス
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Sensor {
+    pub name: String,
+    pub addr: u16,
+    #[serde(default)]
+    pub int_pin: Option<u8>, // INTを接続したGPIO。未指定の場合は監視しない
+    #[serde(default)]
+    pub ct_pin: Option<u8>, // CTを接続したGPIO。未指定の場合は監視しない
+}
+
+/// 温度センサの分解能
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum Resolution {
+    #[default]
+    #[serde(rename = "13bit")]
+    Bits13, // 0.0625 度
+    #[serde(rename = "16bit")]
+    Bits16, // 0.0078 度
+}
+
+/// 環境センサ
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CCS811 {
+    pub addr: u16,                          // 再起動が必要
+    pub drive_mode: DriveMode,              // 測定周期、再起動が必要
+    pub interval_ms: u64,                   // nINT未接続か閾値の割り込みの場合に待つ間隔
+    pub co2_min: u16,                       // これ未満のCO2は破棄
+    pub co2_max: u16,                       // これより大きいCO2は破棄
+    pub tvoc_max: u16,                      // これより大きいTVOCは破棄
+    pub co2_medium: u16,                    // CO2の段階を中とする閾値（THRESHOLDS）
+    pub co2_high: u16,                      // CO2の段階を高とする閾値（THRESHOLDS）
+    pub threshold_interrupt: bool,          // nINTを段階の変化時のみアクティブにする、再起動が必要
+    pub env_interval_ms: u64,               // 温度と湿度を書き込む間隔
+    pub temperature_sensor: Option<String>, // 補正に用いるADT7410の名前。未指定の場合は先頭
+    pub humidity: f64,                      // 湿度を測定していない場合の湿度（%）
+    pub baseline_path: Option<PathBuf>,     // ベースラインの保存先。未指定の場合は保存しない
+    pub baseline_warmup_min: u64,           // 起動からベースラインを保存し始めるまで（分）
+    pub baseline_interval_min: u64,         // ベースラインを保存する間隔（分）
+    pub baseline_max_age_h: u64,            // これより古いベースラインは書き戻さない（時間）
+    pub runtime_path: Option<PathBuf>, // 累積稼働時間の保存先。未指定の場合はバーンイン済みとする
+    pub runtime_interval_min: u64,     // 累積稼働時間を保存する間隔（分）
+    pub burn_in_h: u64,                // バーンインに必要な累積稼働時間（時間）
+    pub warmup_min: u64,               // 起動ごとのウォームアップ（分）
+    pub max_bus_errors: u32,           // 通信エラーがこの回数続いたらリセット
+    pub reject_window: usize,          // 棄却の割合を数える直近のサンプル数
+    pub max_reject_rate: f64,          // 棄却の割合がこれを超えるとセンサの異常を疑う
+}
+
+impl Default for CCS811 {
+    fn default() -> Self {
+        CCS811 {
+            addr: 0x5a,
+            drive_mode: DriveMode::default(),
+            interval_ms: 1000,
+            co2_min: 400,
+            co2_max: 8192,
+            tvoc_max: 1187,
+            co2_medium: 1500,
+            co2_high: 2500,
+            threshold_interrupt: false,
+            env_interval_ms: 60 * 1000,
+            temperature_sensor: None,
+            humidity: 50.0,
+            baseline_path: None,
+            baseline_warmup_min: 20,
+            baseline_interval_min: 60,
+            baseline_max_age_h: 7 * 24,
+            runtime_path: None,
+            runtime_interval_min: 10,
+            burn_in_h: 48,
+            warmup_min: 20,
+            max_bus_errors: 3,
+            reject_window: 20,
+            max_reject_rate: 0.5,
+        }
+    }
+}
+
+/// 環境センサの測定周期（MEAS_MODEのDRIVE_MODE）
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum DriveMode {
+    #[serde(rename = "idle")]
+    Idle, // 測定しない
+    #[default]
+    #[serde(rename = "1s")]
+    Secs1,
+    #[serde(rename = "10s")]
+    Secs10,
+    #[serde(rename = "60s")]
+    Secs60,
+    #[serde(rename = "250ms")]
+    Millis250, // RAW_DATAのみ更新される
+}
+
+impl DriveMode {
+    /// 測定周期（`Idle`は`None`）
+    pub fn period(self) -> Option<Duration> {
+        match self {
+            DriveMode::Idle => None,
+            DriveMode::Secs1 => Some(Duration::from_secs(1)),
+            DriveMode::Secs10 => Some(Duration::from_secs(10)),
+            DriveMode::Secs60 => Some(Duration::from_secs(60)),
+            DriveMode::Millis250 => Some(Duration::from_millis(250)),
+        }
+    }
+}
+
+/// ディスプレイ
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ST7032 {
+    pub addr: u16,               // 再起動が必要
+    pub interval_ms: u64,        // 表示の更新間隔
+    pub contrast: u8,            // 0 - 63
+    pub booster: bool,           // 昇圧回路を使う（3.3Vで動作させる場合）
+    pub follower: u8,            // ボルテージフォロワの増幅率（0 - 7）
+    pub icon: bool,              // アイコンを表示
+    pub cursor: bool,            // カーソルを表示
+    pub blink: bool,             // カーソル位置を点滅
+    pub pages: Vec<Page>,        // 表示するページ（順に切り替える）
+    pub page_interval_ms: u64,   // ページを自動で切り替える間隔。0の場合は切り替えない
+    pub dark_below: Option<f64>, // 明るさ（%）がこれ未満で暗いと判定。未指定の場合は減光しない
+    pub dark_hysteresis: f64,    // 暗いと判定した後、dark_belowよりこれだけ明るくなると元に戻す
+    pub dark_mode: DarkMode,     // 暗い間の表示
+    pub dim_contrast: u8,        // dark_modeが"dim"の場合のコントラスト
+    pub wake_ms: u64,            // 暗い間にボタンを押した後、元の表示に戻しておく時間
+}
+
+impl Default for ST7032 {
+    fn default() -> Self {
+        ST7032 {
+            addr: 0x3e,
+            interval_ms: 1000,
+            contrast: 32,
+            booster: true,
+            follower: 4,
+            icon: false,
+            cursor: false,
+            blink: true,
+            pages: vec![
+                Page::Temperature,
+                Page::Co2,
+                Page::MinMax,
+                Page::Status,
+                Page::Uptime,
+            ],
+            page_interval_ms: 5000,
+            dark_below: None,
+            dark_hysteresis: 5.0,
+            dark_mode: DarkMode::Dim,
+            dim_contrast: 8,
+            wake_ms: 30 * 1000,
+        }
+    }
+}
+
+impl ST7032 {
+    /// ページが空でないか、コントラストなどが範囲内か確認
+    fn validate(&self) -> EResult<()> {
+        if self.pages.is_empty() {
+            return Err("config: st7032: pages must not be empty".into());
+        }
+        if self.contrast > 63 || self.dim_contrast > 63 {
+            return Err("config: st7032: contrast must be 0 - 63".into());
+        }
+        if self.follower > 7 {
+            return Err(format!("config: st7032: follower {} must be 0 - 7", self.follower).into());
+        }
+        if self.dark_hysteresis < 0.0 {
+            return Err("config: st7032: dark_hysteresis must not be negative".into());
+        }
+        Ok(())
+    }
+}
+
+/// ディスプレイのページ
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Page {
+    Temperature, // 気温と明るさ
+    Co2,         // CO2とTVOC
+    MinMax,      // 今日の気温とCO2の最小・最大
+    Status,      // ネットワークとDBの状況
+    Uptime,      // 稼働時間
+}
+
+/// 暗い間の表示
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DarkMode {
+    #[default]
+    Dim, // コントラストをdim_contrastに下げる
+    Off, // 表示をオフにする
+}
+
+/// ADコンバータ
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MCP3208 {
+    pub clock: u32,       // Hz、再起動が必要
+    pub interval_ms: u64, // 測定間隔
+}
+
+impl Default for MCP3208 {
+    fn default() -> Self {
+        MCP3208 {
+            clock: 1000 * 1000, // 1 MHz
+            interval_ms: 1000,
+        }
+    }
+}
+
+/// データベース
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Db {
+    pub window_size: usize, // 平均・中央値を取るサンプル数、再起動が必要
+    pub interval_ms: u64,   // サンプリング間隔
+}
+
+impl Default for Db {
+    fn default() -> Self {
+        Db {
+            window_size: 5,
+            interval_ms: 1000,
+        }
+    }
+}
+
+/// 終了処理
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Shutdown {
+    pub deadline_secs: u64, // この時間内に終了しないタスクがあれば強制終了
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown { deadline_secs: 10 }
+    }
+}
+
+impl Shutdown {
+    pub fn deadline(&self) -> Duration {
+        Duration::from_secs(self.deadline_secs)
+    }
+}
+
+/// タスクの再起動
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Supervisor {
+    pub max_restarts: u32,       // 連続して再起動する回数の上限
+    pub initial_backoff_ms: u64, // 最初の再起動までの待ち時間
+    pub max_backoff_ms: u64,     // 待ち時間の上限
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Supervisor {
+            max_restarts: 5,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 60 * 1000,
+        }
+    }
+}
+
+/// ミリ秒を`Duration`に変換。0の場合は1ミリ秒とする
+pub fn millis(ms: u64) -> Duration {
+    Duration::from_millis(ms.max(1))
+}
+
+/// 設定ファイルのパス
+///
+/// 環境変数`RPI_ASYNC_CONFIG`で指定。未指定の場合は`rpi_async.toml`。
+pub fn path() -> PathBuf {
+    env::var_os(ENV_STR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_PATH))
+}
+
+/// 設定ファイルを読み込む
+///
+/// ファイルが存在しない場合はデフォルト値を用いる。
+pub fn load(path: &Path) -> EResult<Config> {
+    if !path.exists() {
+        println!("config: {} not found, using default values", path.display());
+        return Ok(Config::default());
+    }
+
+    let s = fs::read_to_string(path)?;
+    let config: Config = toml::from_str(&s)?;
+    config.validate()?;
+    Ok(config)
+}
+
+impl Config {
+    /// 各セクションの値と、セクション間の参照を確認
+    fn validate(&self) -> EResult<()> {
+        self.adt7410.validate()?;
+        self.validate_alarm_pins()?;
+        self.validate_ccs811()?;
+        self.st7032.validate()
+    }
+
+    /// 温度アラームのピンを含めたADT7410
+    ///
+    /// `gpio.adt7410_int_pin`、`gpio.adt7410_ct_pin`は先頭のセンサのピンで、センサごとの指定が優先。
+    pub fn alarm_sensors(&self) -> Vec<Sensor> {
+        let mut sensors = self.adt7410.sensors();
+        if let Some(first) = sensors.first_mut() {
+            first.int_pin = first.int_pin.or(self.gpio.adt7410_int_pin);
+            first.ct_pin = first.ct_pin.or(self.gpio.adt7410_ct_pin);
+        }
+        sensors
+    }
+
+    /// 温度アラームのピンの重複を確認
+    ///
+    /// INT、CTをまとめて一つのピンに接続すると、どのセンサのアラームか判定できない。
+    fn validate_alarm_pins(&self) -> EResult<()> {
+        let mut pins = Vec::new();
+        for s in self.alarm_sensors() {
+            for pin in [s.int_pin, s.ct_pin].into_iter().flatten() {
+                if pins.contains(&pin) {
+                    return Err(format!(
+                        "config: adt7410 {}: alarm pin {pin} is already used",
+                        s.name
+                    )
+                    .into());
+                }
+                pins.push(pin);
+            }
+        }
+        Ok(())
+    }
+
+    /// CCS811の補正に用いる温度センサがあるか、CO2の段階の閾値の順序を確認
+    fn validate_ccs811(&self) -> EResult<()> {
+        let conf = &self.ccs811;
+        if conf.co2_medium >= conf.co2_high {
+            return Err(format!(
+                "config: ccs811: co2_medium {} must be less than co2_high {}",
+                conf.co2_medium, conf.co2_high
+            )
+            .into());
+        }
+
+        if conf.co2_min >= conf.co2_max {
+            return Err(format!(
+                "config: ccs811: co2_min {} must be less than co2_max {}",
+                conf.co2_min, conf.co2_max
+            )
+            .into());
+        }
+
+        if conf.reject_window == 0 || !(0.0..=1.0).contains(&conf.max_reject_rate) {
+            return Err("config: ccs811: invalid reject_window or max_reject_rate".into());
+        }
+
+        match &conf.temperature_sensor {
+            Some(name) if !self.adt7410.sensors().iter().any(|s| &s.name == name) => {
+                Err(format!("config: ccs811: no ADT7410 named {name}").into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 設定ファイルを再読み込みし、実行中に反映可能な値のみを適用
+///
+/// 再起動が必要な値の変更は表示のみ行い、現在の値を維持する。
+pub fn reload(shared: &SharedConfig, path: &Path) {
+    let mut new = match load(path) {
+        Ok(config) => config,
+        Err(e) => {
+            perror!(e);
+            eprintln!(
+                "config: failed to reload {}, keeping current configuration",
+                path.display()
+            );
+            return;
+        }
+    };
+
+    let mut guard = shared.write().unwrap();
+    let restart = keep_restart_values(&guard, &mut new);
+
+    // 再起動が必要な値を戻した結果、参照先のセンサが無くなる場合など
+    if let Err(e) = new.validate() {
+        perror!(e);
+        eprintln!(
+            "config: failed to reload {}, keeping current configuration",
+            path.display()
+        );
+        return;
+    }
+
+    for name in restart.iter() {
+        println!("config: {name} changed, restart required to apply");
+    }
+
+    if *guard != new {
+        *guard = new;
+        println!("config: reloaded {}", path.display());
+    } else if restart.is_empty() {
+        println!("config: no changes in {}", path.display());
+    }
+}
+
+/// 再起動が必要な値を現在の値に戻し、変更された値の名前を返す
+fn keep_restart_values(current: &Config, new: &mut Config) -> Vec<&'static str> {
+    let mut restart = Vec::new();
+
+    macro_rules! keep {
+        ($($section: ident . $field: ident),*) => {
+            $(
+                if current.$section.$field != new.$section.$field {
+                    restart.push(concat!(stringify!($section), ".", stringify!($field)));
+                    new.$section.$field = current.$section.$field;
+                }
+            )*
+        };
+    }
+
+    keep!(
+        gpio.input_pin,
+        gpio.led_pin,
+        gpio.ccs811_wake_pin,
+        gpio.adt7410_int_pin,
+        gpio.adt7410_ct_pin,
+        gpio.ccs811_int_pin,
+        gpio.ccs811_reset_pin,
+        gpio.long_press_ms,
+        adt7410.addr,
+        ccs811.addr,
+        ccs811.drive_mode,
+        ccs811.threshold_interrupt,
+        st7032.addr,
+        mcp3208.clock,
+        db.window_size
+    );
+
+    if current.adt7410.sensors != new.adt7410.sensors {
+        restart.push("adt7410.sensors");
+        new.adt7410.sensors = current.adt7410.sensors.clone();
+    }
+
+    restart
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adt7410_sensors() {
+        let conf: Config = toml::from_str("[adt7410]\naddr = 0x49").unwrap();
+        assert_eq!(
+            conf.adt7410.sensors(),
+            vec![Sensor {
+                name: "room".to_string(),
+                addr: 0x49,
+                ..Default::default()
+            }]
+        );
+
+        let conf: Config = toml::from_str(
+            r#"
+            [[adt7410.sensors]]
+            name = "living"
+            addr = 0x48
+
+            [[adt7410.sensors]]
+            name = "bedroom"
+            addr = 0x4b
+            "#,
+        )
+        .unwrap();
+        assert!(conf.adt7410.validate().is_ok());
+        let names: Vec<String> = conf.adt7410.sensors().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["living", "bedroom"]);
+    }
+
+    #[test]
+    fn adt7410_invalid_sensors() {
+        let sensor = |name: &str, addr| Sensor {
+            name: name.to_string(),
+            addr,
+            ..Default::default()
+        };
+        for sensors in [
+            vec![sensor("a", 0x48), sensor("b", 0x4c)], // 範囲外
+            vec![sensor("a", 0x48), sensor("a", 0x49)], // 名前の重複
+            vec![sensor("a", 0x48), sensor("b", 0x48)], // アドレスの重複
+        ] {
+            let conf = ADT7410 {
+                sensors,
+                ..Default::default()
+            };
+            assert!(conf.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn adt7410_alarm_pins() {
+        let s = "[gpio]\nadt7410_int_pin = 23\nadt7410_ct_pin = 24\n\
+                 [[adt7410.sensors]]\nname = \"living\"\naddr = 0x48\n\
+                 [[adt7410.sensors]]\nname = \"bedroom\"\naddr = 0x49\nint_pin = 25\n";
+        let conf: Config = toml::from_str(s).unwrap();
+        assert!(conf.validate_alarm_pins().is_ok());
+        let pins: Vec<_> = conf
+            .alarm_sensors()
+            .into_iter()
+            .map(|s| (s.name, s.int_pin, s.ct_pin))
+            .collect();
+        assert_eq!(
+            pins,
+            vec![
+                ("living".to_string(), Some(23), Some(24)),
+                ("bedroom".to_string(), Some(25), None),
+            ]
+        );
+
+        // 同じピンに接続したINT
+        let conf: Config = toml::from_str(&s.replace("25", "23")).unwrap();
+        assert!(conf.validate_alarm_pins().is_err());
+    }
+
+    #[test]
+    fn adt7410_invalid_limits() {
+        let default = ADT7410::default();
+        assert!(default.validate().is_ok());
+        for conf in [
+            ADT7410 {
+                t_low: 30.0, // t_highと同じ
+                ..default.clone()
+            },
+            ADT7410 {
+                t_crit: 25.0, // t_highより低い
+                ..default.clone()
+            },
+            ADT7410 {
+                t_hyst: 16,
+                ..default.clone()
+            },
+        ] {
+            assert!(conf.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn st7032_pages() {
+        let conf: Config = toml::from_str("[st7032]\npages = [\"co2\", \"minmax\"]").unwrap();
+        assert_eq!(conf.st7032.pages, vec![Page::Co2, Page::MinMax]);
+        assert!(conf.st7032.validate().is_ok());
+
+        let conf: Config = toml::from_str("[st7032]\npages = []").unwrap();
+        assert!(conf.st7032.validate().is_err());
+        assert!(toml::from_str::<Config>("[st7032]\npages = [\"clock\"]").is_err());
+    }
+
+    #[test]
+    fn st7032_dark() {
+        let conf: Config =
+            toml::from_str("[st7032]\ndark_below = 10.0\ndark_mode = \"off\"").unwrap();
+        assert_eq!(conf.st7032.dark_below, Some(10.0));
+        assert_eq!(conf.st7032.dark_mode, DarkMode::Off);
+        assert!(conf.st7032.validate().is_ok());
+
+        for s in ["contrast = 64", "dim_contrast = 64", "follower = 8"] {
+            let conf: Config = toml::from_str(&format!("[st7032]\n{s}")).unwrap();
+            assert!(conf.st7032.validate().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn ccs811_temperature_sensor() {
+        let mut conf = Config::default();
+        conf.ccs811.temperature_sensor = Some("room".to_string());
+        assert!(conf.validate_ccs811().is_ok());
+        conf.ccs811.temperature_sensor = Some("living".to_string());
+        assert!(conf.validate_ccs811().is_err());
+
+        let mut conf = Config::default();
+        conf.ccs811.co2_medium = conf.ccs811.co2_high;
+        assert!(conf.validate_ccs811().is_err());
+
+        let mut conf = Config::default();
+        conf.ccs811.co2_min = conf.ccs811.co2_max;
+        assert!(conf.validate_ccs811().is_err());
+
+        let mut conf = Config::default();
+        conf.ccs811.max_reject_rate = 1.5;
+        assert!(conf.validate_ccs811().is_err());
+    }
+
+    #[test]
+    fn reload_keeps_restart_values() {
+        let path = env::temp_dir().join(format!("config_test_{}.toml", std::process::id()));
+        let sensors = "[[adt7410.sensors]]\nname = \"living\"\naddr = 0x48\n";
+        fs::write(&path, format!("{sensors}[ccs811]\ninterval_ms = 2000\n")).unwrap();
+        let shared = Arc::new(RwLock::new(load(&path).unwrap()));
+
+        // センサの変更は再起動まで反映されず、新しいセンサの名前は解決できない
+        let renamed = sensors.replace("living", "bedroom");
+        let s =
+            format!("{renamed}[ccs811]\ninterval_ms = 3000\ntemperature_sensor = \"bedroom\"\n");
+        fs::write(&path, s).unwrap();
+        reload(&shared, &path);
+        assert_eq!(shared.read().unwrap().ccs811.interval_ms, 2000);
+
+        // 反映できる値のみの変更
+        fs::write(&path, format!("{sensors}[ccs811]\ninterval_ms = 3000\n")).unwrap();
+        reload(&shared, &path);
+        assert_eq!(shared.read().unwrap().ccs811.interval_ms, 3000);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
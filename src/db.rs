@@ -1,7 +1,14 @@
 #[allow(unused_imports)]
 use diesel::prelude::*;
 
-use crate::{perror, schema::*, Air, EResult};
+use crate::{
+    alarm::{Alarms, Event},
+    config::{self, SharedConfig},
+    perror,
+    schema::*,
+    shutdown::{Shutdown, Stage},
+    Air, Ccs811State, Ccs811Versions, DbState, DbStatus, EResult,
+};
 use diesel::{dsl, insert_into, PgConnection};
 use std::{
     env,
@@ -10,18 +17,24 @@ use std::{
         Arc,
     },
     thread,
-    time::Duration,
 };
 
 const ENV_STR: &str = "DATABASE_URL";
-const WINDOW_SIZE: usize = 5;
 
+/// 一行挿入
+///
+/// `current`と`voltage`はCCS811のセンサの電流（μA）と電圧（V）。
+/// `ccs811_ready`は、`co2`と`tvoc`をバーンインとウォームアップの後に測定したか。
+#[allow(clippy::too_many_arguments)]
 pub fn insert(
     conn: &PgConnection,
     temperature: Option<f32>,
     brightness: Option<f32>,
     co2: Option<i32>,
     tvoc: Option<i32>,
+    current: Option<i32>,
+    voltage: Option<f32>,
+    ccs811_ready: Option<bool>,
 ) -> EResult<()> {
     if let Err(e) = insert_into(data::table)
         .values((
@@ -30,6 +43,26 @@ pub fn insert(
             data::brightness.eq(brightness),
             data::co2.eq(co2),
             data::tvoc.eq(tvoc),
+            data::current.eq(current),
+            data::voltage.eq(voltage),
+            data::ccs811_ready.eq(ccs811_ready),
+        ))
+        .execute(conn)
+    {
+        perror!(e);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// センサごとの気温を一行挿入
+pub fn insert_temperature(conn: &PgConnection, sensor: &str, temperature: f32) -> EResult<()> {
+    if let Err(e) = insert_into(temperatures::table)
+        .values((
+            temperatures::datetime.eq(dsl::now),
+            temperatures::sensor.eq(sensor),
+            temperatures::temperature.eq(Some(temperature)),
         ))
         .execute(conn)
     {
@@ -40,7 +73,60 @@ pub fn insert(
     Ok(())
 }
 
-pub fn run(air: Air, bright: Arc<AtomicU64>) -> EResult<()> {
+/// CCS811のバージョンを一行挿入
+pub fn insert_versions(conn: &PgConnection, versions: &Ccs811Versions) -> EResult<()> {
+    if let Err(e) = insert_into(ccs811_versions::table)
+        .values((
+            ccs811_versions::datetime.eq(dsl::now),
+            ccs811_versions::hw_version.eq(versions.hw as i32),
+            ccs811_versions::fw_boot_version.eq(versions.boot.to_string()),
+            ccs811_versions::fw_app_version.eq(versions.app.to_string()),
+        ))
+        .execute(conn)
+    {
+        perror!(e);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// 温度アラームの発生・解除を一行挿入
+pub fn insert_alarm(conn: &PgConnection, event: &Event) -> EResult<()> {
+    if let Err(e) = insert_into(alarms::table)
+        .values((
+            alarms::datetime.eq(dsl::now),
+            alarms::sensor.eq(Some(&event.sensor)),
+            alarms::kind.eq(event.kind.to_string()),
+            alarms::raised.eq(event.raised),
+            alarms::temperature.eq(Some(event.temp as f32)),
+        ))
+        .execute(conn)
+    {
+        perror!(e);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// DBへの書き込みを別スレッドで開始
+///
+/// `db.window_size`個のサンプルごとに、気温と明るさとCCS811の電圧は平均値、
+/// CO2とTVOCとCCS811の電流は中央値を保存する。電流と電圧は未測定の場合は保存しない。
+/// CCS811がすべてのサンプルで`Ready`でなければ、`ccs811_ready`を`false`とする。
+/// 気温はセンサごとに`temperatures`へ、先頭のセンサのものは`data`にも保存する。
+/// 温度アラームは`db.interval_ms`ごとにまとめて保存する。
+/// CCS811のバージョンは初期化で読み込まれるか変わった時に`ccs811_versions`へ保存する。
+/// 接続と保存の状況は`status`に格納する。
+pub fn run(
+    shutdown: &Shutdown,
+    config: SharedConfig,
+    air: Air,
+    bright: Arc<AtomicU64>,
+    alarms: Alarms,
+    status: DbStatus,
+) -> EResult<()> {
     let url = match env::var(ENV_STR) {
         Ok(s) => s,
         Err(e) => {
@@ -51,47 +137,107 @@ pub fn run(air: Air, bright: Arc<AtomicU64>) -> EResult<()> {
 
     match PgConnection::establish(&url) {
         Ok(conn) => {
-            let wsec = Duration::from_secs(1);
-            let mut temp_v = [0.0; WINDOW_SIZE];
-            let mut bright_v = [0.0; WINDOW_SIZE];
-            let mut co2_v = [0; WINDOW_SIZE];
-            let mut tvoc_v = [0; WINDOW_SIZE];
+            status.set_state(DbState::Connected);
+            let token = shutdown.token(Stage::Consumer, "DB");
+            let window_size = config.read().unwrap().db.window_size.max(1);
+            let mut temp_v = vec![vec![0.0; window_size]; air.temps.len()]; // センサごと
+            let mut bright_v = vec![0.0; window_size];
+            let mut co2_v = vec![0; window_size];
+            let mut tvoc_v = vec![0; window_size];
+            let mut current_v = vec![0; window_size];
+            let mut voltage_v = vec![0.0; window_size];
+            let mut state_v = vec![None; window_size];
             let mut idx = 0;
+            let mut versions: Option<Ccs811Versions> = None; // 保存したバージョン
+            let alarm_rx = alarms.subscribe();
 
             let f = move || {
                 loop {
+                    let wsec = config::millis(config.read().unwrap().db.interval_ms);
                     thread::sleep(wsec); // wsec秒待機
 
-                    temp_v[idx] = f64::from_bits(air.temp.load(Ordering::Relaxed));
+                    if token.is_cancelled() {
+                        println!("exiting DB ...");
+                        break;
+                    }
+
+                    // 温度アラーム
+                    while let Ok(event) = alarm_rx.try_recv() {
+                        if let Err(e) = insert_alarm(&conn, &event) {
+                            perror!(e);
+                        }
+                    }
+
+                    // CCS811のバージョン
+                    let current_versions = *air.ccs811_versions.lock().unwrap();
+                    if let Some(v) = current_versions.filter(|v| versions != Some(*v)) {
+                        match insert_versions(&conn, &v) {
+                            Ok(()) => versions = Some(v),
+                            Err(e) => perror!(e),
+                        }
+                    }
+
+                    for (v, temp) in temp_v.iter_mut().zip(air.temps.iter()) {
+                        v[idx] = temp.celsius();
+                    }
                     bright_v[idx] = f64::from_bits(bright.load(Ordering::Relaxed));
                     co2_v[idx] = air.co2.load(Ordering::Relaxed);
                     tvoc_v[idx] = air.tvoc.load(Ordering::Relaxed);
+                    current_v[idx] = air.current.load(Ordering::Relaxed);
+                    voltage_v[idx] = air.voltage.get();
+                    state_v[idx] = air.ccs811_state();
                     idx += 1;
 
-                    if idx == WINDOW_SIZE {
+                    if idx == window_size {
                         // 平均値
-                        let temp_ave =
-                            temp_v.iter().fold(0.0, |acc, n| acc + n) / WINDOW_SIZE as f64;
+                        let temp_ave: Vec<f64> = temp_v
+                            .iter()
+                            .map(|v| v.iter().fold(0.0, |acc, n| acc + n) / window_size as f64)
+                            .collect();
                         let bright_ave =
-                            bright_v.iter().fold(0.0, |acc, n| acc + n) / WINDOW_SIZE as f64;
+                            bright_v.iter().fold(0.0, |acc, n| acc + n) / window_size as f64;
+                        let voltage_ave =
+                            voltage_v.iter().fold(0.0, |acc, n| acc + n) / window_size as f64;
 
                         // 中央値
                         co2_v.sort();
                         tvoc_v.sort();
-                        let co2 = co2_v[WINDOW_SIZE >> 1];
-                        let tvoc = tvoc_v[WINDOW_SIZE >> 1];
+                        current_v.sort();
+                        let co2 = co2_v[window_size >> 1];
+                        let tvoc = tvoc_v[window_size >> 1];
+                        let current = current_v[window_size >> 1];
+
+                        // CCS811の状態（初期化前のみの場合は不明）
+                        let ready = state_v
+                            .iter()
+                            .any(Option::is_some)
+                            .then(|| state_v.iter().all(|s| *s == Some(Ccs811State::Ready)));
 
                         // 挿入
                         if let Err(e) = insert(
                             &conn,
-                            Some(temp_ave as f32),
+                            temp_ave.first().filter(|t| !t.is_nan()).map(|t| *t as f32),
                             Some(bright_ave as f32),
                             Some(co2 as i32),
                             Some(tvoc as i32),
+                            (current > 0).then_some(current as i32),
+                            Some(voltage_ave).filter(|v| !v.is_nan()).map(|v| v as f32),
+                            ready,
                         ) {
                             perror!(e);
+                            status.set_state(DbState::Failed);
                             break;
                         }
+                        status.set_inserted();
+                        // 未測定を含む場合は平均がNaNになるため保存しない
+                        for (temp, ave) in air.temps.iter().zip(temp_ave.iter()) {
+                            if ave.is_nan() {
+                                continue;
+                            }
+                            if let Err(e) = insert_temperature(&conn, &temp.name, *ave as f32) {
+                                perror!(e);
+                            }
+                        }
 
                         println!("inserted to DB");
 
@@ -106,6 +252,7 @@ pub fn run(air: Air, bright: Arc<AtomicU64>) -> EResult<()> {
         }
         Err(e) => {
             perror!(e);
+            status.set_state(DbState::Failed);
             Err(e.into())
         }
     }
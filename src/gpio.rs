@@ -2,44 +2,139 @@
 use async_std::prelude::*;
 
 use super::EResult;
+use crate::Temperature;
+use crate::{
+    alarm::Alarms,
+    button::Button,
+    config::{self, SharedConfig},
+    hal::{Hardware, OutputPin},
+    shutdown::{Shutdown, Stage},
+    supervisor::{self, Supervisor},
+};
 use async_std::{
     channel::{self, Receiver},
-    task::{self, JoinHandle},
+    task::JoinHandle,
 };
-use rppal::gpio::{Gpio, OutputPin, Pin};
+use rppal::gpio::Trigger;
 
+#[cfg(feature = "adt7410")]
+mod alarm;
+#[cfg(feature = "ccs811")]
+mod ccs811;
 mod input;
 mod output;
 
-trait Runner {
-    fn run(self, pin: Pin) -> EResult<JoinHandle<()>>;
+trait Runner<P> {
+    fn run(self, pin: P) -> EResult<JoinHandle<EResult<()>>>;
 }
 
-const INPUT_PIN: u8 = 5;
-const LED_PIN: u8 = 6;
 const CHANNEL_SIZE: usize = 32;
-const CCS811_WAKE_PIN: u8 = 21;
 
-pub async fn run(sig_rx: Receiver<()>) -> EResult<(JoinHandle<()>, OutputPin)> {
-    let gpio = Gpio::new()?;
-    let pin_led = gpio.get(LED_PIN)?;
-    let pin_input = gpio.get(INPUT_PIN)?;
-    let pin_ccs811 = gpio.get(CCS811_WAKE_PIN)?;
+/// CCS811に接続したピン
+pub struct Ccs811Pins<O> {
+    pub wake: O,                          // nWAKE
+    pub reset: Option<O>,                 // nRESET。未接続の場合は`None`
+    pub data_ready: Option<Receiver<()>>, // nINTがアクティブになった通知。未接続の場合は`None`
+}
+
+/// GPIOのタスクを監視付きで起動
+///
+/// CCS811のnWAKE用の出力ピンと、nRESETとnINTのピンが設定されている場合はその出力ピンと通知を返す。
+/// ADT7410のINT、CTのピンが設定されている場合は監視し、そのセンサの気温`temps`で判定して`alarms`に配信する。
+/// 物理スイッチの短押し・長押しは`button`に配信する。
+pub async fn run<H: Hardware>(
+    hw: &H,
+    shutdown: &Shutdown,
+    supervisor: &Supervisor,
+    config: &SharedConfig,
+    temps: Vec<Temperature>,
+    alarms: Alarms,
+    button: Button,
+) -> EResult<Ccs811Pins<H::Output>> {
+    let pins = config.read().unwrap().gpio.clone();
+
+    let mut pin_ccs811 = hw.output_pin(pins.ccs811_wake_pin)?;
+    pin_ccs811.set_high();
+    let reset_ccs811 = match pins.ccs811_reset_pin {
+        Some(pin) => {
+            let mut p = hw.output_pin(pin)?;
+            p.set_high();
+            Some(p)
+        }
+        None => None,
+    };
 
     let (sw_tx, sw_rx) = channel::bounded(CHANNEL_SIZE);
 
-    let task_led = output::Output::new(sig_rx.clone(), sw_rx).run(pin_led)?; // LED
-    let task_input = input::Input::new(sig_rx.clone(), sw_tx).run(pin_input)?; // 物理スイッチ
+    // LED
+    let token = shutdown.token(Stage::Producer, "GPIO Output");
+    let (h, a) = (hw.clone(), alarms.clone());
+    supervisor.spawn("GPIO Output", token, move |token| {
+        let hdl = h
+            .output_pin(pins.led_pin)
+            .and_then(|pin| output::Output::new(token, sw_rx.clone(), a.clone()).run(pin));
+        supervisor::join(hdl)
+    });
+
+    // 物理スイッチ
+    let token = shutdown.token(Stage::Producer, "GPIO Input");
+    let h = hw.clone();
+    let long_press = config::millis(pins.long_press_ms);
+    supervisor.spawn("GPIO Input", token, move |token| {
+        let hdl = h.input_pin(pins.input_pin, Trigger::Both).and_then(|pin| {
+            input::Input::new(token, sw_tx.clone(), button.clone(), long_press).run(pin)
+        });
+        supervisor::join(hdl)
+    });
+
+    // 温度アラーム（センサごと）
+    #[cfg(feature = "adt7410")]
+    for (sensor, temp) in config.read().unwrap().alarm_sensors().iter().zip(temps) {
+        for (pin, kind) in [
+            (sensor.int_pin, alarm::Pin::Int),
+            (sensor.ct_pin, alarm::Pin::Ct),
+        ] {
+            let Some(pin) = pin else { continue };
+            let name = match kind {
+                alarm::Pin::Int => "GPIO ADT7410 INT",
+                alarm::Pin::Ct => "GPIO ADT7410 CT",
+            };
 
-    let hdl = task::spawn(async move {
-        // LEDタスク終了を待機
-        task_led.await;
+            let token = shutdown.token(Stage::Producer, name);
+            let (h, c, t, a) = (hw.clone(), config.clone(), temp.clone(), alarms.clone());
+            supervisor.spawn(name, token, move |token| {
+                let hdl = h.input_pin_pullup(pin, Trigger::Both).and_then(|p| {
+                    alarm::AlarmWatch::new(token, c.clone(), kind, t.clone(), a.clone()).run(p)
+                });
+                supervisor::join(hdl)
+            });
+        }
+    }
+    #[cfg(not(feature = "adt7410"))]
+    let _ = temps;
 
-        // GPIO入力タスク終了を待機
-        task_input.await;
+    // CCS811の新しいデータ
+    #[cfg(feature = "ccs811")]
+    let data_ready = pins.ccs811_int_pin.map(|pin| {
+        let (ready_tx, ready_rx) = channel::bounded(1);
+        let token = shutdown.token(Stage::Producer, "GPIO CCS811 INT");
+        let h = hw.clone();
+        supervisor.spawn("GPIO CCS811 INT", token, move |token| {
+            let hdl = h
+                .input_pin_pullup(pin, Trigger::FallingEdge) // 新しいデータでLow
+                .and_then(|p| ccs811::DataReady::new(token, ready_tx.clone()).run(p));
+            supervisor::join(hdl)
+        });
+        ready_rx
     });
+    #[cfg(not(feature = "ccs811"))]
+    let data_ready = None;
 
     println!("initialized GPIO");
 
-    Ok((hdl, pin_ccs811.into_output_high()))
+    Ok(Ccs811Pins {
+        wake: pin_ccs811,
+        reset: reset_ccs811,
+        data_ready,
+    })
 }
@@ -0,0 +1,318 @@
+#[allow(unused_imports)]
+use async_std::prelude::*;
+
+use super::Runner;
+use crate::{
+    alarm::{Alarms, Event, Kind},
+    config::SharedConfig,
+    hal::InputPin,
+    perror,
+    shutdown::Token,
+    EResult, Temperature,
+};
+use async_std::task::{self, JoinHandle};
+use rppal::gpio::Level;
+use std::time::Duration;
+
+/// 監視するADT7410の出力ピン
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Pin {
+    Int, // T_HIGH、T_LOW
+    Ct,  // T_CRIT
+}
+
+/// ADT7410のINT、CTピンの監視
+///
+/// どちらもコンパレータモード、アクティブLowで設定されている前提。
+/// ピンを接続したセンサの気温で種類を判定し、センサの名前を付けて配信する。
+pub(super) struct AlarmWatch {
+    shutdown: Token,
+    config: SharedConfig,
+    pin: Pin,
+    temp: Temperature, // ピンを接続したセンサの気温
+    alarms: Alarms,
+    raised: Option<Kind>, // このピンで発生中のアラーム
+}
+
+impl AlarmWatch {
+    pub(super) fn new(
+        shutdown: Token,
+        config: SharedConfig,
+        pin: Pin,
+        temp: Temperature,
+        alarms: Alarms,
+    ) -> Self {
+        AlarmWatch {
+            shutdown,
+            config,
+            pin,
+            temp,
+            alarms,
+            raised: None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self.pin {
+            Pin::Int => "GPIO ADT7410 INT",
+            Pin::Ct => "GPIO ADT7410 CT",
+        }
+    }
+
+    /// このピンで発生し得るアラーム
+    fn kinds(&self) -> &'static [Kind] {
+        match self.pin {
+            Pin::Int => &[Kind::High, Kind::Low],
+            Pin::Ct => &[Kind::Critical],
+        }
+    }
+
+    /// ピンがアクティブになった時のアラームの種類
+    ///
+    /// INTは上限と下限のどちらでもアクティブになるため、閾値の中間と気温を比べる。
+    fn kind(&self, temp: f64) -> Kind {
+        match self.pin {
+            Pin::Int => {
+                let conf = self.config.read().unwrap().adt7410.clone();
+                if temp >= (conf.t_high + conf.t_low) / 2.0 {
+                    Kind::High
+                } else {
+                    Kind::Low
+                }
+            }
+            Pin::Ct => Kind::Critical,
+        }
+    }
+
+    /// ピンのレベルをアラームに反映
+    ///
+    /// 気温の測定前はアラームの種類を判定できないため、発生を保留する。
+    fn update(&mut self, level: Level) {
+        let Some(temp) = self.temp.measured() else {
+            return;
+        };
+        let sensor = &self.temp.name;
+
+        match (level, self.raised) {
+            (Level::Low, None) => {
+                let kind = self.kind(temp);
+                self.raised = Some(kind);
+                self.alarms.publish(Event {
+                    sensor: sensor.clone(),
+                    kind,
+                    raised: true,
+                    temp,
+                });
+            }
+            (Level::High, _) => {
+                // 再起動前に発生したものも含めて解除
+                self.raised = None;
+                for kind in self.kinds() {
+                    if self.alarms.is_raised(sensor, *kind) {
+                        self.alarms.publish(Event {
+                            sensor: sensor.clone(),
+                            kind: *kind,
+                            raised: false,
+                            temp,
+                        });
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl<P: InputPin> Runner<P> for AlarmWatch {
+    fn run(mut self, mut pin: P) -> EResult<JoinHandle<EResult<()>>> {
+        let t = Duration::from_millis(200);
+
+        let f = async move {
+            // 起動時のレベル
+            self.update(pin.read());
+
+            loop {
+                // poll_interruptはブロックするため、別スレッドで待機
+                let (p, result) = task::spawn_blocking(move || {
+                    let result = pin.poll_interrupt(false, Some(t));
+                    (pin, result)
+                })
+                .await;
+                pin = p;
+
+                match result {
+                    Ok(Some(level)) => self.update(level),
+                    Ok(None) => {
+                        // timeout
+                        if self.shutdown.is_cancelled() {
+                            println!("exiting {} ({}) ...", self.name(), self.temp.name);
+                            break;
+                        }
+                        self.update(pin.read()); // 保留中のアラームと、変化を逃した時
+                    }
+                    Err(e) => {
+                        perror!(e);
+                        return Err(e);
+                    }
+                }
+            }
+
+            Ok(())
+        };
+
+        Ok(task::spawn(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hal::fake::FakeInputPin,
+        shutdown::{Shutdown, Stage},
+    };
+    use std::sync::atomic::Ordering;
+
+    /// 測定済み（`NaN`の場合は未測定）の気温
+    fn temp(celsius: f64) -> Temperature {
+        let temp = Temperature::new("living".to_string());
+        temp.value.store(celsius.to_bits(), Ordering::Relaxed);
+        temp
+    }
+
+    #[async_std::test]
+    async fn raise_and_clear() {
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Producer, "GPIO ADT7410 INT");
+        let alarms = Alarms::new();
+        let rx = alarms.subscribe();
+        let temp = temp(31.0);
+
+        let pin = FakeInputPin::new(23);
+        pin.push_edge(Level::High); // 起動時はLow（アクティブ）
+
+        let watch = AlarmWatch::new(token, Default::default(), Pin::Int, temp.clone(), alarms);
+        let hdl = watch.run(pin.clone()).unwrap();
+
+        let high = Event {
+            sensor: "living".to_string(),
+            kind: Kind::High,
+            raised: true,
+            temp: 31.0,
+        };
+        assert_eq!(rx.recv().await.unwrap(), high.clone());
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            Event {
+                raised: false,
+                ..high
+            }
+        );
+
+        // 下限
+        temp.value.store(14.0f64.to_bits(), Ordering::Relaxed);
+        pin.push_edge(Level::Low);
+        let ev = rx.recv().await.unwrap();
+        assert_eq!((ev.kind, ev.raised), (Kind::Low, true));
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn wait_for_temperature() {
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Producer, "GPIO ADT7410 CT");
+        let alarms = Alarms::new();
+        let rx = alarms.subscribe();
+        let temp = temp(f64::NAN); // 未測定
+
+        let pin = FakeInputPin::new(24); // 起動時からLow（アクティブ）
+        let watch = AlarmWatch::new(token, Default::default(), Pin::Ct, temp.clone(), alarms);
+        let hdl = watch.run(pin).unwrap();
+
+        task::sleep(Duration::from_millis(50)).await;
+        assert!(rx.try_recv().is_err()); // 測定まで保留
+
+        temp.value.store(41.0f64.to_bits(), Ordering::Relaxed);
+        let ev = rx.recv().await.unwrap();
+        assert_eq!((ev.kind, ev.raised, ev.temp), (Kind::Critical, true, 41.0));
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn clear_without_edges() {
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Producer, "GPIO ADT7410 CT");
+        let alarms = Alarms::new();
+        let rx = alarms.subscribe();
+        let temp = temp(41.0);
+
+        let pin = FakeInputPin::new(24); // 起動時からLow（アクティブ）
+        let watch = AlarmWatch::new(token, Default::default(), Pin::Ct, temp, alarms);
+        let hdl = watch.run(pin.clone()).unwrap();
+        assert!(rx.recv().await.unwrap().raised);
+
+        // 変化が通知されなくても、タイムアウトごとに読み込んで解除
+        pin.set_level(Level::High);
+        let ev = rx.recv().await.unwrap();
+        assert_eq!((ev.kind, ev.raised), (Kind::Critical, false));
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn raise_at_zero() {
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Producer, "GPIO ADT7410 INT");
+        let alarms = Alarms::new();
+        let rx = alarms.subscribe();
+        let temp = temp(0.0); // 0度は測定済み
+
+        let pin = FakeInputPin::new(23); // 起動時からLow（アクティブ）
+        let watch = AlarmWatch::new(token, Default::default(), Pin::Int, temp, alarms);
+        let hdl = watch.run(pin).unwrap();
+
+        let ev = rx.recv().await.unwrap();
+        assert_eq!((ev.kind, ev.raised, ev.temp), (Kind::Low, true, 0.0));
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn keep_other_sensors() {
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Producer, "GPIO ADT7410 INT");
+        let alarms = Alarms::new();
+        alarms.publish(Event {
+            sensor: "living".to_string(),
+            kind: Kind::High,
+            raised: true,
+            temp: 31.0,
+        });
+        let rx = alarms.subscribe();
+
+        // 別のセンサのINTが非アクティブでも、livingのアラームは解除しない
+        let bedroom = Temperature::new("bedroom".to_string());
+        bedroom.value.store(31.0f64.to_bits(), Ordering::Relaxed);
+        let pin = FakeInputPin::new(25);
+        pin.set_level(Level::High);
+        let watch = AlarmWatch::new(token, Default::default(), Pin::Int, bedroom, alarms.clone());
+        let hdl = watch.run(pin.clone()).unwrap();
+
+        task::sleep(Duration::from_millis(300)).await;
+        assert!(rx.try_recv().is_err());
+        assert!(alarms.is_raised("living", Kind::High));
+
+        pin.push_edge(Level::Low);
+        let ev = rx.recv().await.unwrap();
+        assert_eq!((ev.sensor.as_str(), ev.kind), ("bedroom", Kind::High));
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+}
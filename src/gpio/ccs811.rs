@@ -0,0 +1,109 @@
+#[allow(unused_imports)]
+use async_std::prelude::*;
+
+use super::Runner;
+use crate::{hal::InputPin, perror, shutdown::Token, EResult};
+use async_std::{
+    channel::{Sender, TrySendError},
+    task::{self, JoinHandle},
+};
+use rppal::gpio::Level;
+use std::time::Duration;
+
+/// CCS811のnINTの監視
+///
+/// nINTはアクティブLowで、新しいデータを読み込むまでLowのまま。
+/// 立ち下がりの割り込みを設定したピンで、Lowになるたびに`ready_tx`で通知する。
+pub(super) struct DataReady {
+    shutdown: Token,
+    ready_tx: Sender<()>,
+}
+
+impl DataReady {
+    pub(super) fn new(shutdown: Token, ready_tx: Sender<()>) -> Self {
+        DataReady { shutdown, ready_tx }
+    }
+
+    /// 通知。受信側が終了していれば`false`
+    ///
+    /// 未読の通知があれば、まとめて一度の読み込みで済むため破棄する。
+    fn notify(&self) -> bool {
+        !matches!(self.ready_tx.try_send(()), Err(TrySendError::Closed(_)))
+    }
+}
+
+impl<P: InputPin> Runner<P> for DataReady {
+    fn run(self, mut pin: P) -> EResult<JoinHandle<EResult<()>>> {
+        let t = Duration::from_millis(200);
+
+        let f = async move {
+            // 起動前から未読のデータがある場合
+            if pin.read() == Level::Low && !self.notify() {
+                return Ok(());
+            }
+
+            loop {
+                // poll_interruptはブロックするため、別スレッドで待機
+                let (p, result) = task::spawn_blocking(move || {
+                    let result = pin.poll_interrupt(false, Some(t));
+                    (pin, result)
+                })
+                .await;
+                pin = p;
+
+                let level = match result {
+                    Ok(Some(level)) => level,
+                    Ok(None) => {
+                        // timeout
+                        if self.shutdown.is_cancelled() {
+                            break;
+                        }
+                        pin.read() // 読み込み直後に再びLowになった場合など、変化を逃した時
+                    }
+                    Err(e) => {
+                        perror!(e);
+                        return Err(e);
+                    }
+                };
+
+                if level == Level::Low && !self.notify() {
+                    break;
+                }
+            }
+
+            println!("exiting GPIO CCS811 INT ...");
+            Ok(())
+        };
+
+        Ok(task::spawn(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hal::fake::FakeInputPin,
+        shutdown::{Shutdown, Stage},
+    };
+    use async_std::channel;
+
+    #[async_std::test]
+    async fn notify_on_falling_edge() {
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Producer, "GPIO CCS811 INT");
+        let (ready_tx, ready_rx) = channel::bounded(1);
+
+        let pin = FakeInputPin::new(20);
+        pin.push_edge(Level::High); // 起動時はLow（未読のデータ）
+
+        let hdl = DataReady::new(token, ready_tx).run(pin.clone()).unwrap();
+        ready_rx.recv().await.unwrap();
+
+        pin.push_edge(Level::Low);
+        ready_rx.recv().await.unwrap();
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+}
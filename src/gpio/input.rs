@@ -2,37 +2,71 @@
 use async_std::prelude::*;
 
 use super::Runner;
-use crate::{perror, EResult};
+use crate::{
+    button::{Button, Detector},
+    hal::InputPin,
+    perror,
+    shutdown::Token,
+    EResult,
+};
 use async_std::{
-    channel::{Receiver, Sender, TryRecvError},
+    channel::Sender,
     task::{self, JoinHandle},
 };
-use rppal::gpio::{Level, Pin};
-use std::time::Duration;
+use rppal::gpio::Level;
+use std::time::{Duration, Instant};
 
+/// 物理スイッチ
+///
+/// レベルをLEDのタスクに送信し、短押し・長押しを`button`に配信する。
 pub(super) struct Input {
-    sig_rx: Receiver<()>,
+    shutdown: Token,
     sw_tx: Sender<Level>,
+    button: Button,
+    long_press: Duration, // 長押しとする時間
 }
 
 impl Input {
-    pub(super) fn new(sig_rx: Receiver<()>, sw_tx: Sender<Level>) -> Self {
-        Input { sig_rx, sw_tx }
+    pub(super) fn new(
+        shutdown: Token,
+        sw_tx: Sender<Level>,
+        button: Button,
+        long_press: Duration,
+    ) -> Self {
+        Input {
+            shutdown,
+            sw_tx,
+            button,
+            long_press,
+        }
     }
 }
 
-impl Runner for Input {
-    fn run(self, pin: Pin) -> EResult<JoinHandle<()>> {
+impl<P: InputPin> Runner<P> for Input {
+    fn run(self, mut pin: P) -> EResult<JoinHandle<EResult<()>>> {
         let t = Duration::from_millis(200);
-        let mut pin = pin.into_input_pulldown();
 
         // GPIOの入力変化を送信
         let f = async move {
+            let mut detector = Detector::default();
+
             loop {
-                match pin.poll_interrupt(false, Some(t)) {
+                // poll_interruptはブロックするため、別スレッドで待機
+                let (p, result) = task::spawn_blocking(move || {
+                    let result = pin.poll_interrupt(false, Some(t));
+                    (pin, result)
+                })
+                .await;
+                pin = p;
+
+                match result {
                     Ok(Some(level)) => {
                         let p = pin.pin();
                         println!("GPIO({p}): {level}");
+                        if let Some(press) = detector.update(level, Instant::now(), self.long_press)
+                        {
+                            self.button.publish(press);
+                        }
                         if self.sw_tx.send(level).await.is_err() {
                             println!("exiting GPIO Input ...");
                             break;
@@ -40,16 +74,17 @@ impl Runner for Input {
                     }
                     Ok(None) => {
                         // timeout
-                        match self.sig_rx.try_recv() {
-                            Err(TryRecvError::Empty) => (),
-                            _ => {
-                                println!("exiting GPIO Input ...");
-                                break;
-                            }
+                        if self.shutdown.is_cancelled() {
+                            println!("exiting GPIO Input ...");
+                            break;
                         }
 
-                        // 現在のレベルを送信
+                        // 現在のレベルを送信（押し続けている場合は長押しを判定）
                         let level = pin.read();
+                        if let Some(press) = detector.update(level, Instant::now(), self.long_press)
+                        {
+                            self.button.publish(press);
+                        }
                         if let Err(e) = self.sw_tx.send(level).await {
                             perror!(e);
                             println!("exiting GPIO Input ...");
@@ -58,12 +93,51 @@ impl Runner for Input {
                     }
                     Err(e) => {
                         perror!(e);
-                        break;
+                        return Err(e);
                     }
                 }
             }
+
+            Ok(())
         };
 
         Ok(task::spawn(f))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        button::Press,
+        hal::fake::FakeInputPin,
+        shutdown::{Shutdown, Stage},
+    };
+    use async_std::channel;
+
+    #[async_std::test]
+    async fn send_level() {
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Producer, "GPIO Input");
+        let (sw_tx, sw_rx) = channel::bounded(8);
+
+        let button = Button::new();
+        let press_rx = button.subscribe();
+
+        let pin = FakeInputPin::new(5);
+        pin.push_edge(Level::High);
+        pin.push_timeout();
+        pin.push_edge(Level::Low);
+
+        let hdl = Input::new(token, sw_tx, button, Duration::from_secs(2))
+            .run(pin)
+            .unwrap();
+        assert_eq!(sw_rx.recv().await.unwrap(), Level::High); // 入力の変化
+        assert_eq!(sw_rx.recv().await.unwrap(), Level::High); // タイムアウト時の現在のレベル
+        assert_eq!(sw_rx.recv().await.unwrap(), Level::Low);
+        assert_eq!(press_rx.recv().await.unwrap(), Press::Short);
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+}
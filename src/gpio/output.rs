@@ -2,55 +2,146 @@
 use async_std::prelude::*;
 
 use super::Runner;
-use crate::{perror, EResult};
+use crate::{alarm::Alarms, hal::OutputPin, perror, shutdown::Token, EResult};
 use async_std::{
     channel::Receiver,
     task::{self, JoinHandle},
 };
-use futures::{select, FutureExt};
-use rppal::gpio::{Level, Pin};
+use futures::{pin_mut, select, FutureExt};
+use rppal::gpio::Level;
 
+/// LED
+///
+/// スイッチが押されているか、温度アラームが発生している間は点灯する。
 pub(super) struct Output {
-    sig_rx: Receiver<()>,
+    shutdown: Token,
     sw_rx: Receiver<Level>,
+    alarms: Alarms,
 }
 
 impl Output {
-    pub(super) fn new(sig_rx: Receiver<()>, sw_rx: Receiver<Level>) -> Self {
-        Output { sig_rx, sw_rx }
+    pub(super) fn new(shutdown: Token, sw_rx: Receiver<Level>, alarms: Alarms) -> Self {
+        Output {
+            shutdown,
+            sw_rx,
+            alarms,
+        }
     }
 }
 
-impl Runner for Output {
-    fn run(self, pin: Pin) -> EResult<JoinHandle<()>> {
-        let mut pin = pin.into_output();
-
+impl<P: OutputPin> Runner<P> for Output {
+    fn run(self, mut pin: P) -> EResult<JoinHandle<EResult<()>>> {
         let f = async move {
+            let alarm_rx = self.alarms.subscribe();
+            let mut sw = Level::Low;
+
             loop {
-                let mut sig_rx = self.sig_rx.recv().fuse();
+                let cancelled = self.shutdown.cancelled().fuse();
                 let mut sw_rx = self.sw_rx.recv().fuse();
+                let mut alarm_rx = alarm_rx.recv().fuse();
+                pin_mut!(cancelled);
 
                 select!(
-                    _ = sig_rx => {
+                    _ = cancelled => {
                         // 終了シグナルを受信
                         println!("exiting GPIO Output ...");
                         break;
                     },
                     level = sw_rx => {
-                        // LEDをオン・オフ
                         match level {
-                            Ok(Level::High) => pin.set_high(),
-                            Ok(Level::Low) => pin.set_low(),
+                            Ok(level) => sw = level,
                             Err(e) => {
                                 perror!(e);
-                                break;
+                                return Err(e.into());
                             }
                         }
-                    }
-                )
+                    },
+                    _ = alarm_rx => (), // 発生中のアラームはAlarmsから取得
+                );
+
+                // LEDをオン・オフ
+                if sw == Level::High || !self.alarms.active().is_empty() {
+                    pin.set_high();
+                } else {
+                    pin.set_low();
+                }
             }
+
+            Ok(())
         };
 
         Ok(task::spawn(f))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        alarm::{Event, Kind},
+        hal::fake::FakeOutputPin,
+        shutdown::{Shutdown, Stage},
+    };
+    use async_std::channel;
+    use std::time::Duration;
+
+    #[async_std::test]
+    async fn switch_led() {
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Producer, "GPIO Output");
+        let (sw_tx, sw_rx) = channel::bounded(8);
+
+        let pin = FakeOutputPin::new();
+        let hdl = Output::new(token, sw_rx, Alarms::new())
+            .run(pin.clone())
+            .unwrap();
+
+        sw_tx.send(Level::High).await.unwrap();
+        sw_tx.send(Level::Low).await.unwrap();
+        sw_tx.send(Level::High).await.unwrap();
+        task::sleep(Duration::from_millis(10)).await;
+        assert_eq!(pin.level(), Some(Level::High));
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+        assert_eq!(pin.levels(), vec![Level::High, Level::Low, Level::High]);
+    }
+
+    #[async_std::test]
+    async fn light_on_alarm() {
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Producer, "GPIO Output");
+        let (sw_tx, sw_rx) = channel::bounded(8);
+        let alarms = Alarms::new();
+
+        let pin = FakeOutputPin::new();
+        let hdl = Output::new(token, sw_rx, alarms.clone())
+            .run(pin.clone())
+            .unwrap();
+        task::sleep(Duration::from_millis(10)).await; // 購読を待つ
+
+        let event = Event {
+            sensor: "living".to_string(),
+            kind: Kind::Critical,
+            raised: true,
+            temp: 41.0,
+        };
+        alarms.publish(event.clone());
+        task::sleep(Duration::from_millis(10)).await;
+        assert_eq!(pin.level(), Some(Level::High));
+
+        sw_tx.send(Level::Low).await.unwrap(); // アラーム中はスイッチを離しても点灯
+        task::sleep(Duration::from_millis(10)).await;
+        assert_eq!(pin.level(), Some(Level::High));
+
+        alarms.publish(Event {
+            raised: false,
+            ..event
+        });
+        task::sleep(Duration::from_millis(10)).await;
+        assert_eq!(pin.level(), Some(Level::Low));
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+//! ドライバが使用するI2C、SPI、GPIOの操作
+//!
+//! Raspberry Pi上では`rppal`の型を用い、テストでは`fake`の偽デバイスを用いる。
+//! シミュレーションモードでは[crate::sim]の模擬デバイスを用いる。
+//!
+//! 各ドライバの`eh`モジュールには、これらのトレイトの代わりに
+//! `embedded-hal`と`embedded-hal-async`のトレイトで動作する版がある。
+
+use crate::EResult;
+use rppal::{
+    gpio::{self, Gpio, Level, Trigger},
+    i2c::I2c,
+    spi::{Bus, Mode, SlaveSelect, Spi},
+};
+use std::time::Duration;
+
+#[cfg(test)]
+pub mod fake;
+
+/// I2Cバス
+pub trait I2cBus: Send + 'static {
+    fn set_slave_address(&mut self, addr: u16) -> EResult<()>;
+    fn write(&mut self, buf: &[u8]) -> EResult<()>; // SMBusではない書き込み（最初のバイトはレジスタ）
+    fn smbus_send_byte(&mut self, value: u8) -> EResult<()>;
+    fn smbus_read_byte(&mut self, reg: u8) -> EResult<u8>;
+    fn smbus_write_byte(&mut self, reg: u8, value: u8) -> EResult<()>;
+    fn smbus_read_word(&mut self, reg: u8) -> EResult<u16>;
+    fn smbus_block_write(&mut self, reg: u8, buf: &[u8]) -> EResult<()>;
+    fn block_read(&mut self, reg: u8, buf: &mut [u8]) -> EResult<()>;
+}
+
+/// SPIバス
+pub trait SpiBus: Send + 'static {
+    fn transfer(&mut self, read_buf: &mut [u8], write_buf: &[u8]) -> EResult<usize>;
+}
+
+/// GPIO入力ピン
+pub trait InputPin: Send + 'static {
+    fn pin(&self) -> u8;
+    fn read(&self) -> Level;
+    fn poll_interrupt(&mut self, reset: bool, timeout: Option<Duration>) -> EResult<Option<Level>>;
+}
+
+/// GPIO出力ピン
+pub trait OutputPin: Send + 'static {
+    fn set_high(&mut self);
+    fn set_low(&mut self);
+}
+
+/// デバイスの取得
+///
+/// 各タスクは再起動のたびにここからデバイスを取得する。
+/// 入力ピンは`trigger`のレベルの変化を`poll_interrupt`で通知する。
+pub trait Hardware: Clone + Send + Sync + 'static {
+    type I2c: I2cBus;
+    type Spi: SpiBus;
+    type Input: InputPin;
+    type Output: OutputPin;
+
+    fn i2c(&self) -> EResult<Self::I2c>;
+    fn spi(&self, clock: u32) -> EResult<Self::Spi>;
+    fn input_pin(&self, pin: u8, trigger: Trigger) -> EResult<Self::Input>;
+    fn input_pin_pullup(&self, pin: u8, trigger: Trigger) -> EResult<Self::Input>; // オープンドレインの出力用
+    fn output_pin(&self, pin: u8) -> EResult<Self::Output>;
+}
+
+/// Raspberry Pi
+#[derive(Clone)]
+pub struct Rpi {
+    gpio: Gpio,
+}
+
+impl Rpi {
+    pub fn new() -> EResult<Self> {
+        Ok(Rpi { gpio: Gpio::new()? })
+    }
+}
+
+impl Hardware for Rpi {
+    type I2c = I2c;
+    type Spi = Spi;
+    type Input = gpio::InputPin;
+    type Output = gpio::OutputPin;
+
+    fn i2c(&self) -> EResult<I2c> {
+        Ok(I2c::new()?)
+    }
+
+    fn spi(&self, clock: u32) -> EResult<Spi> {
+        Ok(Spi::new(Bus::Spi0, SlaveSelect::Ss0, clock, Mode::Mode0)?)
+    }
+
+    fn input_pin(&self, pin: u8, trigger: Trigger) -> EResult<gpio::InputPin> {
+        let mut pin = self.gpio.get(pin)?.into_input_pulldown();
+        pin.set_interrupt(trigger)?; // 設定しないとpoll_interruptはタイムアウトしか返さない
+        Ok(pin)
+    }
+
+    fn input_pin_pullup(&self, pin: u8, trigger: Trigger) -> EResult<gpio::InputPin> {
+        let mut pin = self.gpio.get(pin)?.into_input_pullup();
+        pin.set_interrupt(trigger)?;
+        Ok(pin)
+    }
+
+    fn output_pin(&self, pin: u8) -> EResult<gpio::OutputPin> {
+        Ok(self.gpio.get(pin)?.into_output())
+    }
+}
+
+impl I2cBus for I2c {
+    fn set_slave_address(&mut self, addr: u16) -> EResult<()> {
+        I2c::set_slave_address(self, addr)?;
+        Ok(())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> EResult<()> {
+        I2c::write(self, buf)?;
+        Ok(())
+    }
+
+    fn smbus_send_byte(&mut self, value: u8) -> EResult<()> {
+        I2c::smbus_send_byte(self, value)?;
+        Ok(())
+    }
+
+    fn smbus_read_byte(&mut self, reg: u8) -> EResult<u8> {
+        Ok(I2c::smbus_read_byte(self, reg)?)
+    }
+
+    fn smbus_write_byte(&mut self, reg: u8, value: u8) -> EResult<()> {
+        I2c::smbus_write_byte(self, reg, value)?;
+        Ok(())
+    }
+
+    fn smbus_read_word(&mut self, reg: u8) -> EResult<u16> {
+        Ok(I2c::smbus_read_word(self, reg)?)
+    }
+
+    fn smbus_block_write(&mut self, reg: u8, buf: &[u8]) -> EResult<()> {
+        I2c::smbus_block_write(self, reg, buf)?;
+        Ok(())
+    }
+
+    fn block_read(&mut self, reg: u8, buf: &mut [u8]) -> EResult<()> {
+        I2c::block_read(self, reg, buf)?;
+        Ok(())
+    }
+}
+
+impl SpiBus for Spi {
+    fn transfer(&mut self, read_buf: &mut [u8], write_buf: &[u8]) -> EResult<usize> {
+        Ok(Spi::transfer(self, read_buf, write_buf)?)
+    }
+}
+
+impl InputPin for gpio::InputPin {
+    fn pin(&self) -> u8 {
+        gpio::InputPin::pin(self)
+    }
+
+    fn read(&self) -> Level {
+        gpio::InputPin::read(self)
+    }
+
+    fn poll_interrupt(&mut self, reset: bool, timeout: Option<Duration>) -> EResult<Option<Level>> {
+        Ok(gpio::InputPin::poll_interrupt(self, reset, timeout)?)
+    }
+}
+
+impl OutputPin for gpio::OutputPin {
+    fn set_high(&mut self) {
+        gpio::OutputPin::set_high(self)
+    }
+
+    fn set_low(&mut self) {
+        gpio::OutputPin::set_low(self)
+    }
+}
@@ -0,0 +1,314 @@
+//! テスト用の偽デバイス
+//!
+//! 各偽デバイスは`Clone`で状態を共有するため、ドライバに渡した後も
+//! テスト側から応答の設定や書き込み内容の確認ができる。
+
+use super::{I2cBus, InputPin, OutputPin, SpiBus};
+use crate::EResult;
+use rppal::gpio::Level;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// I2Cへの書き込み
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Write {
+    pub addr: u16,
+    pub reg: Option<u8>, // smbus_send_byteの場合はNone。writeの場合は最初のバイト
+    pub data: Vec<u8>,   // smbus_block_writeの場合は先頭がバイト数（rppalと同じくバスに送られる）
+}
+
+#[derive(Default)]
+struct I2cState {
+    addr: u16,
+    regs: BTreeMap<(u16, u8), Vec<u8>>,             // レジスタの値
+    queued: BTreeMap<(u16, u8), VecDeque<Vec<u8>>>, // 一度だけ返す値（regsより優先）
+    writes: Vec<Write>,
+    errors: VecDeque<String>, // 次の操作で返すエラー
+}
+
+/// 偽I2Cバス
+#[derive(Clone, Default)]
+pub struct FakeI2c {
+    state: Arc<Mutex<I2cState>>,
+}
+
+impl FakeI2c {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// レジスタの値を設定
+    pub fn set_reg(&self, addr: u16, reg: u8, data: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state.regs.insert((addr, reg), data.to_vec());
+    }
+
+    /// 次の読み込みで一度だけ返す値を追加
+    pub fn push_read(&self, addr: u16, reg: u8, data: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .queued
+            .entry((addr, reg))
+            .or_default()
+            .push_back(data.to_vec());
+    }
+
+    /// 次の操作をエラーにする
+    pub fn fail_next(&self, msg: &str) {
+        self.state.lock().unwrap().errors.push_back(msg.to_string());
+    }
+
+    /// これまでの書き込み
+    pub fn writes(&self) -> Vec<Write> {
+        self.state.lock().unwrap().writes.clone()
+    }
+
+    /// 書き込みの記録を消去
+    pub fn clear_writes(&self) {
+        self.state.lock().unwrap().writes.clear();
+    }
+
+    fn check_error(state: &mut I2cState) -> EResult<()> {
+        match state.errors.pop_front() {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    fn read(&self, reg: u8, buf: &mut [u8]) -> EResult<()> {
+        let mut state = self.state.lock().unwrap();
+        Self::check_error(&mut state)?;
+
+        let key = (state.addr, reg);
+        let data = match state.queued.get_mut(&key).and_then(|q| q.pop_front()) {
+            Some(data) => data,
+            None => match state.regs.get(&key) {
+                Some(data) => data.clone(),
+                None => {
+                    return Err(
+                        format!("fake I2C: no data at 0x{:02x}, reg 0x{:02x}", key.0, reg).into(),
+                    )
+                }
+            },
+        };
+
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = data.get(i).copied().unwrap_or(0);
+        }
+
+        Ok(())
+    }
+
+    fn record(&self, reg: Option<u8>, data: &[u8]) -> EResult<()> {
+        let mut state = self.state.lock().unwrap();
+        Self::check_error(&mut state)?;
+
+        let addr = state.addr;
+        state.writes.push(Write {
+            addr,
+            reg,
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+}
+
+impl I2cBus for FakeI2c {
+    fn set_slave_address(&mut self, addr: u16) -> EResult<()> {
+        self.state.lock().unwrap().addr = addr;
+        Ok(())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> EResult<()> {
+        match buf.split_first() {
+            Some((reg, data)) => self.record(Some(*reg), data),
+            None => self.record(None, &[]),
+        }
+    }
+
+    fn smbus_send_byte(&mut self, value: u8) -> EResult<()> {
+        self.record(None, &[value])
+    }
+
+    fn smbus_read_byte(&mut self, reg: u8) -> EResult<u8> {
+        let mut buf = [0; 1];
+        self.read(reg, &mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn smbus_write_byte(&mut self, reg: u8, value: u8) -> EResult<()> {
+        self.record(Some(reg), &[value])
+    }
+
+    fn smbus_read_word(&mut self, reg: u8) -> EResult<u16> {
+        // SMBusのワードは最初に受信したバイトが下位
+        let mut buf = [0; 2];
+        self.read(reg, &mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn smbus_block_write(&mut self, reg: u8, buf: &[u8]) -> EResult<()> {
+        self.record(Some(reg), &[&[buf.len() as u8][..], buf].concat())
+    }
+
+    fn block_read(&mut self, reg: u8, buf: &mut [u8]) -> EResult<()> {
+        self.read(reg, buf)
+    }
+}
+
+#[derive(Default)]
+struct SpiState {
+    responses: VecDeque<Vec<u8>>,
+    writes: Vec<Vec<u8>>,
+}
+
+/// 偽SPIバス
+#[derive(Clone, Default)]
+pub struct FakeSpi {
+    state: Arc<Mutex<SpiState>>,
+}
+
+impl FakeSpi {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// 次の転送で受信するデータを追加
+    pub fn push_response(&self, data: &[u8]) {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .push_back(data.to_vec());
+    }
+
+    /// これまでに送信したデータ
+    pub fn writes(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().writes.clone()
+    }
+}
+
+impl SpiBus for FakeSpi {
+    fn transfer(&mut self, read_buf: &mut [u8], write_buf: &[u8]) -> EResult<usize> {
+        let mut state = self.state.lock().unwrap();
+        state.writes.push(write_buf.to_vec());
+
+        let data = match state.responses.pop_front() {
+            Some(data) => data,
+            None => return Err("fake SPI: no response".into()),
+        };
+
+        for (i, b) in read_buf.iter_mut().enumerate() {
+            *b = data.get(i).copied().unwrap_or(0);
+        }
+
+        Ok(read_buf.len())
+    }
+}
+
+struct InputState {
+    level: Level,
+    events: VecDeque<Option<Level>>, // poll_interruptの結果（Noneはタイムアウト）
+}
+
+/// 偽GPIO入力ピン
+#[derive(Clone)]
+pub struct FakeInputPin {
+    pin: u8,
+    state: Arc<Mutex<InputState>>,
+}
+
+impl FakeInputPin {
+    pub fn new(pin: u8) -> Self {
+        FakeInputPin {
+            pin,
+            state: Arc::new(Mutex::new(InputState {
+                level: Level::Low,
+                events: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// 入力の変化を追加
+    pub fn push_edge(&self, level: Level) {
+        self.state.lock().unwrap().events.push_back(Some(level));
+    }
+
+    /// 変化を通知せずにレベルを変更（割り込みを逃した場合）
+    pub fn set_level(&self, level: Level) {
+        self.state.lock().unwrap().level = level;
+    }
+
+    /// タイムアウトを追加
+    pub fn push_timeout(&self) {
+        self.state.lock().unwrap().events.push_back(None);
+    }
+}
+
+impl InputPin for FakeInputPin {
+    fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    fn read(&self) -> Level {
+        self.state.lock().unwrap().level
+    }
+
+    fn poll_interrupt(
+        &mut self,
+        _reset: bool,
+        timeout: Option<Duration>,
+    ) -> EResult<Option<Level>> {
+        let event = self.state.lock().unwrap().events.pop_front();
+        match event {
+            Some(Some(level)) => {
+                self.state.lock().unwrap().level = level;
+                Ok(Some(level))
+            }
+            _ => {
+                // 実機と同様にタイムアウトまで待機
+                if let Some(t) = timeout {
+                    std::thread::sleep(t);
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// 偽GPIO出力ピン
+///
+/// 出力したレベルを記録する。
+#[derive(Clone, Default)]
+pub struct FakeOutputPin {
+    levels: Arc<Mutex<Vec<Level>>>,
+}
+
+impl FakeOutputPin {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// これまでに出力したレベル
+    pub fn levels(&self) -> Vec<Level> {
+        self.levels.lock().unwrap().clone()
+    }
+
+    /// 現在のレベル
+    pub fn level(&self) -> Option<Level> {
+        self.levels.lock().unwrap().last().copied()
+    }
+}
+
+impl OutputPin for FakeOutputPin {
+    fn set_high(&mut self) {
+        self.levels.lock().unwrap().push(Level::High);
+    }
+
+    fn set_low(&mut self) {
+        self.levels.lock().unwrap().push(Level::Low);
+    }
+}
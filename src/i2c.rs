@@ -1,49 +1,129 @@
+#[cfg(feature = "linux")]
 #[allow(unused_imports)]
 use async_std::prelude::*;
 
-use super::{Air, EResult};
-use async_std::{
-    channel::Receiver,
-    sync::Mutex,
-    task::{self, JoinHandle},
+#[cfg(feature = "linux")]
+use super::{Air, DbStatus, EResult};
+#[cfg(feature = "linux")]
+use crate::{
+    alarm::Alarms,
+    button::Button,
+    co2::Bands,
+    config::SharedConfig,
+    gpio::Ccs811Pins,
+    hal::Hardware,
+    shutdown::{Shutdown, Stage},
+    supervisor::{self, Supervisor},
 };
-use rppal::{gpio::OutputPin, i2c::I2c};
+#[cfg(feature = "linux")]
+use async_std::{sync::Mutex, task::JoinHandle};
+#[cfg(feature = "linux")]
 use std::sync::{atomic::AtomicU64, Arc};
 
-mod adt7410;
-mod ccs811;
-mod st7032;
+#[cfg(feature = "adt7410")]
+pub mod adt7410;
+#[cfg(feature = "ccs811")]
+pub mod ccs811;
+#[cfg(feature = "st7032")]
+pub mod st7032;
 
-trait Runner {
-    fn run(self, bus: Arc<Mutex<I2c>>) -> EResult<JoinHandle<()>>;
+/// I2Cデバイスを周期的に実行するタスク
+#[cfg(feature = "linux")]
+pub trait Runner<B> {
+    /// タスクを起動。終了が要求されるとJoinHandleが完了する
+    fn run(self, bus: Arc<Mutex<B>>) -> EResult<JoinHandle<EResult<()>>>;
 }
 
-pub async fn run(
-    sig_rx: Receiver<()>,
-    ccs811_pin: OutputPin,
+/// I2Cのタスクを監視付きで起動
+///
+/// 有効なfeatureのデバイスのみ起動する。
+/// ディスプレイは`button`でページを切り替え、`db`の状況も表示する。
+#[cfg(feature = "linux")]
+#[allow(clippy::too_many_arguments)]
+pub async fn run<H: Hardware>(
+    hw: &H,
+    shutdown: &Shutdown,
+    supervisor: &Supervisor,
+    config: SharedConfig,
+    ccs811_pins: Ccs811Pins<H::Output>,
     air: Air,
     bright: Arc<AtomicU64>,
-) -> EResult<JoinHandle<()>> {
-    let bus = Arc::new(Mutex::new(I2c::new()?));
+    alarms: Alarms,
+    bands: Bands,
+    button: Button,
+    db: DbStatus,
+) -> EResult<()> {
+    let bus = Arc::new(Mutex::new(hw.i2c()?));
 
-    // ディスプレイ
-    let display = st7032::ST7032::new(sig_rx.clone(), air.temp.clone(), bright)
-        .init(&bus)
-        .await?;
+    // 無効なデバイスの引数
+    #[cfg(not(feature = "st7032"))]
+    let _ = (bright, alarms, button, db);
+    #[cfg(not(feature = "ccs811"))]
+    let _ = (ccs811_pins, bands);
 
-    let task_display = display.run(bus.clone())?;
+    // ディスプレイ（最後に終了させる）
+    #[cfg(feature = "st7032")]
+    {
+        let token = shutdown.token(Stage::Consumer, "ST7032");
+        let (c, b, a) = (config.clone(), bus.clone(), air.clone());
+        let started = std::time::Instant::now(); // 再起動しても稼働時間は起動時から数える
+        supervisor.spawn("ST7032", token, move |token| {
+            let display = st7032::ST7032::new(
+                token,
+                c.clone(),
+                a.clone(),
+                bright.clone(),
+                alarms.clone(),
+                button.clone(),
+                db.clone(),
+                started,
+            );
+            let bus = b.clone();
+            async move {
+                let display = display.init(&bus).await?;
+                supervisor::join(display.run(bus)).await
+            }
+        });
+    }
 
-    // 温度センサ
-    let task_adt7410 = adt7410::ADT7410::new(sig_rx.clone(), air.temp.clone()).run(bus.clone())?;
+    // 温度センサ（センサごとにタスクを起動し、バスを共有する）
+    #[cfg(feature = "adt7410")]
+    {
+        let sensors = config.read().unwrap().adt7410.sensors();
+        for (sensor, temp) in sensors.into_iter().zip(air.temps.iter().cloned()) {
+            // タスク名は起動時に一度だけ生成する
+            let name: &'static str = Box::leak(format!("ADT7410 {}", sensor.name).into_boxed_str());
+            let token = shutdown.token(Stage::Producer, name);
+            let (c, b) = (config.clone(), bus.clone());
+            supervisor.spawn(name, token, move |token| {
+                let adt7410 = adt7410::ADT7410::new(token, c.clone(), sensor.addr, temp.clone());
+                supervisor::join(adt7410.run(b.clone()))
+            });
+        }
+    }
 
     // 環境センサ
-    let task_ccs811 = ccs811::CCS811::new(sig_rx, ccs811_pin, air).run(bus)?;
+    #[cfg(feature = "ccs811")]
+    {
+        let token = shutdown.token(Stage::Producer, "CCS811");
+        let (c, b, a) = (config.clone(), bus.clone(), air.clone());
+        let ccs811_pin = Arc::new(Mutex::new(ccs811_pins.wake));
+        let reset_pin = ccs811_pins.reset.map(|p| Arc::new(Mutex::new(p)));
+        let data_ready = ccs811_pins.data_ready;
+        supervisor.spawn("CCS811", token, move |token| {
+            let ccs811 = ccs811::CCS811::new(
+                token,
+                c.clone(),
+                ccs811_pin.clone(),
+                reset_pin.clone(),
+                data_ready.clone(),
+                a.clone(),
+                bands.clone(),
+            );
+            supervisor::join(ccs811.run(b.clone()))
+        });
+    }
 
-    let hdl = task::spawn(async move {
-        task_adt7410.await;
-        task_display.await;
-        task_ccs811.await;
-    });
-
-    Ok(hdl)
+    println!("initialized I2C");
+    Ok(())
 }
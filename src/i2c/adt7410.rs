@@ -1,70 +1,158 @@
-#[allow(unused_imports)]
-use async_std::prelude::*;
-
-use super::Runner;
-use crate::{perror, EResult};
-use async_std::{
-    channel::Receiver,
-    future::timeout,
-    sync::Mutex,
-    task::{self, JoinHandle},
-};
-use rppal::i2c::I2c;
-use std::{
-    sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
-    },
-    time::Duration,
-};
-
-/// 温度センサーADT7410
-pub(super) struct ADT7410 {
-    sig_rx: Receiver<()>,
-    temp: Arc<AtomicU64>, // 気温
+//! 温度センサーADT7410
+//!
+//! レジスタと温度の変換は、Linuxのドライバ[ADT7410]と`embedded-hal`版の[eh::ADT7410]で共通。
+
+use core::time::Duration;
+
+#[cfg(feature = "embedded-hal")]
+pub mod eh;
+#[cfg(feature = "linux")]
+mod linux;
+
+#[cfg(feature = "linux")]
+pub use linux::ADT7410;
+
+const REG_TEMP: u8 = 0;
+const REG_CONFIG: u8 = 3;
+const REG_T_HIGH: u8 = 4;
+const REG_T_LOW: u8 = 6;
+const REG_T_CRIT: u8 = 8;
+const REG_T_HYST: u8 = 0x0a;
+const REG_ID: u8 = 0x0b;
+
+const ID: u8 = 0b1100_1000; // 製造者ID（上位5ビット）
+const ID_MASK: u8 = 0b1111_1000; // 下位3ビットはリビジョン
+
+const CONFIG_RESOLUTION: u8 = 0b1000_0000; // 0: 13ビット、1: 16ビット
+const CONFIG_COMPARATOR: u8 = 0b0001_0000; // 0: 割り込みモード、1: コンパレータモード
+const CONFIG_INT_POLARITY: u8 = 0b0000_1000; // 0: アクティブLow、1: アクティブHigh
+const CONFIG_CT_POLARITY: u8 = 0b0000_0100; // 0: アクティブLow、1: アクティブHigh
+
+/// 分解能
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    Bits13, // 0.0625 度
+    Bits16, // 0.0078 度
+}
+
+/// アラームの閾値
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub t_high: f64, // これより高いとINTをアクティブにする（度）
+    pub t_low: f64,  // これより低いとINTをアクティブにする（度）
+    pub t_crit: f64, // これより高いとCTをアクティブにする（度）
+    pub t_hyst: u8,  // 解除までのヒステリシス（0 - 15度）
+}
+
+/// 動作モード
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Continuous, // 連続して変換
+    OneShot,    // 一度だけ変換し、その後はシャットダウン
+    Sps1,       // 1秒に一度変換（1 SPS）
+    Shutdown,   // 変換しない
+}
+
+impl Mode {
+    const MASK: u8 = 0b0110_0000;
+
+    const SPS1_INTERVAL: Duration = Duration::from_secs(1); // これ以上の間隔では1 SPS
+    const ONE_SHOT_INTERVAL: Duration = Duration::from_secs(4); // これ以上の間隔ではワンショット
+
+    /// 測定間隔から消費電力の少ないモードを選ぶ
+    ///
+    /// 1 SPSは1秒ごとに60ミリ秒、ワンショットは読み込みごとに240ミリ秒変換するため、
+    /// 4秒以上の間隔ではワンショットの方が変換している時間が短い。
+    pub fn from_interval(interval: Duration) -> Mode {
+        if interval >= Self::ONE_SHOT_INTERVAL {
+            Mode::OneShot
+        } else if interval >= Self::SPS1_INTERVAL {
+            Mode::Sps1
+        } else {
+            Mode::Continuous
+        }
+    }
+
+    /// 変換にかかる時間
+    pub fn conversion_time(self) -> Duration {
+        match self {
+            Mode::Continuous | Mode::OneShot => Duration::from_millis(240),
+            Mode::Sps1 => Duration::from_millis(60),
+            Mode::Shutdown => Duration::ZERO,
+        }
+    }
+
+    /// 読み込みを待つ間のモード
+    ///
+    /// ワンショットは読み込みの直前に変換を開始するため、それまではシャットダウンしておく。
+    fn idle(self) -> Mode {
+        match self {
+            Mode::OneShot => Mode::Shutdown,
+            m => m,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Mode::Continuous => 0b0000_0000,
+            Mode::OneShot => 0b0010_0000,
+            Mode::Sps1 => 0b0100_0000,
+            Mode::Shutdown => 0b0110_0000,
+        }
+    }
 }
 
-impl ADT7410 {
-    const ADDR: u16 = 0x48;
-    const REG: u8 = 0;
+/// IDレジスタの値がADT7410か
+fn valid_id(id: u8) -> bool {
+    id & ID_MASK == ID
+}
 
-    pub(super) fn new(sig_rx: Receiver<()>, temp: Arc<AtomicU64>) -> Self {
-        ADT7410 { sig_rx, temp }
+/// 閾値の大小とヒステリシスの範囲を確認
+fn check_limits(limits: &Limits) -> Result<(), &'static str> {
+    if !(limits.t_low < limits.t_high && limits.t_high < limits.t_crit) {
+        return Err("limits must be t_low < t_high < t_crit");
     }
+    if limits.t_hyst > 15 {
+        return Err("t_hyst must be 0 - 15");
+    }
+    Ok(())
+}
+
+/// コンフィギュレーションレジスタに書き込む値
+fn config_value(conf: u8, resolution: Resolution, mode: Mode) -> u8 {
+    let conf = match resolution {
+        Resolution::Bits13 => conf & !CONFIG_RESOLUTION,
+        Resolution::Bits16 => conf | CONFIG_RESOLUTION,
+    };
+    let conf = (conf & !Mode::MASK) | mode.bits();
+    (conf | CONFIG_COMPARATOR) & !(CONFIG_INT_POLARITY | CONFIG_CT_POLARITY)
+}
+
+/// 16ビットの閾値のレジスタと温度
+fn setpoints(limits: &Limits) -> [(u8, f64); 3] {
+    [
+        (REG_T_HIGH, limits.t_high),
+        (REG_T_LOW, limits.t_low),
+        (REG_T_CRIT, limits.t_crit),
+    ]
+}
+
+/// 温度を閾値のレジスタの値（16ビットの2の補数、上位、下位の順）に変換
+///
+/// 1/128度単位に四捨五入する（`f64::round`はno_stdで使えないため、0.5ずらして切り捨て）。
+fn encode(celsius: f64) -> [u8; 2] {
+    let code = celsius * 128.0;
+    let code = if code < 0.0 { code - 0.5 } else { code + 0.5 };
+    (code as i16).to_be_bytes()
 }
 
-impl Runner for ADT7410 {
-    fn run(self, bus: Arc<Mutex<I2c>>) -> EResult<JoinHandle<()>> {
-        let wsec = Duration::from_secs(1);
-
-        let f = async move {
-            loop {
-                // タイムアウトかシグナルでの終了を待つ
-                if timeout(wsec, self.sig_rx.recv()).await.is_ok() {
-                    println!("exiting ADT7410 ...");
-                    break;
-                }
-
-                {
-                    let mut guard = bus.lock().await;
-                    if let Err(e) = guard.set_slave_address(Self::ADDR) {
-                        perror!(e);
-                    }
-
-                    match guard.smbus_read_word(Self::REG) {
-                        Ok(n) => {
-                            let celciuls = (n.to_be() >> 3) as f64 / 16.0;
-                            self.temp.store(celciuls.to_bits(), Ordering::Relaxed); // 共有変数に保存
-                            println!("ADT7410: {:.2} 度", celciuls);
-                        }
-                        Err(e) => {
-                            perror!(e);
-                        }
-                    }
-                }
-            }
-        };
-
-        Ok(task::spawn(f))
+/// 受信した2バイト（上位、下位の順）を温度に変換
+///
+/// どちらの分解能も2の補数。13ビットの場合、下位3ビットはフラグなので捨てる。
+fn decode(raw: [u8; 2], resolution: Resolution) -> f64 {
+    let code = i16::from_be_bytes(raw);
+    match resolution {
+        Resolution::Bits13 => (code >> 3) as f64 / 16.0,
+        Resolution::Bits16 => code as f64 / 128.0,
     }
 }
@@ -0,0 +1,261 @@
+//! `embedded-hal`のI2Cで動作するADT7410
+//!
+//! `linux-embedded-hal`やマイコンのHALのI2Cを渡して使う。
+//! レジスタの読み方と温度への変換はLinuxのドライバと共通。std、allocを使わない。
+
+use super::{
+    check_limits, config_value, decode, encode, setpoints, valid_id, Limits, Mode, Resolution,
+    REG_CONFIG, REG_ID, REG_TEMP, REG_T_HYST,
+};
+use core::fmt;
+
+/// エラー
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    Bus(E),                      // I2Cの通信エラー
+    InvalidId(u8),               // IDレジスタの値がADT7410ではない
+    InvalidLimits(&'static str), // 閾値の大小かヒステリシスの範囲が不正
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::Bus(e)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Bus(e) => write!(f, "ADT7410: bus error: {e:?}"),
+            Error::InvalidId(id) => write!(f, "ADT7410: unexpected ID 0x{id:02x}"),
+            Error::InvalidLimits(e) => write!(f, "ADT7410: {e}"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for Error<E> {}
+
+/// 温度センサーADT7410
+pub struct ADT7410<I2C> {
+    i2c: I2C,
+    addr: u8,
+    resolution: Resolution,
+}
+
+impl<I2C> ADT7410<I2C> {
+    /// 生成
+    ///
+    /// 分解能は電源投入時と同じ13ビットとみなす。変更する場合は`init`を呼ぶ。
+    pub fn new(i2c: I2C, addr: u8) -> Self {
+        ADT7410 {
+            i2c,
+            addr,
+            resolution: Resolution::Bits13,
+        }
+    }
+
+    /// I2Cを返して破棄
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C: embedded_hal::i2c::I2c> ADT7410<I2C> {
+    /// IDを確認し、分解能と動作モードを設定
+    ///
+    /// ワンショットの場合は`one_shot`を呼ぶまでシャットダウンしておく。
+    pub fn init(&mut self, resolution: Resolution, mode: Mode) -> Result<(), Error<I2C::Error>> {
+        let id = self.read_byte(REG_ID)?;
+        if !valid_id(id) {
+            return Err(Error::InvalidId(id));
+        }
+        self.configure(resolution, mode.idle())?;
+        self.resolution = resolution;
+        Ok(())
+    }
+
+    /// アラームの閾値を設定
+    ///
+    /// `t_low < t_high < t_crit`でない場合と、`t_hyst`が15度を超える場合はエラー。
+    pub fn set_limits(&mut self, limits: &Limits) -> Result<(), Error<I2C::Error>> {
+        check_limits(limits).map_err(Error::InvalidLimits)?;
+        for (reg, celsius) in setpoints(limits) {
+            let [msb, lsb] = encode(celsius);
+            self.i2c.write(self.addr, &[reg, msb, lsb])?;
+        }
+        let hyst = [REG_T_HYST, limits.t_hyst];
+        self.i2c.write(self.addr, &hyst)?;
+        Ok(())
+    }
+
+    /// 温度を一度読み込む
+    ///
+    /// 最後に変換した値を返す。
+    pub fn read(&mut self) -> Result<f64, Error<I2C::Error>> {
+        let mut buf = [0; 2];
+        self.i2c.write_read(self.addr, &[REG_TEMP], &mut buf)?;
+        Ok(decode(buf, self.resolution))
+    }
+
+    /// 一度だけ変換して読み込む
+    ///
+    /// 変換後はシャットダウンする。
+    pub fn one_shot<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<f64, Error<I2C::Error>> {
+        self.configure(self.resolution, Mode::OneShot)?;
+        delay.delay_ms(Mode::OneShot.conversion_time().as_millis() as u32);
+        self.read()
+    }
+
+    /// シャットダウンモードにする
+    pub fn shutdown(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.configure(self.resolution, Mode::Shutdown)
+    }
+
+    fn read_byte(&mut self, reg: u8) -> Result<u8, Error<I2C::Error>> {
+        let mut buf = [0; 1];
+        self.i2c.write_read(self.addr, &[reg], &mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn configure(&mut self, resolution: Resolution, mode: Mode) -> Result<(), Error<I2C::Error>> {
+        let reg = REG_CONFIG;
+        let conf = self.read_byte(reg)?;
+        let conf = config_value(conf, resolution, mode);
+        self.i2c.write(self.addr, &[reg, conf])?;
+        Ok(())
+    }
+}
+
+impl<I2C: embedded_hal_async::i2c::I2c> ADT7410<I2C> {
+    /// IDを確認し、分解能と動作モードを設定（非同期）
+    pub async fn init_async(
+        &mut self,
+        resolution: Resolution,
+        mode: Mode,
+    ) -> Result<(), Error<I2C::Error>> {
+        let id = self.read_byte_async(REG_ID).await?;
+        if !valid_id(id) {
+            return Err(Error::InvalidId(id));
+        }
+        self.configure_async(resolution, mode.idle()).await?;
+        self.resolution = resolution;
+        Ok(())
+    }
+
+    /// アラームの閾値を設定（非同期）
+    pub async fn set_limits_async(&mut self, limits: &Limits) -> Result<(), Error<I2C::Error>> {
+        check_limits(limits).map_err(Error::InvalidLimits)?;
+        for (reg, celsius) in setpoints(limits) {
+            let [msb, lsb] = encode(celsius);
+            self.i2c.write(self.addr, &[reg, msb, lsb]).await?;
+        }
+        let hyst = [REG_T_HYST, limits.t_hyst];
+        self.i2c.write(self.addr, &hyst).await?;
+        Ok(())
+    }
+
+    /// 温度を一度読み込む（非同期）
+    pub async fn read_async(&mut self) -> Result<f64, Error<I2C::Error>> {
+        let mut buf = [0; 2];
+        self.i2c
+            .write_read(self.addr, &[REG_TEMP], &mut buf)
+            .await?;
+        Ok(decode(buf, self.resolution))
+    }
+
+    /// 一度だけ変換して読み込む（非同期）
+    pub async fn one_shot_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<f64, Error<I2C::Error>> {
+        self.configure_async(self.resolution, Mode::OneShot).await?;
+        delay
+            .delay_ms(Mode::OneShot.conversion_time().as_millis() as u32)
+            .await;
+        self.read_async().await
+    }
+
+    /// シャットダウンモードにする（非同期）
+    pub async fn shutdown_async(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.configure_async(self.resolution, Mode::Shutdown).await
+    }
+
+    async fn read_byte_async(&mut self, reg: u8) -> Result<u8, Error<I2C::Error>> {
+        let mut buf = [0; 1];
+        self.i2c.write_read(self.addr, &[reg], &mut buf).await?;
+        Ok(buf[0])
+    }
+
+    async fn configure_async(
+        &mut self,
+        resolution: Resolution,
+        mode: Mode,
+    ) -> Result<(), Error<I2C::Error>> {
+        let reg = REG_CONFIG;
+        let conf = self.read_byte_async(reg).await?;
+        let conf = config_value(conf, resolution, mode);
+        self.i2c.write(self.addr, &[reg, conf]).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock, Transaction},
+    };
+
+    #[test]
+    fn read_temperature() {
+        let i2c = Mock::new(&[Transaction::write_read(0x48, vec![0], vec![0x0c, 0x80])]);
+        let mut adt7410 = ADT7410::new(i2c, 0x48);
+        assert_eq!(adt7410.read().unwrap(), 25.0);
+        adt7410.release().done();
+    }
+
+    #[test]
+    fn one_shot() {
+        let i2c = Mock::new(&[
+            Transaction::write_read(0x48, vec![0x0b], vec![0xcb]), // ID
+            Transaction::write_read(0x48, vec![3], vec![0x00]),
+            Transaction::write(0x48, vec![3, 0x70]), // シャットダウン
+            Transaction::write_read(0x48, vec![3], vec![0x70]),
+            Transaction::write(0x48, vec![3, 0x30]), // ワンショット
+            Transaction::write_read(0x48, vec![0], vec![0x0c, 0x80]),
+        ]);
+        let mut adt7410 = ADT7410::new(i2c, 0x48);
+        adt7410.init(Resolution::Bits13, Mode::OneShot).unwrap();
+        assert_eq!(adt7410.one_shot(&mut NoopDelay).unwrap(), 25.0);
+        adt7410.release().done();
+    }
+
+    #[test]
+    fn invalid_id() {
+        let i2c = Mock::new(&[Transaction::write_read(0x48, vec![0x0b], vec![0x81])]);
+        let mut adt7410 = ADT7410::new(i2c, 0x48);
+        assert!(adt7410.init(Resolution::Bits13, Mode::Continuous).is_err());
+        adt7410.release().done();
+    }
+
+    #[async_std::test]
+    async fn read_16bit_async() {
+        let i2c = Mock::new(&[
+            Transaction::write_read(0x48, vec![0x0b], vec![0xcb]),
+            Transaction::write_read(0x48, vec![3], vec![0x00]),
+            Transaction::write(0x48, vec![3, 0x90]), // 16ビット、コンパレータモード
+            Transaction::write_read(0x48, vec![0], vec![0xff, 0xff]), // -0.0078 度
+        ]);
+        let mut adt7410 = ADT7410::new(i2c, 0x48);
+        adt7410
+            .init_async(Resolution::Bits16, Mode::Continuous)
+            .await
+            .unwrap();
+        assert_eq!(adt7410.read_async().await.unwrap(), -0.0078125);
+        adt7410.release().done();
+    }
+}
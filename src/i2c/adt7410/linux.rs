@@ -0,0 +1,491 @@
+//! Linuxの[I2cBus]で動作するADT7410
+
+#[allow(unused_imports)]
+use async_std::prelude::*;
+
+use super::{
+    check_limits, config_value, decode, encode, setpoints, valid_id, Limits, Mode, Resolution,
+    REG_CONFIG, REG_ID, REG_TEMP, REG_T_HYST,
+};
+use crate::{
+    config::{self, SharedConfig},
+    hal::I2cBus,
+    i2c::Runner,
+    perror,
+    shutdown::Token,
+    EResult, Temperature,
+};
+use async_std::{
+    future::timeout,
+    sync::Mutex,
+    task::{self, JoinHandle},
+};
+use std::sync::{atomic::Ordering, Arc};
+
+/// 温度センサーADT7410
+///
+/// 同じバスに複数接続する場合は、アドレスごとに生成する。
+pub struct ADT7410 {
+    shutdown: Token,
+    config: SharedConfig,
+    addr: u16,
+    temp: Temperature, // 気温
+}
+
+impl From<&config::ADT7410> for Limits {
+    fn from(conf: &config::ADT7410) -> Self {
+        Limits {
+            t_high: conf.t_high,
+            t_low: conf.t_low,
+            t_crit: conf.t_crit,
+            t_hyst: conf.t_hyst,
+        }
+    }
+}
+
+impl From<config::Resolution> for Resolution {
+    fn from(resolution: config::Resolution) -> Self {
+        match resolution {
+            config::Resolution::Bits13 => Resolution::Bits13,
+            config::Resolution::Bits16 => Resolution::Bits16,
+        }
+    }
+}
+
+impl ADT7410 {
+    /// 生成
+    ///
+    /// 読み込んだ気温は`temp`に`f64::to_bits`で格納される。
+    pub fn new(shutdown: Token, config: SharedConfig, addr: u16, temp: Temperature) -> Self {
+        ADT7410 {
+            shutdown,
+            config,
+            addr,
+            temp,
+        }
+    }
+
+    /// 初期化
+    ///
+    /// IDレジスタでADT7410であることを確認してから、`configure`で設定する。
+    /// ワンショットの場合は、変換を開始するまでシャットダウンしておく。
+    pub fn init<B: I2cBus>(
+        bus: &mut B,
+        addr: u16,
+        resolution: Resolution,
+        mode: Mode,
+    ) -> EResult<()> {
+        Self::check_id(bus, addr)?;
+        Self::configure(bus, addr, resolution, mode.idle())
+    }
+
+    /// IDレジスタを確認
+    ///
+    /// 別のデバイスの値を気温として扱わないようにする。
+    pub fn check_id<B: I2cBus>(bus: &mut B, addr: u16) -> EResult<()> {
+        bus.set_slave_address(addr)?;
+        let id = bus.smbus_read_byte(REG_ID)?;
+        if !valid_id(id) {
+            return Err(format!("ADT7410: unexpected ID 0x{id:02x} at 0x{addr:02x}").into());
+        }
+        Ok(())
+    }
+
+    /// 分解能と動作モードを設定
+    ///
+    /// コンフィギュレーションレジスタの分解能と動作モードを設定し、INTとCTをコンパレータモード、
+    /// アクティブLowにする。フォールトキューは変更しない。
+    /// ワンショットを指定すると変換を開始するので、`Mode::conversion_time`待ってから読み込む。
+    pub fn configure<B: I2cBus>(
+        bus: &mut B,
+        addr: u16,
+        resolution: Resolution,
+        mode: Mode,
+    ) -> EResult<()> {
+        bus.set_slave_address(addr)?;
+        let conf = bus.smbus_read_byte(REG_CONFIG)?;
+        bus.smbus_write_byte(REG_CONFIG, config_value(conf, resolution, mode))?;
+        Ok(())
+    }
+
+    /// アラームの閾値を設定
+    ///
+    /// `t_low < t_high < t_crit`でない場合と、`t_hyst`が15度を超える場合はエラー。
+    pub fn set_limits<B: I2cBus>(bus: &mut B, addr: u16, limits: &Limits) -> EResult<()> {
+        check_limits(limits).map_err(|e| format!("ADT7410: {e}"))?;
+        bus.set_slave_address(addr)?;
+        for (reg, celsius) in setpoints(limits) {
+            let [msb, lsb] = encode(celsius);
+            bus.write(&[reg, msb, lsb])?;
+        }
+        bus.smbus_write_byte(REG_T_HYST, limits.t_hyst)?;
+        Ok(())
+    }
+
+    /// 温度を一度読み込む
+    ///
+    /// `resolution`は`init`で設定した分解能。最後に変換した値を返す。
+    pub fn read<B: I2cBus>(bus: &mut B, addr: u16, resolution: Resolution) -> EResult<f64> {
+        bus.set_slave_address(addr)?;
+        let n = bus.smbus_read_word(REG_TEMP)?;
+        Ok(decode(n.to_le_bytes(), resolution)) // SMBusのワードは最初に受信したバイトが下位
+    }
+}
+
+impl ADT7410 {
+    /// ワンショットの場合は変換を開始して、変換時間待つ
+    async fn convert<B: I2cBus>(
+        &self,
+        bus: &Arc<Mutex<B>>,
+        resolution: Resolution,
+        mode: Mode,
+    ) -> EResult<()> {
+        if mode == Mode::OneShot {
+            {
+                let mut guard = bus.lock().await;
+                Self::configure(&mut *guard, self.addr, resolution, mode)?;
+            }
+            task::sleep(mode.conversion_time()).await; // 待機中はバスを解放
+        }
+        Ok(())
+    }
+
+    /// シャットダウンモードにする
+    async fn power_down<B: I2cBus>(&self, bus: &Arc<Mutex<B>>, resolution: Resolution) {
+        let mut guard = bus.lock().await;
+        if let Err(e) = Self::configure(&mut *guard, self.addr, resolution, Mode::Shutdown) {
+            perror!(e);
+        }
+    }
+}
+
+impl<B: I2cBus> Runner<B> for ADT7410 {
+    fn run(self, bus: Arc<Mutex<B>>) -> EResult<JoinHandle<EResult<()>>> {
+        let addr = self.addr;
+
+        let f = async move {
+            let conf = self.config.read().unwrap().adt7410.clone();
+            let mut resolution = Resolution::from(conf.resolution);
+            let mut mode = Mode::from_interval(config::millis(conf.interval_ms));
+            let mut limits = Limits::from(&conf);
+            {
+                let mut guard = bus.lock().await;
+                if let Err(e) = Self::init(&mut *guard, addr, resolution, mode)
+                    .and_then(|_| Self::set_limits(&mut *guard, addr, &limits))
+                {
+                    perror!(e);
+                    return Err(e);
+                }
+            }
+            println!("ADT7410 {}: {:?} mode", self.temp.name, mode);
+            task::sleep(mode.idle().conversion_time()).await; // 最初の変換を待つ
+
+            loop {
+                let conf = self.config.read().unwrap().adt7410.clone();
+                let wsec = config::millis(conf.interval_ms);
+
+                // タイムアウトかシグナルでの終了を待つ
+                if timeout(wsec, self.shutdown.cancelled()).await.is_ok() {
+                    println!("exiting ADT7410 {} ...", self.temp.name);
+                    self.power_down(&bus, resolution).await; // 次に起動するまで変換しない
+                    break;
+                }
+
+                {
+                    let mut guard = bus.lock().await;
+
+                    // 設定の再読み込みによる分解能、動作モードの変更
+                    let new_mode = Mode::from_interval(wsec);
+                    let new_resolution = Resolution::from(conf.resolution);
+                    if new_resolution != resolution || new_mode != mode {
+                        if let Err(e) =
+                            Self::configure(&mut *guard, addr, new_resolution, new_mode.idle())
+                        {
+                            perror!(e);
+                            return Err(e);
+                        }
+                        if new_mode != mode {
+                            println!("ADT7410 {}: {:?} mode", self.temp.name, new_mode);
+                        }
+                        resolution = new_resolution;
+                        mode = new_mode;
+                    }
+
+                    // 設定の再読み込みによる閾値の変更
+                    if Limits::from(&conf) != limits {
+                        limits = Limits::from(&conf);
+                        if let Err(e) = Self::set_limits(&mut *guard, addr, &limits) {
+                            perror!(e);
+                            return Err(e);
+                        }
+                    }
+                }
+
+                let result = match self.convert(&bus, resolution, mode).await {
+                    Ok(()) => {
+                        let mut guard = bus.lock().await;
+                        Self::read(&mut *guard, addr, resolution)
+                    }
+                    Err(e) => Err(e),
+                };
+
+                match result {
+                    Ok(celsius) => {
+                        // 共有変数に保存
+                        self.temp.value.store(celsius.to_bits(), Ordering::Relaxed);
+                        println!("ADT7410 {}: {:.2} 度", self.temp.name, celsius);
+                    }
+                    Err(e) => {
+                        perror!(e);
+                    }
+                }
+            }
+
+            Ok(())
+        };
+
+        Ok(task::spawn(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hal::fake::FakeI2c,
+        i2c::adt7410::{REG_T_CRIT, REG_T_HIGH, REG_T_LOW},
+        shutdown::{Shutdown, Stage},
+        Air,
+    };
+    use std::{sync::RwLock, time::Duration};
+
+    #[test]
+    fn read_temperature() {
+        let mut bus = FakeI2c::new();
+        bus.set_reg(0x48, REG_TEMP, &[0x0c, 0x80]); // 25 度
+
+        let celsius = ADT7410::read(&mut bus, 0x48, Resolution::Bits13).unwrap();
+        assert_eq!(celsius, 25.0);
+    }
+
+    #[test]
+    fn read_error() {
+        let mut bus = FakeI2c::new();
+        bus.fail_next("bus error");
+        assert!(ADT7410::read(&mut bus, 0x48, Resolution::Bits13).is_err());
+    }
+
+    #[test]
+    fn decode_13bit() {
+        // データシートの13ビットの温度データ形式の例（レジスタの値は左詰め）
+        let codes: [(u16, f64); 10] = [
+            (0x1d80, -40.0),
+            (0x1e70, -25.0),
+            (0x1ff8, -0.5),
+            (0x1fff, -0.0625),
+            (0x0000, 0.0),
+            (0x0001, 0.0625),
+            (0x0190, 25.0),
+            (0x0690, 105.0),
+            (0x07d0, 125.0),
+            (0x0960, 150.0),
+        ];
+
+        for (code, celsius) in codes {
+            // 左詰めし、下位3ビットにフラグを立てる
+            let reg = (code << 3) | 0b111;
+            let decoded = decode(reg.to_be_bytes(), Resolution::Bits13);
+            assert_eq!(decoded, celsius, "code = 0x{code:04x}");
+        }
+    }
+
+    #[test]
+    fn decode_16bit() {
+        // データシートの16ビットの温度データ形式の例
+        let codes: [(u16, f64); 10] = [
+            (0xec00, -40.0),
+            (0xf380, -25.0),
+            (0xffc0, -0.5),
+            (0xffff, -0.0078125),
+            (0x0000, 0.0),
+            (0x0001, 0.0078125),
+            (0x0c80, 25.0),
+            (0x3480, 105.0),
+            (0x3e80, 125.0),
+            (0x4b00, 150.0),
+        ];
+
+        for (code, celsius) in codes {
+            let decoded = decode(code.to_be_bytes(), Resolution::Bits16);
+            assert_eq!(decoded, celsius, "code = 0x{code:04x}");
+        }
+    }
+
+    #[test]
+    fn init_config() {
+        let mut bus = FakeI2c::new();
+        bus.set_reg(0x48, REG_ID, &[0xcb]);
+        bus.set_reg(0x48, REG_CONFIG, &[0b0001_0011]); // INT/CTと割り込みの設定は保持
+
+        ADT7410::init(&mut bus, 0x48, Resolution::Bits16, Mode::Continuous).unwrap();
+        bus.set_reg(0x48, REG_CONFIG, &[0b1001_0011]);
+        ADT7410::init(&mut bus, 0x48, Resolution::Bits13, Mode::Sps1).unwrap();
+        bus.set_reg(0x48, REG_CONFIG, &[0b0101_0011]);
+        ADT7410::init(&mut bus, 0x48, Resolution::Bits13, Mode::OneShot).unwrap();
+
+        let data: Vec<Vec<u8>> = bus.writes().into_iter().map(|w| w.data).collect();
+        assert_eq!(
+            data,
+            vec![
+                vec![0b1001_0011],
+                vec![0b0101_0011],
+                vec![0b0111_0011], // ワンショットは変換を開始するまでシャットダウン
+            ]
+        );
+        assert!(bus.writes().iter().all(|w| w.reg == Some(REG_CONFIG)));
+    }
+
+    #[test]
+    fn set_limits() {
+        let mut bus = FakeI2c::new();
+        let mut limits = Limits {
+            t_high: 30.0,
+            t_low: -10.5,
+            t_crit: 147.0,
+            t_hyst: 16, // 15度まで
+        };
+        assert!(ADT7410::set_limits(&mut bus, 0x48, &limits).is_err());
+        limits.t_low = 31.0; // t_highより高い
+        limits.t_hyst = 15;
+        assert!(ADT7410::set_limits(&mut bus, 0x48, &limits).is_err());
+        assert!(bus.writes().is_empty());
+
+        limits.t_low = -10.5;
+        ADT7410::set_limits(&mut bus, 0x48, &limits).unwrap();
+
+        let data: Vec<(Option<u8>, Vec<u8>)> =
+            bus.writes().into_iter().map(|w| (w.reg, w.data)).collect();
+        assert_eq!(
+            data,
+            vec![
+                (Some(REG_T_HIGH), vec![0x0f, 0x00]),
+                (Some(REG_T_LOW), vec![0xfa, 0xc0]),
+                (Some(REG_T_CRIT), vec![0x49, 0x80]), // 電源投入時の値
+                (Some(REG_T_HYST), vec![15]),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_id() {
+        let mut bus = FakeI2c::new();
+        bus.set_reg(0x48, REG_ID, &[0x00]); // 別のデバイス
+        bus.set_reg(0x48, REG_CONFIG, &[0]);
+
+        assert!(ADT7410::init(&mut bus, 0x48, Resolution::Bits13, Mode::Continuous).is_err());
+        assert!(bus.writes().is_empty());
+    }
+
+    #[test]
+    fn mode_from_interval() {
+        let ms = Duration::from_millis;
+        assert_eq!(Mode::from_interval(ms(500)), Mode::Continuous);
+        assert_eq!(Mode::from_interval(ms(1000)), Mode::Sps1);
+        assert_eq!(Mode::from_interval(ms(3999)), Mode::Sps1);
+        assert_eq!(Mode::from_interval(ms(60 * 1000)), Mode::OneShot);
+    }
+
+    #[async_std::test]
+    async fn one_shot() {
+        let fake = FakeI2c::new();
+        fake.set_reg(0x48, REG_CONFIG, &[0b0111_0000]); // シャットダウン中
+        let bus = Arc::new(Mutex::new(fake.clone()));
+
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Producer, "ADT7410");
+        let adt7410 = ADT7410::new(token, Default::default(), 0x48, Default::default());
+
+        let start = std::time::Instant::now();
+        adt7410
+            .convert(&bus, Resolution::Bits13, Mode::OneShot)
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= Mode::OneShot.conversion_time()); // 変換を待つ
+
+        let data: Vec<(Option<u8>, Vec<u8>)> =
+            fake.writes().into_iter().map(|w| (w.reg, w.data)).collect();
+        assert_eq!(data, vec![(Some(REG_CONFIG), vec![0b0011_0000])]);
+
+        // 連続変換では何もしない
+        fake.clear_writes();
+        adt7410
+            .convert(&bus, Resolution::Bits13, Mode::Continuous)
+            .await
+            .unwrap();
+        assert!(fake.writes().is_empty());
+    }
+
+    #[test]
+    fn read_negative() {
+        let mut bus = FakeI2c::new();
+        bus.set_reg(0x48, REG_TEMP, &[0xf3, 0x80]); // -25 度
+
+        let celsius = ADT7410::read(&mut bus, 0x48, Resolution::Bits13).unwrap();
+        assert_eq!(celsius, -25.0);
+        let celsius = ADT7410::read(&mut bus, 0x48, Resolution::Bits16).unwrap();
+        assert_eq!(celsius, -25.0);
+    }
+
+    #[async_std::test]
+    async fn two_sensors_on_one_bus() {
+        let fake = FakeI2c::new();
+        fake.set_reg(0x48, REG_TEMP, &[0x0c, 0x80]); // 25 度
+        fake.set_reg(0x4b, REG_TEMP, &[0x09, 0x60]); // 18.75 度
+        for addr in [0x48, 0x4b] {
+            fake.set_reg(addr, REG_ID, &[0xcb]);
+            fake.set_reg(addr, REG_CONFIG, &[0]);
+        }
+        let bus = Arc::new(Mutex::new(fake));
+
+        let mut conf = config::Config::default();
+        conf.adt7410.interval_ms = 10;
+        let config: SharedConfig = Arc::new(RwLock::new(conf));
+
+        let shutdown = Shutdown::new();
+        let air = Air::new(&config::ADT7410 {
+            sensors: vec![
+                config::Sensor {
+                    name: "living".to_string(),
+                    addr: 0x48,
+                    ..Default::default()
+                },
+                config::Sensor {
+                    name: "bedroom".to_string(),
+                    addr: 0x4b,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        });
+
+        let mut hdls = Vec::new();
+        for (addr, temp) in [0x48, 0x4b].into_iter().zip(air.temps.iter().cloned()) {
+            let token = shutdown.token(Stage::Producer, "ADT7410");
+            let adt7410 = ADT7410::new(token, config.clone(), addr, temp);
+            hdls.push(adt7410.run(bus.clone()).unwrap());
+        }
+
+        task::sleep(Duration::from_millis(300)).await; // 最初の変換を待ってから読み込む
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        for hdl in hdls {
+            hdl.await.unwrap();
+        }
+
+        let temps: Vec<(&str, f64)> = air
+            .temps
+            .iter()
+            .map(|t| (t.name.as_str(), t.celsius()))
+            .collect();
+        assert_eq!(temps, vec![("living", 25.0), ("bedroom", 18.75)]);
+    }
+}
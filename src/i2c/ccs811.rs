@@ -1,36 +1,41 @@
-#[allow(unused_imports)]
-use async_std::prelude::*;
-
-use super::Runner;
-use crate::{perror, Air, EResult};
-use async_std::{
-    channel::Receiver,
-    future::timeout,
-    sync::Mutex,
-    task::{self, JoinHandle},
-};
+//! 二酸化炭素・総揮発性有機化合物センサCCS811
+//!
+//! レジスタ、ハードウェアIDの確認、測定値とENV_DATA、THRESHOLDSの変換は、
+//! Linuxのドライバ[CCS811]と`embedded-hal`版の[eh::CCS811]で共通。
+
+use crate::co2::{self, Thresholds};
+#[cfg(feature = "linux")]
+use crate::EResult;
 use bitflags::bitflags;
-use rppal::{gpio::OutputPin, i2c::I2c};
-use std::{
-    sync::{atomic::Ordering, Arc},
-    time::Duration,
-};
-
-const ADDR: u16 = 0x5a;
+use core::fmt;
+
+#[cfg(feature = "linux")]
+mod baseline;
+#[cfg(feature = "embedded-hal")]
+pub mod eh;
+#[cfg(feature = "linux")]
+pub mod firmware;
+#[cfg(feature = "linux")]
+mod linux;
+#[cfg(feature = "linux")]
+mod runtime;
+
+#[cfg(feature = "linux")]
+pub use linux::{adc_voltage, Data, CCS811};
 
 bitflags! {
     struct Status: u8 {
         const FW_START   = 0b1000_0000; // 0: ブートモード、1: アプリケーションモード（読み込み可能）
+        const APP_ERASE  = 0b0100_0000; // ブートモード: 1: APP_ERASEで消去済み
+        const APP_VERIFY = 0b0010_0000; // ブートモード: 1: APP_VERIFYで検証済み
         const APP_VALID  = 0b0001_0000; // 0: ファームウェアのロード失敗、1: 有効
         const DATA_READY = 0b0000_1000; // 0: 新規データ無し、1: 新規データあり
         const ERROR      = 0b0000_0001; // 0: 正常、1: エラー
-        const RESERVED0  = 0b0100_0000;
-        const RESERVED1  = 0b0010_0000;
         const RESERVED2  = 0b0000_0100;
         const RESERVED3  = 0b0000_0010;
     }
 
-    struct Error: u8 {
+    pub struct Error: u8 {
         const HEATER_SUPPLY     = 0b0010_0000;
         const HEATER_FAULT      = 0b0001_0000;
         const MOX_RESISTANCE    = 0b0000_1000;
@@ -42,247 +47,112 @@ bitflags! {
     }
 }
 
-pub(super) struct CCS811 {
-    sig_rx: Receiver<()>,
-    ccs811_pin: OutputPin,
-    air: Air,
-}
-
-struct WakeGuard<'a> {
-    ccs811_pin: &'a mut OutputPin,
-}
-
-impl<'a> WakeGuard<'a> {
-    const HW_ID: u8 = 0x81;
-    const MODE: u8 = 0b00010000; // 1秒ごとに測定。割り込み無し
-
-    const REG_STATUS: u8 = 0;
-    const REG_MEAS_MODE: u8 = 1;
-    const REG_ALG_RESULT_DATA: u8 = 2;
-    const REG_HW_ID: u8 = 0x20;
-    const REG_ERROR_ID: u8 = 0xe0;
-    const REG_APP_START: u8 = 0xf4;
-
-    async fn new(ccs811_pin: &'a mut OutputPin) -> WakeGuard<'a> {
-        ccs811_pin.set_low();
-        task::sleep(Duration::from_micros(100)).await;
-        WakeGuard { ccs811_pin }
-    }
-
-    fn set_mode(&self, bus: &I2c) -> EResult<()> {
-        bus.smbus_write_byte(Self::REG_MEAS_MODE, Self::MODE)?;
-        Ok(())
-    }
-
-    fn get_status(&self, bus: &I2c) -> EResult<Status> {
-        let status = bus.smbus_read_byte(Self::REG_STATUS)?;
-        match Status::from_bits(status) {
-            Some(s) => Ok(s),
-            None => Err(format!("CCS811: error: status = 0b{:0b}", status).into()),
-        }
-    }
-
-    fn get_mode(&self, bus: &I2c) -> EResult<u8> {
-        let mode = bus.smbus_read_byte(Self::REG_MEAS_MODE)?;
-        Ok(mode)
-    }
-
-    fn get_hw_id(&self, bus: &I2c) -> EResult<u8> {
-        let id = bus.smbus_read_byte(Self::REG_HW_ID)?;
-        Ok(id)
-    }
-
-    fn get_error(&self, bus: &I2c) -> EResult<Error> {
-        let err = bus.smbus_read_byte(Self::REG_ERROR_ID)?;
-        Ok(Error::from_bits(err).unwrap())
-    }
-
-    fn get_data(&self, bus: &I2c) -> EResult<Option<(u16, u16)>> {
-        let status = self.get_status(bus)?;
-        if (status & Status::DATA_READY) != Status::DATA_READY {
-            return Ok(None);
-        }
-
-        let mut buf: [u8; 8] = [0; 8];
-        bus.block_read(Self::REG_ALG_RESULT_DATA, &mut buf)?;
-        let co2 = ((buf[0] as u16) << 8) | (buf[1] as u16);
-        let tvoc = ((buf[2] as u16) << 8) | (buf[3] as u16);
-
-        self.print_error(Status::from_bits(buf[4]).unwrap(), bus);
-
-        Ok(Some((co2, tvoc)))
+const HW_ID: u8 = 0x81;
+const INT_DATARDY: u8 = 0b0000_1000; // MEAS_MODE: 新しいデータでnINTをアクティブにする
+const INT_THRESH: u8 = 0b0000_0100; // MEAS_MODE: INT_DATARDYのうち、段階が変わった時のみにする
+
+const REG_STATUS: u8 = 0;
+const REG_MEAS_MODE: u8 = 1;
+const REG_ALG_RESULT_DATA: u8 = 2;
+const REG_ENV_DATA: u8 = 5;
+const REG_THRESHOLDS: u8 = 0x10;
+const REG_BASELINE: u8 = 0x11;
+const REG_HW_ID: u8 = 0x20;
+const REG_HW_VERSION: u8 = 0x21;
+const REG_FW_BOOT_VERSION: u8 = 0x23;
+const REG_FW_APP_VERSION: u8 = 0x24;
+const REG_ERROR_ID: u8 = 0xe0;
+const REG_APP_START: u8 = 0xf4;
+#[cfg(feature = "linux")]
+const REG_SW_RESET: u8 = 0xff;
+
+#[cfg(feature = "linux")]
+const SW_RESET: [u8; 4] = [0x11, 0xe5, 0x72, 0x8a]; // SW_RESETに書き込む値
+
+/// ハードウェアIDの確認
+#[cfg(feature = "linux")]
+fn check_hw_id(hw_id: u8) -> EResult<()> {
+    if hw_id != HW_ID {
+        return Err(format!("CCS811: invalid HW ID {hw_id}").into());
     }
+    Ok(())
+}
 
-    fn print_error(&self, status: Status, bus: &I2c) {
-        if (status & Status::ERROR) != Status::ERROR {
-            return;
-        }
+/// ERROR_IDで報告されたエラー
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SensorError(pub Error);
 
-        if let Ok(e) = self.get_error(bus) {
-            eprintln!("CCS811: error: {:?}", e);
-        }
+impl fmt::Display for SensorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CCS811: error: {:?}", self.0)
     }
+}
 
-    async fn init(&mut self, bus: &Arc<Mutex<I2c>>) -> EResult<()> {
-        {
-            let mut guard = bus.lock().await;
-            if let Err(e) = guard.set_slave_address(ADDR) {
-                perror!(e);
-            }
-
-            println!("CCS811: checking HW ID");
-            let hw_id = self.get_hw_id(&guard)?;
-            if hw_id != Self::HW_ID {
-                return Err(format!("CCS811: invalid HW ID {hw_id}").into());
-            }
-
-            println!("CCS811: checking status");
-            let status = self.get_status(&guard)?;
-            self.print_error(status, &guard);
-            println!("status = {:?}", status);
-
-            if (status & Status::APP_VALID) != Status::APP_VALID {
-                return Err("CCS811: invalid status".into());
-            }
-
-            if (status & Status::FW_START) == Status::FW_START {
-                // 内部アプリケーションは起動済み
-                println!("CCS811: setting mode");
-
-                // 動作モード設定
-                self.set_mode(&guard)?; // 1秒ごとに測定
-                task::sleep(Duration::from_micros(50)).await;
-                return Ok(());
-            }
-        }
-
-        task::sleep(Duration::from_micros(100)).await;
-
-        {
-            // 内部アプリケーションを起動
-            let mut guard = bus.lock().await;
-            if let Err(e) = guard.set_slave_address(ADDR) {
-                perror!(e);
-            }
-
-            println!("CCS811: starting");
-            guard.smbus_send_byte(Self::REG_APP_START)?;
-        }
-
-        task::sleep(Duration::from_micros(100)).await;
-
-        {
-            let mut guard = bus.lock().await;
-            if let Err(e) = guard.set_slave_address(ADDR) {
-                perror!(e);
-            }
-
-            // 内部アプリケーションの起動をチェック
-            println!("CCS811: checking status again");
-            let status = self.get_status(&guard)?;
-            self.print_error(status, &guard);
-            println!("status = {:?}", status);
-
-            if (status & Status::FW_START) != Status::FW_START {
-                // 内部アプリケーションの起動に失敗
-                return Err("CCS811: failed to start FW".into());
-            }
-
-            // 動作モードを設定
-            println!("CCS811: setting mode");
-            self.set_mode(&guard)?; // 1秒ごとに測定
-
-            let mode = self.get_mode(&guard)?;
-            println!("CCS811: mode = 0b{:0b}", mode);
-        }
+impl core::error::Error for SensorError {}
 
-        task::sleep(Duration::from_micros(50)).await;
-        Ok(())
-    }
+/// 測定周期（MEAS_MODEのDRIVE_MODE）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DriveMode {
+    Idle, // 測定しない
+    Secs1,
+    Secs10,
+    Secs60,
+    Millis250, // RAW_DATAのみ更新される
 }
 
-impl<'a> Drop for WakeGuard<'a> {
-    fn drop(&mut self) {
-        self.ccs811_pin.set_high();
-    }
+/// nINTをアクティブ（Low）にする条件
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    Disabled,  // 使わない
+    DataReady, // 新しいデータを読み込めるようになった時
+    Threshold, // 新しいデータのeCO2の段階が、前回アクティブにした時から変わった時
 }
 
-impl CCS811 {
-    pub(super) fn new(sig_rx: Receiver<()>, ccs811_pin: OutputPin, air: Air) -> Self {
-        CCS811 {
-            sig_rx,
-            ccs811_pin,
-            air,
-        }
-    }
-
-    async fn wake_up<'a>(&mut self) -> WakeGuard<'_> {
-        WakeGuard::new(&mut self.ccs811_pin).await
-    }
+/// MEAS_MODEの値
+fn meas_mode(drive: DriveMode, interrupt: Interrupt) -> u8 {
+    let drive = match drive {
+        DriveMode::Idle => 0,
+        DriveMode::Secs1 => 1,
+        DriveMode::Secs10 => 2,
+        DriveMode::Secs60 => 3,
+        DriveMode::Millis250 => 4,
+    };
+    let int = match interrupt {
+        Interrupt::Disabled => 0,
+        Interrupt::DataReady => INT_DATARDY,
+        Interrupt::Threshold => INT_DATARDY | INT_THRESH,
+    };
+    (drive << 4) | int
 }
 
-impl Runner for CCS811 {
-    fn run(mut self, bus: Arc<Mutex<I2c>>) -> EResult<JoinHandle<()>> {
-        let wsec = Duration::from_secs(1);
-
-        let f = async move {
-            {
-                // 初期化
-                let mut wake = self.wake_up().await;
-                if let Err(e) = wake.init(&bus).await {
-                    perror!(e);
-                    return;
-                }
-            }
-
-            // 10分待機
-            if timeout(Duration::from_secs(1), self.sig_rx.recv())
-                .await
-                .is_ok()
-            {
-                println!("exiting CCS811 ...");
-                return;
-            }
-
-            loop {
-                // タイムアウトかシグナルでの終了を待つ
-                if timeout(wsec, self.sig_rx.recv()).await.is_ok() {
-                    println!("exiting CCS811 ...");
-                    break;
-                }
-
-                let co2_val;
-                let tvoc_val;
-                {
-                    let wake = self.wake_up().await;
-                    let mut guard = bus.lock().await;
-                    if let Err(e) = guard.set_slave_address(ADDR) {
-                        perror!(e);
-                    }
-
-                    match wake.get_data(&guard) {
-                        Ok(Some((co2, tvoc))) => {
-                            if co2 < 400 || co2 > 8192 || tvoc > 1187 {
-                                continue;
-                            }
+/// 段階の閾値をTHRESHOLDSの値に変換
+///
+/// 低から中、中から高の順に、それぞれ上位バイトから送信し、最後にヒステリシスを送る。
+fn encode_thresholds(thresholds: &Thresholds) -> [u8; 5] {
+    let [m0, m1] = thresholds.medium.to_be_bytes();
+    let [h0, h1] = thresholds.high.to_be_bytes();
+    [m0, m1, h0, h1, co2::HYSTERESIS as u8]
+}
 
-                            println!("CCS811: CO2 = {co2}, TVOC = {tvoc}");
-                            co2_val = co2;
-                            tvoc_val = tvoc;
-                        }
-                        Ok(None) => continue,
-                        Err(e) => {
-                            perror!(e);
-                            continue;
-                        }
-                    }
-                }
+/// ALG_RESULT_DATAをCO2、TVOC、ステータスに変換
+fn parse_alg_result(buf: &[u8; 8]) -> (u16, u16, Status) {
+    let co2 = ((buf[0] as u16) << 8) | (buf[1] as u16);
+    let tvoc = ((buf[2] as u16) << 8) | (buf[3] as u16);
+    (co2, tvoc, Status::from_bits(buf[4]).unwrap())
+}
 
-                self.air.co2.store(co2_val, Ordering::Relaxed);
-                self.air.tvoc.store(tvoc_val, Ordering::Relaxed);
-            }
-        };
+/// 湿度（%）と温度（度）をENV_DATAの値に変換
+///
+/// どちらも1/512単位の16ビットで、温度は-25度を0とする。上位バイトから送信。
+fn encode_env_data(humidity: f64, celsius: f64) -> [u8; 4] {
+    let fixed = |v: f64| round(v * 512.0).clamp(0.0, u16::MAX as f64) as u16;
+    let [h0, h1] = fixed(humidity).to_be_bytes();
+    let [t0, t1] = fixed(celsius + 25.0).to_be_bytes();
+    [h0, h1, t0, t1]
+}
 
-        Ok(task::spawn(f))
-    }
+/// 四捨五入（`f64::round`はno_stdで使えないため、0.5ずらして切り捨て）
+fn round(v: f64) -> f64 {
+    let v = if v < 0.0 { v - 0.5 } else { v + 0.5 };
+    v as i64 as f64
 }
@@ -0,0 +1,82 @@
+//! CCS811のベースラインの保存
+//!
+//! 16進数のベースラインと、保存した時刻（UNIX時間の秒）を一行で保存する。
+
+use crate::EResult;
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// 保存したベースライン
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Stored {
+    pub(super) value: u16,
+    pub(super) saved: SystemTime,
+}
+
+impl Stored {
+    /// 保存してからの経過時間
+    pub(super) fn age(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.saved).unwrap_or_default()
+    }
+}
+
+/// 読み込む。ファイルが無い場合は`None`
+pub(super) fn load(path: &Path) -> EResult<Option<Stored>> {
+    let s = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut fields = s.split_whitespace();
+    let (Some(value), Some(saved)) = (fields.next(), fields.next()) else {
+        return Err(format!("CCS811: invalid baseline file {}", path.display()).into());
+    };
+
+    Ok(Some(Stored {
+        value: u16::from_str_radix(value, 16)?,
+        saved: UNIX_EPOCH + Duration::from_secs(saved.parse()?),
+    }))
+}
+
+/// 保存
+///
+/// 書き込み途中で終了しても壊れないよう、一時ファイルに書き込んでから置き換える。
+pub(super) fn save(path: &Path, stored: &Stored) -> EResult<()> {
+    let secs = stored.saved.duration_since(UNIX_EPOCH)?.as_secs();
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, format!("{:04x} {}\n", stored.value, secs))?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn save_and_load() {
+        let path = env::temp_dir().join(format!("ccs811_baseline_{}", std::process::id()));
+        assert_eq!(load(&path).unwrap(), None);
+
+        let stored = Stored {
+            value: 0x8a3c,
+            saved: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        };
+        save(&path, &stored).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "8a3c 1700000000\n");
+        assert_eq!(load(&path).unwrap(), Some(stored));
+        assert_eq!(
+            stored.age(stored.saved + Duration::from_secs(60)),
+            Duration::from_secs(60)
+        );
+
+        fs::write(&path, "garbage").unwrap();
+        assert!(load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,628 @@
+//! `embedded-hal`のI2Cとデジタル出力で動作するCCS811
+//!
+//! レジスタ、ハードウェアIDの確認、ALG_RESULT_DATAの変換はLinuxのドライバと共通。std、allocを使わない。
+//! ステータスのERRORビットが立っていた場合は、ERROR_IDを読んで[Error::Sensor]を返す。
+
+use super::{
+    encode_env_data, encode_thresholds, meas_mode, parse_alg_result, DriveMode, Interrupt,
+    SensorError, Status, HW_ID, REG_ALG_RESULT_DATA, REG_APP_START, REG_BASELINE, REG_ENV_DATA,
+    REG_ERROR_ID, REG_FW_APP_VERSION, REG_FW_BOOT_VERSION, REG_HW_ID, REG_HW_VERSION,
+    REG_MEAS_MODE, REG_STATUS, REG_THRESHOLDS,
+};
+use crate::{co2::Thresholds, Ccs811Versions, FwVersion};
+use core::fmt;
+use embedded_hal::{
+    digital::{self, OutputPin},
+    i2c::I2c,
+};
+
+/// エラー
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    Bus(E),                  // I2Cの通信エラー
+    Pin(digital::ErrorKind), // nWAKEの出力ピンのエラー
+    InvalidHwId(u8),         // ハードウェアIDがCCS811ではない
+    InvalidApp,              // 内部アプリケーションが無効（STATUSのAPP_VALIDが0）
+    FwStart,                 // 内部アプリケーションを起動できない
+    Sensor(SensorError),     // ERROR_IDで報告されたエラー
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::Bus(e)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Bus(e) => write!(f, "CCS811: bus error: {e:?}"),
+            Error::Pin(kind) => write!(f, "CCS811: nWAKE error: {kind:?}"),
+            Error::InvalidHwId(id) => write!(f, "CCS811: invalid HW ID {id}"),
+            Error::InvalidApp => f.write_str("CCS811: invalid status"),
+            Error::FwStart => f.write_str("CCS811: failed to start FW"),
+            Error::Sensor(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for Error<E> {}
+
+/// 出力ピンのエラーを変換
+fn pin_error<E, P: digital::Error>(e: P) -> Error<E> {
+    Error::Pin(e.kind())
+}
+
+/// 環境センサCCS811
+pub struct CCS811<I2C, W> {
+    i2c: I2C,
+    wake: W, // nWAKE
+    addr: u8,
+}
+
+impl<I2C, W> CCS811<I2C, W> {
+    /// 生成
+    ///
+    /// `wake`はnWAKEに接続した出力ピンで、通信時のみLowにする。
+    pub fn new(i2c: I2C, wake: W, addr: u8) -> Self {
+        CCS811 { i2c, wake, addr }
+    }
+
+    /// I2CとnWAKEのピンを返して破棄
+    pub fn release(self) -> (I2C, W) {
+        (self.i2c, self.wake)
+    }
+}
+
+/// バージョンのレジスタの値を変換
+fn to_versions(hw: u8, boot: [u8; 2], app: [u8; 2]) -> Ccs811Versions {
+    Ccs811Versions {
+        hw,
+        boot: FwVersion::from_bytes(boot),
+        app: FwVersion::from_bytes(app),
+    }
+}
+
+/// ERROR_IDをエラーに変換
+fn to_error<E>(err: u8) -> Error<E> {
+    Error::Sensor(SensorError(super::Error::from_bits_truncate(err)))
+}
+
+impl<I2C: I2c, W: OutputPin> CCS811<I2C, W> {
+    /// 初期化
+    ///
+    /// ハードウェアIDを確認し、内部アプリケーションを起動して`drive`の周期で測定を開始する。
+    /// nINTは`interrupt`の条件でLowにする。
+    pub fn init<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        drive: DriveMode,
+        interrupt: Interrupt,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.wake_up(delay)?;
+        let result = self.init_awake(meas_mode(drive, interrupt), delay);
+        self.wake.set_high().map_err(pin_error)?;
+        result
+    }
+
+    /// CO2とTVOCを一度読み込む
+    ///
+    /// 新しいデータが無い場合は`None`を返す。
+    pub fn read<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<Option<(u16, u16)>, Error<I2C::Error>> {
+        self.wake_up(delay)?;
+        let result = self.read_awake();
+        self.wake.set_high().map_err(pin_error)?;
+        result
+    }
+
+    /// 補正用の湿度（%）と温度（度）を書き込む
+    pub fn set_env_data<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        humidity: f64,
+        celsius: f64,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        let [h0, h1, t0, t1] = encode_env_data(humidity, celsius);
+        self.wake_up(delay)?;
+        let result = self.write(&[REG_ENV_DATA, h0, h1, t0, t1]);
+        self.wake.set_high().map_err(pin_error)?;
+        result
+    }
+
+    /// eCO2の段階の閾値を書き込む
+    pub fn set_thresholds<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        thresholds: &Thresholds,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        let [m0, m1, h0, h1, hyst] = encode_thresholds(thresholds);
+        self.wake_up(delay)?;
+        let result = self.write(&[REG_THRESHOLDS, m0, m1, h0, h1, hyst]);
+        self.wake.set_high().map_err(pin_error)?;
+        result
+    }
+
+    /// ベースラインを読み込む
+    pub fn baseline<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<u16, Error<I2C::Error>> {
+        self.wake_up(delay)?;
+        let mut buf = [0; 2];
+        let result = self
+            .i2c
+            .write_read(self.addr, &[REG_BASELINE], &mut buf)
+            .map_err(Error::Bus);
+        self.wake.set_high().map_err(pin_error)?;
+        result?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    /// ハードウェアとファームウェアのバージョンを読み込む
+    pub fn versions<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<Ccs811Versions, Error<I2C::Error>> {
+        self.wake_up(delay)?;
+        let mut hw = [0; 1];
+        let mut boot = [0; 2];
+        let mut app = [0; 2];
+        let result = self
+            .i2c
+            .write_read(self.addr, &[REG_HW_VERSION], &mut hw)
+            .and_then(|_| {
+                self.i2c
+                    .write_read(self.addr, &[REG_FW_BOOT_VERSION], &mut boot)
+            })
+            .and_then(|_| {
+                self.i2c
+                    .write_read(self.addr, &[REG_FW_APP_VERSION], &mut app)
+            })
+            .map_err(Error::Bus);
+        self.wake.set_high().map_err(pin_error)?;
+        result?;
+        Ok(to_versions(hw[0], boot, app))
+    }
+
+    /// 保存していたベースラインを書き込む
+    ///
+    /// `init`で測定を開始した後に呼ぶ。
+    pub fn set_baseline<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        baseline: u16,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        let [msb, lsb] = baseline.to_be_bytes();
+        self.wake_up(delay)?;
+        let result = self.write(&[REG_BASELINE, msb, lsb]);
+        self.wake.set_high().map_err(pin_error)?;
+        result
+    }
+
+    fn wake_up<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.wake.set_low().map_err(pin_error)?;
+        delay.delay_us(100);
+        Ok(())
+    }
+
+    fn read_byte(&mut self, reg: u8) -> Result<u8, Error<I2C::Error>> {
+        let mut buf = [0; 1];
+        self.i2c.write_read(self.addr, &[reg], &mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Error<I2C::Error>> {
+        self.i2c.write(self.addr, data)?;
+        Ok(())
+    }
+
+    fn get_status(&mut self) -> Result<Status, Error<I2C::Error>> {
+        let status = Status::from_bits_truncate(self.read_byte(REG_STATUS)?);
+        if status.contains(Status::ERROR) {
+            return Err(to_error(self.read_byte(REG_ERROR_ID)?));
+        }
+        Ok(status)
+    }
+
+    fn init_awake<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        mode: u8,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        let hw_id = self.read_byte(REG_HW_ID)?;
+        if hw_id != HW_ID {
+            return Err(Error::InvalidHwId(hw_id));
+        }
+
+        let status = self.get_status()?;
+        if !status.contains(Status::APP_VALID) {
+            return Err(Error::InvalidApp);
+        }
+
+        if !status.contains(Status::FW_START) {
+            // 内部アプリケーションを起動
+            self.write(&[REG_APP_START])?;
+            delay.delay_us(100);
+
+            if !self.get_status()?.contains(Status::FW_START) {
+                return Err(Error::FwStart);
+            }
+        }
+
+        // 動作モードを設定
+        self.write(&[REG_MEAS_MODE, mode])?;
+        delay.delay_us(50);
+        Ok(())
+    }
+
+    fn read_awake(&mut self) -> Result<Option<(u16, u16)>, Error<I2C::Error>> {
+        if !self.get_status()?.contains(Status::DATA_READY) {
+            return Ok(None);
+        }
+
+        let mut buf = [0; 8];
+        self.i2c
+            .write_read(self.addr, &[REG_ALG_RESULT_DATA], &mut buf)?;
+        let (co2, tvoc, status) = parse_alg_result(&buf);
+        if status.contains(Status::ERROR) {
+            return Err(to_error(buf[5]));
+        }
+
+        Ok(Some((co2, tvoc)))
+    }
+}
+
+impl<I2C: embedded_hal_async::i2c::I2c, W: OutputPin> CCS811<I2C, W> {
+    /// 初期化（非同期）
+    pub async fn init_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        drive: DriveMode,
+        interrupt: Interrupt,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.wake_up_async(delay).await?;
+        let result = self
+            .init_awake_async(meas_mode(drive, interrupt), delay)
+            .await;
+        self.wake.set_high().map_err(pin_error)?;
+        result
+    }
+
+    /// CO2とTVOCを一度読み込む（非同期）
+    pub async fn read_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<Option<(u16, u16)>, Error<I2C::Error>> {
+        self.wake_up_async(delay).await?;
+        let result = self.read_awake_async().await;
+        self.wake.set_high().map_err(pin_error)?;
+        result
+    }
+
+    /// 補正用の湿度（%）と温度（度）を書き込む（非同期）
+    pub async fn set_env_data_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        humidity: f64,
+        celsius: f64,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        let [h0, h1, t0, t1] = encode_env_data(humidity, celsius);
+        self.wake_up_async(delay).await?;
+        let result = self.write_async(&[REG_ENV_DATA, h0, h1, t0, t1]).await;
+        self.wake.set_high().map_err(pin_error)?;
+        result
+    }
+
+    /// eCO2の段階の閾値を書き込む（非同期）
+    pub async fn set_thresholds_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        thresholds: &Thresholds,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        let [m0, m1, h0, h1, hyst] = encode_thresholds(thresholds);
+        self.wake_up_async(delay).await?;
+        let result = self
+            .write_async(&[REG_THRESHOLDS, m0, m1, h0, h1, hyst])
+            .await;
+        self.wake.set_high().map_err(pin_error)?;
+        result
+    }
+
+    /// ベースラインを読み込む（非同期）
+    pub async fn baseline_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<u16, Error<I2C::Error>> {
+        self.wake_up_async(delay).await?;
+        let mut buf = [0; 2];
+        let result = self
+            .i2c
+            .write_read(self.addr, &[REG_BASELINE], &mut buf)
+            .await
+            .map_err(Error::Bus);
+        self.wake.set_high().map_err(pin_error)?;
+        result?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    /// ハードウェアとファームウェアのバージョンを読み込む（非同期）
+    pub async fn versions_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<Ccs811Versions, Error<I2C::Error>> {
+        self.wake_up_async(delay).await?;
+        let mut hw = [0; 1];
+        let mut boot = [0; 2];
+        let mut app = [0; 2];
+        let result = async {
+            self.i2c
+                .write_read(self.addr, &[REG_HW_VERSION], &mut hw)
+                .await?;
+            self.i2c
+                .write_read(self.addr, &[REG_FW_BOOT_VERSION], &mut boot)
+                .await?;
+            self.i2c
+                .write_read(self.addr, &[REG_FW_APP_VERSION], &mut app)
+                .await
+        }
+        .await
+        .map_err(Error::Bus);
+        self.wake.set_high().map_err(pin_error)?;
+        result?;
+        Ok(to_versions(hw[0], boot, app))
+    }
+
+    /// 保存していたベースラインを書き込む（非同期）
+    pub async fn set_baseline_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        baseline: u16,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        let [msb, lsb] = baseline.to_be_bytes();
+        self.wake_up_async(delay).await?;
+        let result = self.write_async(&[REG_BASELINE, msb, lsb]).await;
+        self.wake.set_high().map_err(pin_error)?;
+        result
+    }
+
+    async fn wake_up_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.wake.set_low().map_err(pin_error)?;
+        delay.delay_us(100).await;
+        Ok(())
+    }
+
+    async fn read_byte_async(&mut self, reg: u8) -> Result<u8, Error<I2C::Error>> {
+        let mut buf = [0; 1];
+        self.i2c.write_read(self.addr, &[reg], &mut buf).await?;
+        Ok(buf[0])
+    }
+
+    async fn write_async(&mut self, data: &[u8]) -> Result<(), Error<I2C::Error>> {
+        self.i2c.write(self.addr, data).await?;
+        Ok(())
+    }
+
+    async fn get_status_async(&mut self) -> Result<Status, Error<I2C::Error>> {
+        let status = Status::from_bits_truncate(self.read_byte_async(REG_STATUS).await?);
+        if status.contains(Status::ERROR) {
+            return Err(to_error(self.read_byte_async(REG_ERROR_ID).await?));
+        }
+        Ok(status)
+    }
+
+    async fn init_awake_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        mode: u8,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        let hw_id = self.read_byte_async(REG_HW_ID).await?;
+        if hw_id != HW_ID {
+            return Err(Error::InvalidHwId(hw_id));
+        }
+
+        let status = self.get_status_async().await?;
+        if !status.contains(Status::APP_VALID) {
+            return Err(Error::InvalidApp);
+        }
+
+        if !status.contains(Status::FW_START) {
+            // 内部アプリケーションを起動
+            self.write_async(&[REG_APP_START]).await?;
+            delay.delay_us(100).await;
+
+            if !self.get_status_async().await?.contains(Status::FW_START) {
+                return Err(Error::FwStart);
+            }
+        }
+
+        // 動作モードを設定
+        self.write_async(&[REG_MEAS_MODE, mode]).await?;
+        delay.delay_us(50).await;
+        Ok(())
+    }
+
+    async fn read_awake_async(&mut self) -> Result<Option<(u16, u16)>, Error<I2C::Error>> {
+        if !self.get_status_async().await?.contains(Status::DATA_READY) {
+            return Ok(None);
+        }
+
+        let mut buf = [0; 8];
+        self.i2c
+            .write_read(self.addr, &[REG_ALG_RESULT_DATA], &mut buf)
+            .await?;
+        let (co2, tvoc, status) = parse_alg_result(&buf);
+        if status.contains(Status::ERROR) {
+            return Err(to_error(buf[5]));
+        }
+
+        Ok(Some((co2, tvoc)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        digital::{Mock as PinMock, State, Transaction as PinTransaction},
+        i2c::{Mock, Transaction},
+    };
+
+    const ADDR: u8 = 0x5a;
+
+    fn wake_pin(times: usize) -> PinMock {
+        let t: Vec<PinTransaction> = (0..times)
+            .flat_map(|_| {
+                [
+                    PinTransaction::set(State::Low),
+                    PinTransaction::set(State::High),
+                ]
+            })
+            .collect();
+        PinMock::new(&t)
+    }
+
+    #[test]
+    fn init_and_read() {
+        let i2c = Mock::new(&[
+            // 初期化（ブートモードからアプリケーションを起動）
+            Transaction::write_read(ADDR, vec![REG_HW_ID], vec![0x81]),
+            Transaction::write_read(ADDR, vec![REG_STATUS], vec![0b0001_0000]),
+            Transaction::write(ADDR, vec![REG_APP_START]),
+            Transaction::write_read(ADDR, vec![REG_STATUS], vec![0b1001_0000]),
+            Transaction::write(ADDR, vec![REG_MEAS_MODE, 0b0001_1000]), // 1秒ごと、割り込みあり
+            // 読み込み
+            Transaction::write_read(ADDR, vec![REG_STATUS], vec![0b1001_1000]),
+            Transaction::write_read(
+                ADDR,
+                vec![REG_ALG_RESULT_DATA],
+                vec![0x01, 0x90, 0x00, 0x10, 0b1001_1000, 0, 0, 0],
+            ),
+        ]);
+
+        let mut ccs811 = CCS811::new(i2c, wake_pin(2), ADDR);
+        ccs811
+            .init(DriveMode::Secs1, Interrupt::DataReady, &mut NoopDelay)
+            .unwrap();
+        assert_eq!(ccs811.read(&mut NoopDelay).unwrap(), Some((400, 16)));
+
+        let (mut i2c, mut wake) = ccs811.release();
+        i2c.done();
+        wake.done();
+    }
+
+    #[test]
+    fn invalid_hw_id() {
+        let i2c = Mock::new(&[Transaction::write_read(ADDR, vec![REG_HW_ID], vec![0x00])]);
+
+        let mut ccs811 = CCS811::new(i2c, wake_pin(1), ADDR);
+        assert!(ccs811
+            .init(DriveMode::Secs1, Interrupt::Disabled, &mut NoopDelay)
+            .is_err());
+
+        let (mut i2c, mut wake) = ccs811.release();
+        i2c.done();
+        wake.done(); // エラーでもnWAKEはHighに戻す
+    }
+
+    #[test]
+    fn set_env_data() {
+        let i2c = Mock::new(&[Transaction::write(
+            ADDR,
+            vec![REG_ENV_DATA, 0x61, 0x00, 0x61, 0x00],
+        )]);
+
+        let mut ccs811 = CCS811::new(i2c, wake_pin(1), ADDR);
+        ccs811.set_env_data(48.5, 23.5, &mut NoopDelay).unwrap();
+
+        let (mut i2c, mut wake) = ccs811.release();
+        i2c.done();
+        wake.done();
+    }
+
+    #[test]
+    fn baseline() {
+        let i2c = Mock::new(&[
+            Transaction::write_read(ADDR, vec![REG_BASELINE], vec![0x8a, 0x3c]),
+            Transaction::write(ADDR, vec![REG_BASELINE, 0x8a, 0x3c]),
+        ]);
+
+        let mut ccs811 = CCS811::new(i2c, wake_pin(2), ADDR);
+        let baseline = ccs811.baseline(&mut NoopDelay).unwrap();
+        assert_eq!(baseline, 0x8a3c);
+        ccs811.set_baseline(baseline, &mut NoopDelay).unwrap();
+
+        let (mut i2c, mut wake) = ccs811.release();
+        i2c.done();
+        wake.done();
+    }
+
+    #[test]
+    fn versions() {
+        let i2c = Mock::new(&[
+            Transaction::write_read(ADDR, vec![REG_HW_VERSION], vec![0x12]),
+            Transaction::write_read(ADDR, vec![REG_FW_BOOT_VERSION], vec![0x10, 0x00]),
+            Transaction::write_read(ADDR, vec![REG_FW_APP_VERSION], vec![0x20, 0x01]),
+        ]);
+
+        let mut ccs811 = CCS811::new(i2c, wake_pin(1), ADDR);
+        let versions = ccs811.versions(&mut NoopDelay).unwrap();
+        assert_eq!(versions.to_string(), "HW 0x12, boot 1.0.0, app 2.0.1");
+
+        let (mut i2c, mut wake) = ccs811.release();
+        i2c.done();
+        wake.done();
+    }
+
+    #[test]
+    fn set_thresholds() {
+        let i2c = Mock::new(&[Transaction::write(
+            ADDR,
+            vec![REG_THRESHOLDS, 0x03, 0xe8, 0x07, 0xd0, 50],
+        )]);
+
+        let thresholds = Thresholds {
+            medium: 1000,
+            high: 2000,
+        };
+        let mut ccs811 = CCS811::new(i2c, wake_pin(1), ADDR);
+        ccs811.set_thresholds(&thresholds, &mut NoopDelay).unwrap();
+
+        let (mut i2c, mut wake) = ccs811.release();
+        i2c.done();
+        wake.done();
+    }
+
+    #[async_std::test]
+    async fn read_async() {
+        let i2c = Mock::new(&[
+            Transaction::write_read(ADDR, vec![REG_STATUS], vec![0b1001_0000]),
+            Transaction::write_read(ADDR, vec![REG_STATUS], vec![0b1001_1000]),
+            Transaction::write_read(
+                ADDR,
+                vec![REG_ALG_RESULT_DATA],
+                vec![0x01, 0x90, 0x00, 0x10, 0b1001_1000, 0, 0, 0],
+            ),
+        ]);
+
+        let mut ccs811 = CCS811::new(i2c, wake_pin(2), ADDR);
+        assert_eq!(ccs811.read_async(&mut NoopDelay).await.unwrap(), None); // DATA_READYではない
+        assert_eq!(
+            ccs811.read_async(&mut NoopDelay).await.unwrap(),
+            Some((400, 16))
+        );
+
+        let (mut i2c, mut wake) = ccs811.release();
+        i2c.done();
+        wake.done();
+    }
+}
@@ -0,0 +1,388 @@
+//! CCS811のファームウェアの更新
+//!
+//! ブートローダで内部アプリケーションを消去し、イメージを8バイトずつ書き込んで検証する。
+//! 更新中はnWAKEをLowにしておき、他のタスクがバスを使わないようにする。
+//! 進捗は[Step]として`progress`に渡し、表示は呼び出し側で行う。
+//! [DryRun]を渡すと、センサ無しで手順を確認できる。
+
+use super::{
+    check_hw_id, Error, Status, REG_ERROR_ID, REG_HW_ID, REG_STATUS, REG_SW_RESET, SW_RESET,
+};
+use crate::{hal::I2cBus, EResult};
+use async_std::task;
+use std::{fs, path::Path, time::Duration};
+
+const REG_APP_ERASE: u8 = 0xf1;
+const REG_APP_DATA: u8 = 0xf2;
+const REG_APP_VERIFY: u8 = 0xf3;
+
+const APP_ERASE: [u8; 4] = [0xe7, 0xa7, 0xe6, 0x09];
+
+/// 一度に書き込むバイト数
+pub const CHUNK_SIZE: usize = 8;
+
+/// 各手順の後の待ち時間
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Delays {
+    pub reset: Duration,  // SW_RESETからブートローダの起動まで
+    pub erase: Duration,  // APP_ERASE
+    pub data: Duration,   // APP_DATAの8バイトごと
+    pub verify: Duration, // APP_VERIFY
+}
+
+impl Default for Delays {
+    /// アプリケーションノートの値
+    fn default() -> Self {
+        Delays {
+            reset: Duration::from_millis(2),
+            erase: Duration::from_millis(500),
+            data: Duration::from_millis(50),
+            verify: Duration::from_millis(500),
+        }
+    }
+}
+
+impl Delays {
+    /// 待たない（[DryRun]、テスト用）
+    pub const NONE: Delays = Delays {
+        reset: Duration::ZERO,
+        erase: Duration::ZERO,
+        data: Duration::ZERO,
+        verify: Duration::ZERO,
+    };
+}
+
+/// 更新の手順（`progress`に渡す）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Reset,               // SW_RESETでブートモードに戻す
+    Erase,               // 内部アプリケーションの消去
+    Write(usize, usize), // 書き込んだバイト数と全体のバイト数
+    Verify,              // 検証
+    Done,                // 更新の完了
+}
+
+/// ファームウェアのイメージを読み込む
+///
+/// 8バイト単位で書き込むため、サイズが8の倍数でない場合はエラー。
+pub fn load(path: &Path) -> EResult<Vec<u8>> {
+    let image = fs::read(path)?;
+    if image.is_empty() || !image.len().is_multiple_of(CHUNK_SIZE) {
+        return Err(format!(
+            "CCS811: firmware {}: size {} is not a multiple of {CHUNK_SIZE}",
+            path.display(),
+            image.len()
+        )
+        .into());
+    }
+    Ok(image)
+}
+
+/// ステータスを読み込み、ERRORビットが立っていればERROR_IDをエラーにする
+fn get_status<B: I2cBus>(bus: &mut B) -> EResult<Status> {
+    let status = bus.smbus_read_byte(REG_STATUS)?;
+    let status = Status::from_bits(status).unwrap();
+    if status.contains(Status::ERROR) {
+        let err = Error::from_bits(bus.smbus_read_byte(REG_ERROR_ID)?).unwrap();
+        return Err(format!("CCS811: error: {err:?}").into());
+    }
+    Ok(status)
+}
+
+/// ファームウェアを更新
+///
+/// 内部アプリケーションが起動している場合はSW_RESETでブートモードに戻す。
+/// `progress`には各手順の開始と、書き込んだバイト数を渡す。
+/// 更新後は電源の再投入か、通常の初期化（APP_START）で新しいアプリケーションを起動する。
+pub async fn update<B: I2cBus>(
+    bus: &mut B,
+    addr: u16,
+    image: &[u8],
+    delays: &Delays,
+    mut progress: impl FnMut(Step),
+) -> EResult<()> {
+    if image.is_empty() || !image.len().is_multiple_of(CHUNK_SIZE) {
+        return Err("CCS811: invalid firmware size".into());
+    }
+
+    bus.set_slave_address(addr)?;
+    check_hw_id(bus.smbus_read_byte(REG_HW_ID)?)?;
+
+    // ブートモード
+    if get_status(bus)?.contains(Status::FW_START) {
+        progress(Step::Reset);
+        bus.write(&[&[REG_SW_RESET][..], &SW_RESET].concat())?;
+        task::sleep(delays.reset).await;
+        if get_status(bus)?.contains(Status::FW_START) {
+            return Err("CCS811: failed to enter boot mode".into());
+        }
+    }
+
+    // 消去
+    progress(Step::Erase);
+    bus.write(&[&[REG_APP_ERASE][..], &APP_ERASE].concat())?;
+    task::sleep(delays.erase).await;
+    if !get_status(bus)?.contains(Status::APP_ERASE) {
+        return Err("CCS811: failed to erase application".into());
+    }
+
+    // 書き込み
+    progress(Step::Write(0, image.len()));
+    for (i, chunk) in image.chunks(CHUNK_SIZE).enumerate() {
+        bus.write(&[&[REG_APP_DATA][..], chunk].concat())?;
+        task::sleep(delays.data).await;
+        progress(Step::Write((i + 1) * CHUNK_SIZE, image.len()));
+    }
+    get_status(bus)?;
+
+    // 検証
+    progress(Step::Verify);
+    bus.smbus_send_byte(REG_APP_VERIFY)?;
+    task::sleep(delays.verify).await;
+    let status = get_status(bus)?;
+    if !status.contains(Status::APP_VERIFY | Status::APP_VALID) {
+        return Err(format!("CCS811: verification failed: status = {status:?}").into());
+    }
+
+    progress(Step::Done);
+    Ok(())
+}
+
+/// 各手順と、書き込みの10%ごとの進捗を表示する`progress`
+pub fn print_progress() -> impl FnMut(Step) {
+    let mut printed = 0;
+    move |step| match step {
+        Step::Reset => println!("CCS811: resetting to boot mode"),
+        Step::Erase => println!("CCS811: erasing application"),
+        Step::Write(0, total) => println!("CCS811: writing {total} bytes"),
+        Step::Write(written, total) => {
+            let percent = written * 100 / total;
+            if percent / 10 > printed / 10 || written == total {
+                println!("CCS811: {percent:3} % ({written}/{total} bytes)");
+                printed = percent;
+            }
+        }
+        Step::Verify => println!("CCS811: verifying application"),
+        Step::Done => println!("CCS811: firmware updated"),
+    }
+}
+
+/// 更新手順の確認用のバス
+///
+/// ブートローダとして応答し、手順が正しければ検証に成功する。
+/// 手順の誤りは書き込みのエラーとして返す。
+/// 内部アプリケーションが起動した状態から始める。
+pub struct DryRun {
+    addr: u16,
+    app_running: bool,
+    erased: bool,
+    written: usize, // 消去後に書き込んだバイト数
+    verified: bool,
+}
+
+impl DryRun {
+    pub fn new() -> Self {
+        DryRun {
+            addr: 0,
+            app_running: true,
+            erased: false,
+            written: 0,
+            verified: false,
+        }
+    }
+
+    /// 検証に成功した時に書き込まれていたバイト数
+    pub fn written(&self) -> Option<usize> {
+        self.verified.then_some(self.written)
+    }
+
+    fn status(&self) -> Status {
+        let mut status = Status::APP_VALID;
+        status.set(Status::FW_START, self.app_running);
+        status.set(Status::APP_ERASE, self.erased);
+        status.set(Status::APP_VERIFY, self.verified);
+        status.set(Status::APP_VALID, !self.erased || self.verified);
+        status
+    }
+
+    /// ブートモードでない場合の書き込みはエラー
+    fn boot_command(&self, name: &str) -> EResult<()> {
+        if self.app_running {
+            return Err(format!("dry-run: {name} in application mode").into());
+        }
+        Ok(())
+    }
+}
+
+impl Default for DryRun {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl I2cBus for DryRun {
+    fn set_slave_address(&mut self, addr: u16) -> EResult<()> {
+        self.addr = addr;
+        Ok(())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> EResult<()> {
+        match buf.split_first() {
+            Some((&REG_SW_RESET, data)) if data == SW_RESET => {
+                println!("dry-run: 0x{:02x} SW_RESET", self.addr);
+                self.app_running = false;
+            }
+            Some((&REG_APP_ERASE, data)) if data == APP_ERASE => {
+                self.boot_command("APP_ERASE")?;
+                println!("dry-run: 0x{:02x} APP_ERASE", self.addr);
+                self.erased = true;
+                self.written = 0;
+                self.verified = false;
+            }
+            Some((&REG_APP_DATA, data)) if data.len() == CHUNK_SIZE => {
+                self.boot_command("APP_DATA")?;
+                if !self.erased {
+                    return Err("dry-run: APP_DATA before APP_ERASE".into());
+                }
+                self.written += CHUNK_SIZE;
+            }
+            _ => return Err(format!("dry-run: unexpected write {buf:02x?}").into()),
+        }
+        Ok(())
+    }
+
+    fn smbus_send_byte(&mut self, value: u8) -> EResult<()> {
+        if value != REG_APP_VERIFY {
+            return Err(format!("dry-run: unexpected command 0x{value:02x}").into());
+        }
+        self.boot_command("APP_VERIFY")?;
+        println!(
+            "dry-run: 0x{:02x} APP_VERIFY ({} bytes)",
+            self.addr, self.written
+        );
+        self.verified = self.erased && self.written > 0;
+        Ok(())
+    }
+
+    fn smbus_read_byte(&mut self, reg: u8) -> EResult<u8> {
+        match reg {
+            REG_STATUS => Ok(self.status().bits()),
+            REG_HW_ID => Ok(super::HW_ID),
+            REG_ERROR_ID => Ok(0), // 手順の誤りは書き込みのエラーとして返す
+            _ => Err(format!("dry-run: CCS811 register 0x{reg:02x}").into()),
+        }
+    }
+
+    fn smbus_write_byte(&mut self, reg: u8, value: u8) -> EResult<()> {
+        self.write(&[reg, value])
+    }
+
+    fn smbus_read_word(&mut self, reg: u8) -> EResult<u16> {
+        Ok(self.smbus_read_byte(reg)? as u16)
+    }
+
+    fn smbus_block_write(&mut self, reg: u8, buf: &[u8]) -> EResult<()> {
+        self.write(&[&[reg][..], buf].concat())
+    }
+
+    fn block_read(&mut self, reg: u8, buf: &mut [u8]) -> EResult<()> {
+        let value = self.smbus_read_byte(reg)?;
+        buf.iter_mut().for_each(|b| *b = 0);
+        if let Some(b) = buf.first_mut() {
+            *b = value;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::fake::FakeI2c;
+
+    const ADDR: u16 = 0x5a;
+
+    #[async_std::test]
+    async fn update_sequence() {
+        let mut bus = FakeI2c::new();
+        bus.set_reg(ADDR, REG_HW_ID, &[0x81]);
+        bus.push_read(ADDR, REG_STATUS, &[0b1001_0000]); // アプリケーションモード
+        bus.push_read(ADDR, REG_STATUS, &[0b0001_0000]); // ブートモード
+        bus.push_read(ADDR, REG_STATUS, &[0b0100_0000]); // 消去済み
+        bus.push_read(ADDR, REG_STATUS, &[0b0100_0000]);
+        bus.push_read(ADDR, REG_STATUS, &[0b0111_0000]); // 検証済み
+
+        let image: Vec<u8> = (0..16).collect();
+        let mut steps = Vec::new();
+        update(&mut bus, ADDR, &image, &Delays::NONE, |s| steps.push(s))
+            .await
+            .unwrap();
+
+        let data: Vec<(Option<u8>, Vec<u8>)> =
+            bus.writes().into_iter().map(|w| (w.reg, w.data)).collect();
+        assert_eq!(
+            data,
+            vec![
+                (Some(REG_SW_RESET), SW_RESET.to_vec()),
+                (Some(REG_APP_ERASE), APP_ERASE.to_vec()),
+                (Some(REG_APP_DATA), (0..8).collect()),
+                (Some(REG_APP_DATA), (8..16).collect()),
+                (None, vec![REG_APP_VERIFY]),
+            ]
+        );
+        assert_eq!(
+            steps,
+            vec![
+                Step::Reset,
+                Step::Erase,
+                Step::Write(0, 16),
+                Step::Write(8, 16),
+                Step::Write(16, 16),
+                Step::Verify,
+                Step::Done,
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn verification_failed() {
+        let mut bus = FakeI2c::new();
+        bus.set_reg(ADDR, REG_HW_ID, &[0x81]);
+        bus.push_read(ADDR, REG_STATUS, &[0b0001_0000]); // ブートモード
+        bus.push_read(ADDR, REG_STATUS, &[0b0100_0000]);
+        bus.push_read(ADDR, REG_STATUS, &[0b0100_0000]);
+        bus.push_read(ADDR, REG_STATUS, &[0b0100_0000]); // 検証されない
+
+        let result = update(&mut bus, ADDR, &[0; 8], &Delays::NONE, |_| ()).await;
+        assert!(result.is_err());
+        assert!(update(&mut bus, ADDR, &[0; 7], &Delays::NONE, |_| ())
+            .await
+            .is_err()); // 8の倍数でない
+    }
+
+    #[async_std::test]
+    async fn dry_run() {
+        let mut bus = DryRun::new();
+        let image = vec![0xff; 64];
+        update(&mut bus, ADDR, &image, &Delays::NONE, print_progress())
+            .await
+            .unwrap();
+        assert_eq!(bus.written(), Some(64));
+    }
+
+    #[test]
+    fn dry_run_errors() {
+        let mut bus = DryRun::new();
+        assert!(bus
+            .write(&[&[REG_APP_ERASE][..], &APP_ERASE].concat())
+            .is_err()); // アプリケーションモード
+        bus.write(&[&[REG_SW_RESET][..], &SW_RESET].concat())
+            .unwrap();
+        assert!(bus
+            .write(&[&[REG_APP_DATA][..], &[0; CHUNK_SIZE]].concat())
+            .is_err()); // 消去前
+        assert!(bus.write(&[REG_APP_DATA, 0]).is_err()); // 8バイトでない
+        assert!(bus.smbus_send_byte(0x00).is_err());
+        assert_eq!(bus.written(), None);
+    }
+}
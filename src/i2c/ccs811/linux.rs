@@ -0,0 +1,1519 @@
+//! Linuxの[I2cBus]と[OutputPin]で動作するCCS811
+
+#[allow(unused_imports)]
+use async_std::prelude::*;
+
+use super::{
+    baseline, check_hw_id, encode_env_data, encode_thresholds, meas_mode, parse_alg_result,
+    runtime, DriveMode, Error, Interrupt, SensorError, Status, REG_ALG_RESULT_DATA, REG_APP_START,
+    REG_BASELINE, REG_ENV_DATA, REG_ERROR_ID, REG_FW_APP_VERSION, REG_FW_BOOT_VERSION, REG_HW_ID,
+    REG_HW_VERSION, REG_MEAS_MODE, REG_STATUS, REG_SW_RESET, REG_THRESHOLDS, SW_RESET,
+};
+use crate::{
+    co2::{Bands, Thresholds},
+    config::{self, SharedConfig},
+    hal::{I2cBus, OutputPin},
+    i2c::Runner,
+    perror,
+    shutdown::Token,
+    Air, Ccs811State, Ccs811Versions, EResult, FwVersion,
+};
+use async_std::{
+    channel::Receiver,
+    future::timeout,
+    sync::{Mutex, MutexGuard},
+    task::{self, JoinHandle},
+};
+use futures::{pin_mut, select, FutureExt};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant, SystemTime},
+};
+
+const REG_RAW_DATA: u8 = 3;
+
+const RESET_DELAY: Duration = Duration::from_millis(2); // リセットからブートローダの起動まで
+const THRESHOLD_FALLBACK: Duration = Duration::from_secs(10 * 60); // 閾値の割り込みが無くても読み込む間隔
+
+impl From<config::DriveMode> for DriveMode {
+    fn from(drive: config::DriveMode) -> Self {
+        match drive {
+            config::DriveMode::Idle => DriveMode::Idle,
+            config::DriveMode::Secs1 => DriveMode::Secs1,
+            config::DriveMode::Secs10 => DriveMode::Secs10,
+            config::DriveMode::Secs60 => DriveMode::Secs60,
+            config::DriveMode::Millis250 => DriveMode::Millis250,
+        }
+    }
+}
+
+/// RAW_DATAを電流（μA）とADCの値（1.65 V / 1023単位）に変換
+fn parse_raw_data(buf: &[u8; 2]) -> (u8, u16) {
+    let current = buf[0] >> 2;
+    let adc = (((buf[0] & 0b11) as u16) << 8) | (buf[1] as u16);
+    (current, adc)
+}
+
+/// ADCの値を電圧（V）に変換
+pub fn adc_voltage(adc: u16) -> f64 {
+    adc as f64 * 1.65 / 1023.0
+}
+
+/// 読み込んだデータ
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Data {
+    pub co2: u16,    // eCO2（ppm）
+    pub tvoc: u16,   // TVOC（ppb）
+    pub current: u8, // センサの電流（μA）
+    pub adc: u16,    // センサの電圧（ADCの値）
+}
+
+impl Data {
+    /// ALG_RESULT_DATAから変換
+    ///
+    /// 7、8バイト目はRAW_DATAと同じ。
+    fn parse(buf: &[u8; 8]) -> (Self, Status) {
+        let (co2, tvoc, status) = parse_alg_result(buf);
+        let (current, adc) = parse_raw_data(&[buf[6], buf[7]]);
+        let data = Data {
+            co2,
+            tvoc,
+            current,
+            adc,
+        };
+        (data, status)
+    }
+}
+
+/// エラーへの対処
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Recovery {
+    Retry, // 次の読み込みで回復する
+    Init,  // 初期化し直してMEAS_MODEを設定する
+    Reset, // リセットしてから初期化し直す
+}
+
+impl Error {
+    /// 立っているビットのうち、最も重い対処
+    ///
+    /// ヒーターとセンサの異常はリセット、MEAS_MODEの異常は初期化、
+    /// 不正なI2Cの書き込みや読み込みは一時的なものとしてやり直す。
+    fn recovery(self) -> Recovery {
+        if self.intersects(Error::HEATER_SUPPLY | Error::HEATER_FAULT | Error::MOX_RESISTANCE) {
+            Recovery::Reset
+        } else if self.contains(Error::MEAS_MODE_INVALID) {
+            Recovery::Init
+        } else {
+            Recovery::Retry
+        }
+    }
+}
+
+/// 累積の稼働時間`runtime`と起動からの経過時間`uptime`による状態
+///
+/// データシートでは、新品は48時間のバーンイン、起動ごとに20分のウォームアップが必要。
+fn sensor_state(runtime: Duration, uptime: Duration, conf: &config::CCS811) -> Ccs811State {
+    if runtime < Duration::from_secs(conf.burn_in_h * 60 * 60) {
+        Ccs811State::BurnIn
+    } else if uptime < Duration::from_secs(conf.warmup_min * 60) {
+        Ccs811State::WarmingUp
+    } else {
+        Ccs811State::Ready
+    }
+}
+
+/// 範囲外のサンプルを棄却する理由
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Rejection {
+    Co2Low,  // `co2_min`未満
+    Co2High, // `co2_max`より大きい
+    Tvoc,    // `tvoc_max`より大きい
+}
+
+impl Rejection {
+    /// 設定の範囲外であれば棄却する理由
+    fn check(co2: u16, tvoc: u16, conf: &config::CCS811) -> Option<Rejection> {
+        if co2 < conf.co2_min {
+            Some(Rejection::Co2Low)
+        } else if co2 > conf.co2_max {
+            Some(Rejection::Co2High)
+        } else if tvoc > conf.tvoc_max {
+            Some(Rejection::Tvoc)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Rejection::Co2Low => "co2 < co2_min",
+            Rejection::Co2High => "co2 > co2_max",
+            Rejection::Tvoc => "tvoc > tvoc_max",
+        };
+        f.write_str(s)
+    }
+}
+
+/// 直近のサンプルを棄却したかの記録
+#[derive(Default)]
+struct Rejections {
+    recent: VecDeque<bool>,
+}
+
+impl Rejections {
+    /// 記録し、`window`個より古いものは捨てる
+    fn push(&mut self, rejected: bool, window: usize) {
+        self.recent.push_back(rejected);
+        while self.recent.len() > window {
+            self.recent.pop_front();
+        }
+    }
+
+    /// 直近`window`個のサンプルが揃い、棄却の割合が`max_rate`を超えているか
+    fn suspect(&self, window: usize, max_rate: f64) -> bool {
+        if self.recent.len() < window {
+            return false;
+        }
+        let rejected = self.recent.iter().filter(|r| **r).count();
+        rejected as f64 / self.recent.len() as f64 > max_rate
+    }
+}
+
+/// 環境センサCCS811
+pub struct CCS811<P> {
+    shutdown: Token,
+    config: SharedConfig,
+    ccs811_pin: Arc<Mutex<P>>,
+    reset_pin: Option<Arc<Mutex<P>>>, // nRESET。未接続の場合は`None`
+    data_ready: Option<Receiver<()>>, // nINTがアクティブになった通知
+    air: Air,
+    bands: Bands, // eCO2の段階
+}
+
+struct WakeGuard<'a, P: OutputPin> {
+    ccs811_pin: MutexGuard<'a, P>,
+    addr: u16,
+}
+
+impl<'a, P: OutputPin> WakeGuard<'a, P> {
+    async fn new(mut ccs811_pin: MutexGuard<'a, P>, addr: u16) -> WakeGuard<'a, P> {
+        ccs811_pin.set_low();
+        task::sleep(Duration::from_micros(100)).await;
+        WakeGuard { ccs811_pin, addr }
+    }
+
+    fn set_mode<B: I2cBus>(&self, bus: &mut B, mode: u8) -> EResult<()> {
+        bus.smbus_write_byte(REG_MEAS_MODE, mode)?;
+        Ok(())
+    }
+
+    fn get_status<B: I2cBus>(&self, bus: &mut B) -> EResult<Status> {
+        let status = bus.smbus_read_byte(REG_STATUS)?;
+        match Status::from_bits(status) {
+            Some(s) => Ok(s),
+            None => Err(format!("CCS811: error: status = 0b{:0b}", status).into()),
+        }
+    }
+
+    fn set_env_data<B: I2cBus>(&self, bus: &mut B, humidity: f64, celsius: f64) -> EResult<()> {
+        let [h0, h1, t0, t1] = encode_env_data(humidity, celsius);
+        bus.write(&[REG_ENV_DATA, h0, h1, t0, t1])?;
+        Ok(())
+    }
+
+    fn get_baseline<B: I2cBus>(&self, bus: &mut B) -> EResult<u16> {
+        let mut buf = [0; 2];
+        bus.block_read(REG_BASELINE, &mut buf)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    fn set_thresholds<B: I2cBus>(&self, bus: &mut B, thresholds: &Thresholds) -> EResult<()> {
+        let [m0, m1, h0, h1, hyst] = encode_thresholds(thresholds);
+        bus.write(&[REG_THRESHOLDS, m0, m1, h0, h1, hyst])?;
+        Ok(())
+    }
+
+    fn set_baseline<B: I2cBus>(&self, bus: &mut B, baseline: u16) -> EResult<()> {
+        let [msb, lsb] = baseline.to_be_bytes();
+        bus.write(&[REG_BASELINE, msb, lsb])?;
+        Ok(())
+    }
+
+    /// 保存していたベースラインを書き戻す
+    fn restore_baseline<B: I2cBus>(&self, bus: &mut B, baseline: Option<u16>) -> EResult<()> {
+        if let Some(baseline) = baseline {
+            println!("CCS811: restoring baseline 0x{baseline:04x}");
+            self.set_baseline(bus, baseline)?;
+        }
+        Ok(())
+    }
+
+    fn get_mode<B: I2cBus>(&self, bus: &mut B) -> EResult<u8> {
+        let mode = bus.smbus_read_byte(REG_MEAS_MODE)?;
+        Ok(mode)
+    }
+
+    fn get_hw_id<B: I2cBus>(&self, bus: &mut B) -> EResult<u8> {
+        let id = bus.smbus_read_byte(REG_HW_ID)?;
+        Ok(id)
+    }
+
+    fn get_versions<B: I2cBus>(&self, bus: &mut B) -> EResult<Ccs811Versions> {
+        let hw = bus.smbus_read_byte(REG_HW_VERSION)?;
+        let mut boot = [0; 2];
+        bus.block_read(REG_FW_BOOT_VERSION, &mut boot)?;
+        let mut app = [0; 2];
+        bus.block_read(REG_FW_APP_VERSION, &mut app)?;
+        Ok(Ccs811Versions {
+            hw,
+            boot: FwVersion::from_bytes(boot),
+            app: FwVersion::from_bytes(app),
+        })
+    }
+
+    fn get_error<B: I2cBus>(&self, bus: &mut B) -> EResult<Error> {
+        let err = bus.smbus_read_byte(REG_ERROR_ID)?;
+        Ok(Error::from_bits(err).unwrap())
+    }
+
+    /// 新しいデータがあるか。ステータスのERRORビットが立っていればエラーを返す
+    fn data_ready<B: I2cBus>(&self, bus: &mut B) -> EResult<bool> {
+        let status = self.get_status(bus)?;
+        if status.contains(Status::ERROR) {
+            return Err(SensorError(self.get_error(bus)?).into());
+        }
+        Ok((status & Status::DATA_READY) == Status::DATA_READY)
+    }
+
+    fn get_data<B: I2cBus>(&self, bus: &mut B) -> EResult<Option<Data>> {
+        if !self.data_ready(bus)? {
+            return Ok(None);
+        }
+
+        let mut buf: [u8; 8] = [0; 8];
+        bus.block_read(REG_ALG_RESULT_DATA, &mut buf)?;
+        let (data, status) = Data::parse(&buf);
+        if status.contains(Status::ERROR) {
+            // 6バイト目はERROR_ID
+            return Err(SensorError(Error::from_bits(buf[5]).unwrap()).into());
+        }
+
+        Ok(Some(data))
+    }
+
+    fn get_raw_data<B: I2cBus>(&self, bus: &mut B) -> EResult<Option<(u8, u16)>> {
+        if !self.data_ready(bus)? {
+            return Ok(None);
+        }
+
+        let mut buf = [0; 2];
+        bus.block_read(REG_RAW_DATA, &mut buf)?;
+        Ok(Some(parse_raw_data(&buf)))
+    }
+
+    fn sw_reset<B: I2cBus>(&self, bus: &mut B) -> EResult<()> {
+        bus.write(&[&[REG_SW_RESET][..], &SW_RESET].concat())?;
+        Ok(())
+    }
+
+    fn print_error<B: I2cBus>(&self, status: Status, bus: &mut B) {
+        if (status & Status::ERROR) != Status::ERROR {
+            return;
+        }
+
+        if let Ok(e) = self.get_error(bus) {
+            eprintln!("CCS811: error: {:?}", e);
+        }
+    }
+
+    /// 初期化
+    ///
+    /// `mode`で測定を開始した後、`baseline`があれば書き戻す。
+    /// バージョンはブートモードでも読み込めるため、起動前に読み込んで返す。
+    async fn init<B: I2cBus>(
+        &mut self,
+        bus: &Arc<Mutex<B>>,
+        mode: u8,
+        baseline: Option<u16>,
+    ) -> EResult<Ccs811Versions> {
+        let versions;
+        {
+            let mut guard = bus.lock().await;
+            if let Err(e) = guard.set_slave_address(self.addr) {
+                perror!(e);
+            }
+
+            println!("CCS811: checking HW ID");
+            check_hw_id(self.get_hw_id(&mut *guard)?)?;
+
+            versions = self.get_versions(&mut *guard)?;
+            println!(
+                "CCS811: HW_VERSION 0x{:02x}, FW_BOOT_VERSION {}, FW_APP_VERSION {}",
+                versions.hw, versions.boot, versions.app
+            );
+
+            println!("CCS811: checking status");
+            let status = self.get_status(&mut *guard)?;
+            self.print_error(status, &mut *guard);
+            println!("status = {:?}", status);
+
+            if (status & Status::APP_VALID) != Status::APP_VALID {
+                return Err("CCS811: invalid status (no valid application firmware)".into());
+            }
+
+            if (status & Status::FW_START) == Status::FW_START {
+                // 内部アプリケーションは起動済み
+                println!("CCS811: setting mode");
+
+                // 動作モード設定
+                self.set_mode(&mut *guard, mode)?;
+                self.restore_baseline(&mut *guard, baseline)?;
+                task::sleep(Duration::from_micros(50)).await;
+                return Ok(versions);
+            }
+        }
+
+        task::sleep(Duration::from_micros(100)).await;
+
+        {
+            // 内部アプリケーションを起動
+            let mut guard = bus.lock().await;
+            if let Err(e) = guard.set_slave_address(self.addr) {
+                perror!(e);
+            }
+
+            println!("CCS811: starting");
+            guard.smbus_send_byte(REG_APP_START)?;
+        }
+
+        task::sleep(Duration::from_micros(100)).await;
+
+        {
+            let mut guard = bus.lock().await;
+            if let Err(e) = guard.set_slave_address(self.addr) {
+                perror!(e);
+            }
+
+            // 内部アプリケーションの起動をチェック
+            println!("CCS811: checking status again");
+            let status = self.get_status(&mut *guard)?;
+            self.print_error(status, &mut *guard);
+            println!("status = {:?}", status);
+
+            if (status & Status::FW_START) != Status::FW_START {
+                // 内部アプリケーションの起動に失敗
+                return Err("CCS811: failed to start FW".into());
+            }
+
+            // 動作モードを設定
+            println!("CCS811: setting mode");
+            self.set_mode(&mut *guard, mode)?;
+
+            let mode = self.get_mode(&mut *guard)?;
+            println!("CCS811: mode = 0b{:0b}", mode);
+
+            self.restore_baseline(&mut *guard, baseline)?;
+        }
+
+        task::sleep(Duration::from_micros(50)).await;
+        Ok(versions)
+    }
+}
+
+impl<'a, P: OutputPin> Drop for WakeGuard<'a, P> {
+    fn drop(&mut self) {
+        self.ccs811_pin.set_high();
+    }
+}
+
+impl<P: OutputPin> CCS811<P> {
+    /// 生成
+    ///
+    /// `ccs811_pin`はnWAKEに接続した出力ピンで、通信時のみLowにする。
+    /// `reset_pin`はnRESETに接続した出力ピンで、`None`の場合はSW_RESETでリセットする。
+    /// `data_ready`はnINTを監視するGPIOのタスクからの通知で、
+    /// `None`の場合は`interval_ms`ごとにステータスを確認する。
+    /// 読み込んだeCO2の段階が変わると`bands`に配信する。
+    /// 閾値の割り込みの場合は、nINTの通知を受けた時だけ読み込んで配信する。
+    pub fn new(
+        shutdown: Token,
+        config: SharedConfig,
+        ccs811_pin: Arc<Mutex<P>>,
+        reset_pin: Option<Arc<Mutex<P>>>,
+        data_ready: Option<Receiver<()>>,
+        air: Air,
+        bands: Bands,
+    ) -> Self {
+        CCS811 {
+            shutdown,
+            config,
+            ccs811_pin,
+            reset_pin,
+            data_ready,
+            air,
+            bands,
+        }
+    }
+
+    fn addr(&self) -> u16 {
+        self.config.read().unwrap().ccs811.addr
+    }
+
+    /// nINTの接続と設定に応じた割り込み
+    fn interrupt(&self, conf: &config::CCS811) -> Interrupt {
+        match (self.data_ready.is_some(), conf.threshold_interrupt) {
+            (false, _) => Interrupt::Disabled,
+            (true, false) => Interrupt::DataReady,
+            (true, true) => Interrupt::Threshold,
+        }
+    }
+
+    /// 設定の測定周期と、割り込みに応じたMEAS_MODE
+    fn mode(&self) -> u8 {
+        let conf = self.config.read().unwrap().ccs811.clone();
+        meas_mode(conf.drive_mode.into(), self.interrupt(&conf))
+    }
+
+    async fn wake_up(&self, addr: u16) -> WakeGuard<'_, P> {
+        WakeGuard::new(self.ccs811_pin.lock().await, addr).await
+    }
+
+    /// 初期化
+    ///
+    /// ハードウェアIDを確認し、内部アプリケーションを起動して`ccs811.drive_mode`で測定を開始する。
+    /// `ccs811.baseline_path`に保存したベースラインが古くなければ書き戻す。
+    /// 読み込んだバージョンは`Air`に格納する。
+    pub async fn init<B: I2cBus>(&self, bus: &Arc<Mutex<B>>) -> EResult<()> {
+        let baseline = self.stored_baseline();
+        let mut wake = self.wake_up(self.addr()).await;
+        let versions = wake.init(bus, self.mode(), baseline).await?;
+        *self.air.ccs811_versions.lock().unwrap() = Some(versions);
+        Ok(())
+    }
+
+    /// ベースラインを読み込む
+    pub async fn baseline<B: I2cBus>(&self, bus: &Arc<Mutex<B>>) -> EResult<u16> {
+        let addr = self.addr();
+        let wake = self.wake_up(addr).await;
+        let mut guard = bus.lock().await;
+        guard.set_slave_address(addr)?;
+        wake.get_baseline(&mut *guard)
+    }
+
+    /// ベースラインを書き込む
+    pub async fn set_baseline<B: I2cBus>(&self, bus: &Arc<Mutex<B>>, baseline: u16) -> EResult<()> {
+        let addr = self.addr();
+        let wake = self.wake_up(addr).await;
+        let mut guard = bus.lock().await;
+        guard.set_slave_address(addr)?;
+        wake.set_baseline(&mut *guard, baseline)
+    }
+
+    /// eCO2の段階の閾値を書き込む
+    pub async fn set_thresholds<B: I2cBus>(
+        &self,
+        bus: &Arc<Mutex<B>>,
+        thresholds: &Thresholds,
+    ) -> EResult<()> {
+        let addr = self.addr();
+        let wake = self.wake_up(addr).await;
+        let mut guard = bus.lock().await;
+        guard.set_slave_address(addr)?;
+        wake.set_thresholds(&mut *guard, thresholds)
+    }
+
+    /// 設定の閾値が書き込んだものと異なれば書き込む
+    async fn update_thresholds<B: I2cBus>(
+        &self,
+        bus: &Arc<Mutex<B>>,
+        conf: &config::CCS811,
+        written: &mut Option<Thresholds>,
+    ) {
+        let thresholds = Thresholds::from(conf);
+        if *written == Some(thresholds) {
+            return;
+        }
+
+        match self.set_thresholds(bus, &thresholds).await {
+            Ok(()) => {
+                println!(
+                    "CCS811: THRESHOLDS = {}, {} ppm",
+                    thresholds.medium, thresholds.high
+                );
+                *written = Some(thresholds);
+            }
+            Err(e) => perror!(e),
+        }
+    }
+
+    /// 書き戻すベースライン
+    ///
+    /// 保存していない場合や、`baseline_max_age_h`より古い場合は`None`。
+    fn stored_baseline(&self) -> Option<u16> {
+        let conf = self.config.read().unwrap().ccs811.clone();
+        let path = conf.baseline_path?;
+
+        let stored = match baseline::load(&path) {
+            Ok(stored) => stored?,
+            Err(e) => {
+                perror!(e);
+                return None;
+            }
+        };
+
+        let age = stored.age(SystemTime::now());
+        if age > Duration::from_secs(conf.baseline_max_age_h * 60 * 60) {
+            println!(
+                "CCS811: baseline saved {} h ago is too old, ignored",
+                age.as_secs() / 3600
+            );
+            return None;
+        }
+        Some(stored.value)
+    }
+
+    /// 起動から`baseline_warmup_min`経過した後、`baseline_interval_min`ごとにベースラインを保存
+    async fn save_baseline<B: I2cBus>(
+        &self,
+        bus: &Arc<Mutex<B>>,
+        conf: &config::CCS811,
+        started: Instant,
+        saved: &mut Option<Instant>,
+    ) {
+        let Some(path) = &conf.baseline_path else {
+            return;
+        };
+        let minutes = |m: u64| Duration::from_secs(m * 60);
+        if started.elapsed() < minutes(conf.baseline_warmup_min)
+            || saved.is_some_and(|t| t.elapsed() < minutes(conf.baseline_interval_min))
+        {
+            return;
+        }
+
+        let result = self.baseline(bus).await.and_then(|value| {
+            let stored = baseline::Stored {
+                value,
+                saved: SystemTime::now(),
+            };
+            baseline::save(path, &stored).map(|_| value)
+        });
+        match result {
+            Ok(value) => println!("CCS811: saved baseline 0x{value:04x}"),
+            Err(e) => perror!(e),
+        }
+        *saved = Some(Instant::now()); // 失敗しても次の間隔まで待つ
+    }
+
+    /// リセット
+    ///
+    /// nRESETを接続している場合はLowにし、それ以外はSW_RESETを書き込む。
+    /// リセット後はブートモードになる。
+    pub async fn reset<B: I2cBus>(&self, bus: &Arc<Mutex<B>>) -> EResult<()> {
+        match &self.reset_pin {
+            Some(pin) => {
+                println!("CCS811: nRESET");
+                let mut pin = pin.lock().await;
+                pin.set_low();
+                task::sleep(Duration::from_micros(20)).await;
+                pin.set_high();
+            }
+            None => {
+                println!("CCS811: SW_RESET");
+                let addr = self.addr();
+                let wake = self.wake_up(addr).await;
+                let mut guard = bus.lock().await;
+                guard.set_slave_address(addr)?;
+                wake.sw_reset(&mut *guard)?;
+            }
+        }
+        task::sleep(RESET_DELAY).await;
+        Ok(())
+    }
+
+    /// エラーの回数を数える
+    fn count_error(&self, err: Error) {
+        let counters = &mut *self.air.ccs811_counters.lock().unwrap();
+        for (flag, count) in [
+            (Error::HEATER_SUPPLY, &mut counters.heater_supply),
+            (Error::HEATER_FAULT, &mut counters.heater_fault),
+            (Error::MOX_RESISTANCE, &mut counters.mox_resistance),
+            (Error::MEAS_MODE_INVALID, &mut counters.meas_mode_invalid),
+            (Error::READ_REG_INVALID, &mut counters.read_reg_invalid),
+            (Error::MSG_INVALID, &mut counters.msg_invalid),
+        ] {
+            if err.contains(flag) {
+                *count += 1;
+            }
+        }
+    }
+
+    /// 読み込みのエラーに対処。初期化し直した場合は`true`
+    ///
+    /// ERROR_IDのエラーはビットに応じて対処し、通信エラーは`max_bus_errors`回続いたらリセットする。
+    /// 復旧に失敗した場合はエラーを返す。
+    async fn recover<B: I2cBus>(
+        &self,
+        bus: &Arc<Mutex<B>>,
+        conf: &config::CCS811,
+        e: Box<dyn std::error::Error + Send + Sync>,
+        bus_errors: &mut u32,
+    ) -> EResult<bool> {
+        perror!(e);
+        let recovery = match e.downcast_ref::<SensorError>() {
+            Some(SensorError(err)) => {
+                self.count_error(*err);
+                err.recovery()
+            }
+            None => {
+                self.air.ccs811_counters.lock().unwrap().bus += 1;
+                *bus_errors += 1;
+                if *bus_errors < conf.max_bus_errors {
+                    Recovery::Retry
+                } else {
+                    Recovery::Reset
+                }
+            }
+        };
+        if recovery == Recovery::Retry {
+            return Ok(false);
+        }
+
+        println!("CCS811: recovering ({recovery:?})");
+        *bus_errors = 0;
+        let result = match recovery {
+            Recovery::Reset => self.reset(bus).await,
+            _ => Ok(()),
+        };
+        let result = match result {
+            Ok(()) => self.init(bus).await,
+            Err(e) => Err(e),
+        };
+
+        let mut counters = self.air.ccs811_counters.lock().unwrap();
+        match result {
+            Ok(()) => {
+                counters.recoveries += 1;
+                Ok(true)
+            }
+            Err(e) => {
+                counters.failed_recoveries += 1;
+                perror!(e);
+                Err(e)
+            }
+        }
+    }
+
+    /// 保存した累積稼働時間
+    ///
+    /// `runtime_path`が未指定の場合は、バーンイン済みとして`None`。
+    /// 保存していない場合は新品として0。
+    fn stored_runtime(&self) -> Option<Duration> {
+        let path = self.config.read().unwrap().ccs811.runtime_path.clone()?;
+        match runtime::load(&path) {
+            Ok(stored) => Some(stored.unwrap_or_default()),
+            Err(e) => {
+                perror!(e);
+                Some(Duration::ZERO)
+            }
+        }
+    }
+
+    /// 状態を更新し、変わった場合は表示
+    ///
+    /// `stored`は起動時の累積稼働時間。`suspect`の場合は経過時間によらず`Suspect`とする。
+    fn update_state(
+        &self,
+        conf: &config::CCS811,
+        stored: Option<Duration>,
+        started: Instant,
+        suspect: bool,
+    ) {
+        let uptime = started.elapsed();
+        let runtime = stored.map_or(Duration::MAX, |s| s + uptime);
+        let state = match suspect {
+            true => Ccs811State::Suspect,
+            false => sensor_state(runtime, uptime, conf),
+        };
+
+        let prev = self.air.ccs811_state();
+        if prev == Some(state) {
+            return;
+        }
+        match prev {
+            Some(prev) => println!("CCS811: {prev} -> {state}"),
+            None => println!("CCS811: {state}"),
+        }
+        self.air.set_ccs811_state(state);
+    }
+
+    /// 範囲外のサンプルを数えて表示
+    fn count_rejection(&self, rejection: Rejection, co2: u16, tvoc: u16) {
+        println!("CCS811: rejected CO2 = {co2}, TVOC = {tvoc} ({rejection})");
+        let mut counters = self.air.ccs811_counters.lock().unwrap();
+        match rejection {
+            Rejection::Co2Low => counters.rejected_co2_low += 1,
+            Rejection::Co2High => counters.rejected_co2_high += 1,
+            Rejection::Tvoc => counters.rejected_tvoc += 1,
+        }
+    }
+
+    /// `force`か、前回から`runtime_interval_min`経過していれば累積稼働時間を保存
+    ///
+    /// ヒーターを使わないアイドルの間は数えない。
+    fn save_runtime(
+        &self,
+        conf: &config::CCS811,
+        stored: Option<Duration>,
+        started: Instant,
+        saved: &mut Instant,
+        force: bool,
+    ) {
+        let (Some(path), Some(stored)) = (&conf.runtime_path, stored) else {
+            return;
+        };
+        if conf.drive_mode == config::DriveMode::Idle
+            || !force && saved.elapsed() < Duration::from_secs(conf.runtime_interval_min * 60)
+        {
+            return;
+        }
+
+        if let Err(e) = runtime::save(path, stored + started.elapsed()) {
+            perror!(e);
+        }
+        *saved = Instant::now(); // 失敗しても次の間隔まで待つ
+    }
+
+    /// CO2、TVOC、電流と電圧を一度読み込む
+    ///
+    /// 新しいデータが無い場合は`None`を返す。
+    pub async fn read<B: I2cBus>(&self, bus: &Arc<Mutex<B>>) -> EResult<Option<Data>> {
+        let addr = self.addr();
+        let wake = self.wake_up(addr).await;
+        let mut guard = bus.lock().await;
+        guard.set_slave_address(addr)?;
+        wake.get_data(&mut *guard)
+    }
+
+    /// 電流（μA）とADCの値を一度読み込む
+    ///
+    /// 250ミリ秒周期の場合はこちらのみ更新される。新しいデータが無い場合は`None`を返す。
+    pub async fn read_raw<B: I2cBus>(&self, bus: &Arc<Mutex<B>>) -> EResult<Option<(u8, u16)>> {
+        let addr = self.addr();
+        let wake = self.wake_up(addr).await;
+        let mut guard = bus.lock().await;
+        guard.set_slave_address(addr)?;
+        wake.get_raw_data(&mut *guard)
+    }
+
+    /// 補正用の湿度（%）と温度（度）を書き込む
+    pub async fn set_env_data<B: I2cBus>(
+        &self,
+        bus: &Arc<Mutex<B>>,
+        humidity: f64,
+        celsius: f64,
+    ) -> EResult<()> {
+        let addr = self.addr();
+        let wake = self.wake_up(addr).await;
+        let mut guard = bus.lock().await;
+        guard.set_slave_address(addr)?;
+        wake.set_env_data(&mut *guard, humidity, celsius)
+    }
+
+    /// 電流（μA）とADCの値を格納
+    fn store_raw(&self, current: u8, adc: u16) {
+        self.air.current.store(current as u16, Ordering::Relaxed);
+        self.air.voltage.set(adc_voltage(adc));
+    }
+
+    /// 補正に用いる湿度と温度
+    ///
+    /// 温度センサが未測定の場合は`None`。湿度が未測定の場合は設定の値を用いる。
+    fn env_data(&self, conf: &config::CCS811) -> Option<(f64, f64)> {
+        let temp = self.air.find_temp(conf.temperature_sensor.as_deref())?;
+        let humidity = self.air.humidity.measured().unwrap_or(conf.humidity);
+        Some((humidity, temp.measured()?))
+    }
+
+    /// 前回から`env_interval_ms`経過していれば湿度と温度を書き込む
+    async fn update_env_data<B: I2cBus>(
+        &self,
+        bus: &Arc<Mutex<B>>,
+        conf: &config::CCS811,
+        updated: &mut Option<Instant>,
+    ) {
+        if updated.is_some_and(|t| t.elapsed() < config::millis(conf.env_interval_ms)) {
+            return;
+        }
+
+        let Some((humidity, celsius)) = self.env_data(conf) else {
+            return;
+        };
+        match self.set_env_data(bus, humidity, celsius).await {
+            Ok(()) => {
+                println!("CCS811: ENV_DATA = {humidity:.1} %, {celsius:.2} 度");
+                *updated = Some(Instant::now());
+            }
+            Err(e) => perror!(e),
+        }
+    }
+
+    /// 次に読み込むまで待機
+    ///
+    /// nINTを接続している場合は通知を待つ。通知を逃した場合に備え、測定周期の2倍で打ち切る。
+    /// 閾値の割り込みでは段階が変わった時しか通知されないため、補正の書き込みなどのために`interval_ms`で打ち切る。
+    async fn wait(&self, conf: &config::CCS811) -> Wake {
+        let interval = config::millis(conf.interval_ms);
+        let Some(rx) = self.data_ready.as_ref().filter(|rx| !rx.is_closed()) else {
+            return match timeout(interval, self.shutdown.cancelled()).await {
+                Ok(_) => Wake::Shutdown,
+                Err(_) => Wake::Timeout,
+            };
+        };
+
+        let t = match self.interrupt(conf) {
+            Interrupt::Threshold => interval,
+            _ => conf.drive_mode.period().map_or(interval, |p| p * 2),
+        };
+        let cancelled = self.shutdown.cancelled().fuse();
+        let ready = rx.recv().fuse();
+        let wait = task::sleep(t).fuse();
+        pin_mut!(cancelled, ready, wait);
+
+        select!(
+            _ = cancelled => Wake::Shutdown,
+            _ = ready => Wake::Ready,
+            _ = wait => Wake::Timeout,
+        )
+    }
+}
+
+/// 待機を終えた理由
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Wake {
+    Shutdown, // 終了の要求
+    Ready,    // nINTの通知
+    Timeout,  // 打ち切り
+}
+
+impl<B: I2cBus, P: OutputPin> Runner<B> for CCS811<P> {
+    fn run(self, bus: Arc<Mutex<B>>) -> EResult<JoinHandle<EResult<()>>> {
+        let f = async move {
+            // 初期化
+            if let Err(e) = self.init(&bus).await {
+                perror!(e);
+                return Err(e);
+            }
+
+            let started = Instant::now();
+            let runtime = self.stored_runtime(); // 起動時の累積稼働時間
+            let mut runtime_saved = started; // 累積稼働時間を最後に保存した時刻
+            self.update_state(&self.config.read().unwrap().ccs811, runtime, started, false);
+
+            // 最初の測定まで1秒待機
+            if timeout(Duration::from_secs(1), self.shutdown.cancelled())
+                .await
+                .is_ok()
+            {
+                println!("exiting CCS811 ...");
+                return Ok(());
+            }
+
+            let mut env_updated = None; // ENV_DATAを最後に書き込んだ時刻
+            let mut baseline_saved = None; // ベースラインを最後に保存した時刻
+            let mut thresholds = None; // 書き込んだ段階の閾値
+            let mut bus_errors = 0; // 続いている通信エラーの回数
+            let mut rejections = Rejections::default(); // 直近のサンプルの棄却
+            let mut last_read = Instant::now(); // ALG_RESULT_DATAを最後に読み込んだ時刻
+
+            loop {
+                let conf = self.config.read().unwrap().ccs811.clone();
+
+                // バーンインとウォームアップ
+                let suspect = rejections.suspect(conf.reject_window, conf.max_reject_rate);
+                self.update_state(&conf, runtime, started, suspect);
+                self.save_runtime(&conf, runtime, started, &mut runtime_saved, false);
+
+                // 設定の再読み込みによる閾値の変更
+                self.update_thresholds(&bus, &conf, &mut thresholds).await;
+
+                // 新しいデータかシグナルでの終了を待つ
+                let wake = self.wait(&conf).await;
+                if wake == Wake::Shutdown {
+                    self.save_runtime(&conf, runtime, started, &mut runtime_saved, true);
+                    println!("exiting CCS811 ...");
+                    break;
+                }
+
+                // 温度と湿度による補正
+                self.update_env_data(&bus, &conf, &mut env_updated).await;
+
+                // ベースラインの保存
+                self.save_baseline(&bus, &conf, started, &mut baseline_saved)
+                    .await;
+
+                match conf.drive_mode {
+                    config::DriveMode::Idle => continue,
+                    config::DriveMode::Millis250 => {
+                        // ALG_RESULT_DATAは更新されない
+                        match self.read_raw(&bus).await {
+                            Ok(Some((current, adc))) => {
+                                println!("CCS811: RAW_DATA = {current} uA, {adc}");
+                                self.store_raw(current, adc);
+                                bus_errors = 0;
+                            }
+                            Ok(None) => bus_errors = 0,
+                            Err(e) => {
+                                // 初期化し直した場合は閾値と補正も書き込み直す
+                                if self.recover(&bus, &conf, e, &mut bus_errors).await? {
+                                    (thresholds, env_updated) = (None, None);
+                                }
+                            }
+                        }
+                        continue;
+                    }
+                    _ => (),
+                }
+
+                // 閾値の割り込みでは通知を受けた時だけ読み込む（通知が長く無い場合を除く）
+                if self.interrupt(&conf) == Interrupt::Threshold
+                    && wake != Wake::Ready
+                    && last_read.elapsed() < THRESHOLD_FALLBACK
+                {
+                    continue;
+                }
+                last_read = Instant::now();
+
+                let Data {
+                    co2,
+                    tvoc,
+                    current,
+                    adc,
+                } = match self.read(&bus).await {
+                    Ok(Some(data)) => {
+                        bus_errors = 0;
+                        data
+                    }
+                    Ok(None) => {
+                        bus_errors = 0;
+                        continue;
+                    }
+                    Err(e) => {
+                        if self.recover(&bus, &conf, e, &mut bus_errors).await? {
+                            (thresholds, env_updated) = (None, None);
+                        }
+                        continue;
+                    }
+                };
+
+                // 電流と電圧は範囲外のeCO2でも格納
+                self.store_raw(current, adc);
+
+                // 範囲外のサンプルは数えて棄却
+                let rejection = Rejection::check(co2, tvoc, &conf);
+                rejections.push(rejection.is_some(), conf.reject_window);
+                if let Some(rejection) = rejection {
+                    self.count_rejection(rejection, co2, tvoc);
+                    continue;
+                }
+
+                match self.air.ccs811_state() {
+                    Some(Ccs811State::Ready) | None => {
+                        println!("CCS811: CO2 = {co2}, TVOC = {tvoc}")
+                    }
+                    Some(state) => println!("CCS811: CO2 = {co2}, TVOC = {tvoc} ({state})"),
+                }
+                self.air.co2.store(co2, Ordering::Relaxed);
+                self.air.tvoc.store(tvoc, Ordering::Relaxed);
+                self.bands.update(co2, &Thresholds::from(&conf));
+            }
+
+            Ok(())
+        };
+
+        Ok(task::spawn(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::co2::Band;
+    use crate::{
+        hal::fake::{FakeI2c, FakeOutputPin},
+        shutdown::{Shutdown, Stage},
+    };
+    use rppal::gpio::Level;
+
+    const ADDR: u16 = 0x5a;
+
+    /// ハードウェアIDとバージョン（HW 0x12、boot 1.0.0、app 2.1.3）
+    fn set_ids(fake: &FakeI2c) {
+        fake.set_reg(ADDR, REG_HW_ID, &[0x81]);
+        fake.set_reg(ADDR, REG_HW_VERSION, &[0x12]);
+        fake.set_reg(ADDR, REG_FW_BOOT_VERSION, &[0x10, 0x00]);
+        fake.set_reg(ADDR, REG_FW_APP_VERSION, &[0x21, 0x03]);
+    }
+
+    fn ccs811(shutdown: &Shutdown, pin: &FakeOutputPin) -> CCS811<FakeOutputPin> {
+        let token = shutdown.token(Stage::Producer, "CCS811");
+        let pin = Arc::new(Mutex::new(pin.clone()));
+        CCS811::new(
+            token,
+            Default::default(),
+            pin,
+            None,
+            None,
+            Air::default(),
+            Bands::new(),
+        )
+    }
+
+    #[async_std::test]
+    async fn init_running_app() {
+        let shutdown = Shutdown::new();
+        let pin = FakeOutputPin::new();
+        let bus = Arc::new(Mutex::new(FakeI2c::new()));
+        let fake = bus.lock().await.clone();
+        set_ids(&fake);
+        fake.set_reg(ADDR, REG_STATUS, &[0b1001_0000]);
+
+        let ccs811 = ccs811(&shutdown, &pin);
+        let versions = {
+            let mut wake = ccs811.wake_up(ADDR).await;
+            wake.init(&bus, 0b0001_0000, None).await.unwrap()
+        };
+        assert_eq!(versions.hw, 0x12);
+        assert_eq!(versions.boot.to_string(), "1.0.0");
+        assert_eq!(versions.app.to_string(), "2.1.3");
+
+        let writes = fake.writes();
+        assert_eq!(writes.len(), 1);
+        assert_eq!(writes[0].reg, Some(REG_MEAS_MODE));
+        assert_eq!(writes[0].data, vec![0b0001_0000]); // 1秒ごと、割り込み無し
+        assert_eq!(pin.levels(), vec![Level::Low, Level::High]);
+    }
+
+    #[async_std::test]
+    async fn init_invalid_hw_id() {
+        let shutdown = Shutdown::new();
+        let pin = FakeOutputPin::new();
+        let bus = Arc::new(Mutex::new(FakeI2c::new()));
+        bus.lock().await.set_reg(ADDR, REG_HW_ID, &[0x00]);
+
+        let ccs811 = ccs811(&shutdown, &pin);
+        let mut wake = ccs811.wake_up(ADDR).await;
+        assert!(wake.init(&bus, 0b0001_0000, None).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn get_data() {
+        let shutdown = Shutdown::new();
+        let pin = FakeOutputPin::new();
+        let mut bus = FakeI2c::new();
+        bus.set_slave_address(ADDR).unwrap();
+        bus.push_read(ADDR, REG_STATUS, &[0b1001_0000]);
+        bus.push_read(ADDR, REG_STATUS, &[0b1001_1000]);
+        bus.set_reg(
+            ADDR,
+            REG_ALG_RESULT_DATA,
+            &[0x01, 0x90, 0x00, 0x10, 0b1001_1000, 0, 0b0101_0010, 0xbc],
+        );
+
+        let ccs811 = ccs811(&shutdown, &pin);
+        let wake = ccs811.wake_up(ADDR).await;
+        assert_eq!(wake.get_data(&mut bus).unwrap(), None); // DATA_READYではない
+        let data = Data {
+            co2: 400,
+            tvoc: 16,
+            current: 20,
+            adc: 0x2bc,
+        };
+        assert_eq!(wake.get_data(&mut bus).unwrap(), Some(data));
+    }
+
+    #[test]
+    fn register_values() {
+        assert_eq!(meas_mode(DriveMode::Idle, Interrupt::Disabled), 0b0000_0000);
+        assert_eq!(
+            meas_mode(DriveMode::Secs1, Interrupt::Disabled),
+            0b0001_0000
+        );
+        assert_eq!(
+            meas_mode(DriveMode::Secs60, Interrupt::DataReady),
+            0b0011_1000
+        );
+        assert_eq!(
+            meas_mode(DriveMode::Secs10, Interrupt::Threshold),
+            0b0010_1100
+        );
+        assert_eq!(
+            meas_mode(DriveMode::Millis250, Interrupt::Disabled),
+            0b0100_0000
+        );
+
+        // 1500 ppm、2500 ppm
+        let thresholds = Thresholds {
+            medium: 1500,
+            high: 2500,
+        };
+        assert_eq!(encode_thresholds(&thresholds), [0x05, 0xdc, 0x09, 0xc4, 50]);
+
+        // 20 μA、ADC 0x2bc
+        assert_eq!(parse_raw_data(&[0b0101_0010, 0xbc]), (20, 0x2bc));
+        assert!((adc_voltage(1023) - 1.65).abs() < 1e-9);
+    }
+
+    #[async_std::test]
+    async fn read_on_data_ready() {
+        let mut conf = config::Config::default();
+        conf.ccs811.drive_mode = config::DriveMode::Secs60;
+
+        let shutdown = Shutdown::new();
+        let pin = FakeOutputPin::new();
+        let (ready_tx, ready_rx) = async_std::channel::bounded(1);
+        let mut ccs811 = ccs811(&shutdown, &pin);
+        ccs811.config = Arc::new(std::sync::RwLock::new(conf));
+        ccs811.data_ready = Some(ready_rx);
+        let air = ccs811.air.clone();
+        let band_rx = ccs811.bands.subscribe();
+
+        let fake = FakeI2c::new();
+        set_ids(&fake);
+        fake.set_reg(ADDR, REG_STATUS, &[0b1001_1000]);
+        fake.set_reg(
+            ADDR,
+            REG_ALG_RESULT_DATA,
+            &[0x01, 0x90, 0x00, 0x10, 0b1001_1000, 0, 0, 0],
+        );
+        let hdl = ccs811.run(Arc::new(Mutex::new(fake.clone()))).unwrap();
+
+        // 60秒周期、割り込みあり
+        task::sleep(Duration::from_millis(100)).await;
+        assert_eq!(fake.writes()[0].data, vec![0b0011_1000]);
+
+        // 通知が来るまで読み込まない
+        task::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(air.co2.load(Ordering::Relaxed), 0);
+
+        // 閾値はデフォルトの1500 ppm、2500 ppm
+        let thresholds = fake
+            .writes()
+            .into_iter()
+            .find(|w| w.reg == Some(REG_THRESHOLDS));
+        assert_eq!(thresholds.unwrap().data, vec![0x05, 0xdc, 0x09, 0xc4, 50]);
+
+        ready_tx.send(()).await.unwrap();
+        task::sleep(Duration::from_millis(100)).await;
+        assert_eq!(air.co2.load(Ordering::Relaxed), 400);
+        assert_eq!(band_rx.try_recv().unwrap().to, Band::Low);
+        assert_eq!(air.ccs811_state(), Some(Ccs811State::WarmingUp)); // 起動から20分未満
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn read_on_threshold_interrupt() {
+        let mut conf = config::Config::default();
+        conf.ccs811.drive_mode = config::DriveMode::Secs1;
+        conf.ccs811.threshold_interrupt = true;
+        conf.ccs811.interval_ms = 100;
+
+        let shutdown = Shutdown::new();
+        let pin = FakeOutputPin::new();
+        let (ready_tx, ready_rx) = async_std::channel::bounded(1);
+        let mut ccs811 = ccs811(&shutdown, &pin);
+        ccs811.config = Arc::new(std::sync::RwLock::new(conf));
+        ccs811.data_ready = Some(ready_rx);
+        let air = ccs811.air.clone();
+        let band_rx = ccs811.bands.subscribe();
+
+        let fake = FakeI2c::new();
+        set_ids(&fake);
+        fake.set_reg(ADDR, REG_STATUS, &[0b1001_1000]);
+        fake.set_reg(
+            ADDR,
+            REG_ALG_RESULT_DATA,
+            &[0x06, 0x40, 0x00, 0x10, 0b1001_1000, 0, 0, 0],
+        );
+        let hdl = ccs811.run(Arc::new(Mutex::new(fake.clone()))).unwrap();
+
+        // 1秒周期、閾値の割り込み
+        task::sleep(Duration::from_millis(100)).await;
+        assert_eq!(fake.writes()[0].data, vec![0b0001_1100]);
+
+        // interval_msを過ぎても通知が来るまで読み込まない
+        task::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(air.co2.load(Ordering::Relaxed), 0);
+        assert!(band_rx.try_recv().is_err());
+
+        ready_tx.send(()).await.unwrap();
+        task::sleep(Duration::from_millis(100)).await;
+        assert_eq!(air.co2.load(Ordering::Relaxed), 1600);
+        assert_eq!(band_rx.try_recv().unwrap().to, Band::Medium);
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn restore_and_save_baseline() {
+        let path = std::env::temp_dir().join(format!("ccs811_test_{}", std::process::id()));
+        let mut conf = config::Config::default();
+        conf.ccs811.baseline_path = Some(path.clone());
+        conf.ccs811.baseline_warmup_min = 0;
+
+        let shutdown = Shutdown::new();
+        let pin = FakeOutputPin::new();
+        let mut ccs811 = ccs811(&shutdown, &pin);
+        ccs811.config = Arc::new(std::sync::RwLock::new(conf.clone()));
+
+        let fake = FakeI2c::new();
+        set_ids(&fake);
+        fake.set_reg(ADDR, REG_STATUS, &[0b1001_0000]);
+        let bus = Arc::new(Mutex::new(fake.clone()));
+
+        // 保存したベースラインを書き戻す
+        let stored = baseline::Stored {
+            value: 0x1234,
+            saved: SystemTime::now(),
+        };
+        baseline::save(&path, &stored).unwrap();
+        ccs811.init(&bus).await.unwrap();
+        let versions = ccs811.air.ccs811_versions.lock().unwrap().unwrap();
+        assert_eq!(versions.to_string(), "HW 0x12, boot 1.0.0, app 2.1.3");
+        let data: Vec<(Option<u8>, Vec<u8>)> =
+            fake.writes().into_iter().map(|w| (w.reg, w.data)).collect();
+        assert_eq!(
+            data,
+            vec![
+                (Some(REG_MEAS_MODE), vec![0b0001_0000]),
+                (Some(REG_BASELINE), vec![0x12, 0x34]),
+            ]
+        );
+
+        // 測定中のベースラインを保存
+        fake.set_reg(ADDR, REG_BASELINE, &[0xab, 0xcd]);
+        let mut saved = None;
+        ccs811
+            .save_baseline(&bus, &conf.ccs811, Instant::now(), &mut saved)
+            .await;
+        assert!(saved.is_some());
+        assert_eq!(baseline::load(&path).unwrap().unwrap().value, 0xabcd);
+
+        // 古いベースラインは書き戻さない
+        let old = baseline::Stored {
+            value: 0x1234,
+            saved: SystemTime::now() - Duration::from_secs(8 * 24 * 60 * 60),
+        };
+        baseline::save(&path, &old).unwrap();
+        assert_eq!(ccs811.stored_baseline(), None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn error_recovery() {
+        assert_eq!(Error::MSG_INVALID.recovery(), Recovery::Retry);
+        assert_eq!(Error::MEAS_MODE_INVALID.recovery(), Recovery::Init);
+        assert_eq!(
+            (Error::HEATER_FAULT | Error::READ_REG_INVALID).recovery(),
+            Recovery::Reset
+        );
+    }
+
+    #[async_std::test]
+    async fn recover_from_errors() {
+        let shutdown = Shutdown::new();
+        let pin = FakeOutputPin::new();
+        let reset = FakeOutputPin::new();
+        let mut ccs811 = ccs811(&shutdown, &pin);
+        let conf = config::CCS811::default();
+        let mut bus_errors = 0;
+
+        let fake = FakeI2c::new();
+        set_ids(&fake);
+        fake.set_reg(ADDR, REG_STATUS, &[0b1001_0001]);
+        fake.set_reg(ADDR, REG_ERROR_ID, &[0b0001_0000]);
+        let bus = Arc::new(Mutex::new(fake.clone()));
+
+        // HEATER_FAULTはSW_RESETしてから初期化し直す
+        let e = ccs811.read(&bus).await.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<SensorError>(),
+            Some(&SensorError(Error::HEATER_FAULT))
+        );
+        fake.set_reg(ADDR, REG_STATUS, &[0b1001_0000]);
+        assert!(ccs811
+            .recover(&bus, &conf, e, &mut bus_errors)
+            .await
+            .unwrap());
+        let data: Vec<(Option<u8>, Vec<u8>)> =
+            fake.writes().into_iter().map(|w| (w.reg, w.data)).collect();
+        assert_eq!(
+            data,
+            vec![
+                (Some(REG_SW_RESET), SW_RESET.to_vec()),
+                (Some(REG_MEAS_MODE), vec![0b0001_0000]),
+            ]
+        );
+
+        // 通信エラーは`max_bus_errors`回続いたらnRESETでリセット
+        ccs811.reset_pin = Some(Arc::new(Mutex::new(reset.clone())));
+        let mut recovered = vec![];
+        for _ in 0..conf.max_bus_errors {
+            fake.fail_next("bus error");
+            let e = ccs811.read(&bus).await.unwrap_err();
+            recovered.push(
+                ccs811
+                    .recover(&bus, &conf, e, &mut bus_errors)
+                    .await
+                    .unwrap(),
+            );
+        }
+        assert_eq!(recovered, vec![false, false, true]);
+        assert_eq!(reset.levels(), vec![Level::Low, Level::High]);
+
+        let counters = ccs811.air.ccs811_counters.lock().unwrap().clone();
+        assert_eq!(counters.heater_fault, 1);
+        assert_eq!(counters.bus, 3);
+        assert_eq!(counters.recoveries, 2);
+        assert_eq!(counters.failed_recoveries, 0);
+    }
+
+    #[test]
+    fn reject_out_of_range() {
+        let conf = config::CCS811::default();
+        assert_eq!(Rejection::check(400, 0, &conf), None);
+        assert_eq!(Rejection::check(399, 0, &conf), Some(Rejection::Co2Low));
+        assert_eq!(Rejection::check(8193, 0, &conf), Some(Rejection::Co2High));
+        assert_eq!(Rejection::check(8192, 1188, &conf), Some(Rejection::Tvoc));
+
+        // 直近4個のうち半分を超えて棄却したら疑う
+        let mut rejections = Rejections::default();
+        for rejected in [true, true, true] {
+            rejections.push(rejected, 4);
+        }
+        assert!(!rejections.suspect(4, 0.5)); // サンプルが揃っていない
+        rejections.push(false, 4);
+        assert!(rejections.suspect(4, 0.5));
+        rejections.push(false, 4);
+        assert!(!rejections.suspect(4, 0.5)); // 2/4
+    }
+
+    #[test]
+    fn burn_in_and_warm_up() {
+        let conf = config::CCS811::default();
+        let h = |h: u64| Duration::from_secs(h * 60 * 60);
+        let min = |m: u64| Duration::from_secs(m * 60);
+
+        assert_eq!(sensor_state(h(47), h(1), &conf), Ccs811State::BurnIn);
+        assert_eq!(sensor_state(h(48), min(19), &conf), Ccs811State::WarmingUp);
+        assert_eq!(sensor_state(h(100), min(20), &conf), Ccs811State::Ready);
+    }
+
+    #[test]
+    fn track_runtime() {
+        let path = std::env::temp_dir().join(format!("ccs811_test_runtime_{}", std::process::id()));
+        let mut conf = config::Config::default();
+        conf.ccs811.runtime_path = Some(path.clone());
+
+        let shutdown = Shutdown::new();
+        let pin = FakeOutputPin::new();
+        let mut ccs811 = ccs811(&shutdown, &pin);
+        ccs811.config = Arc::new(std::sync::RwLock::new(conf.clone()));
+
+        // 保存していない場合は新品
+        let stored = ccs811.stored_runtime();
+        assert_eq!(stored, Some(Duration::ZERO));
+        let started = Instant::now();
+        ccs811.update_state(&conf.ccs811, stored, started, false);
+        assert_eq!(ccs811.air.ccs811_state(), Some(Ccs811State::BurnIn));
+
+        // 間隔が経過していなければ保存しない
+        runtime::save(&path, Duration::from_secs(50 * 60 * 60)).unwrap();
+        let stored = ccs811.stored_runtime();
+        let mut saved = started;
+        ccs811.save_runtime(&conf.ccs811, stored, started, &mut saved, false);
+        assert_eq!(saved, started);
+        ccs811.update_state(&conf.ccs811, stored, started, false);
+        assert_eq!(ccs811.air.ccs811_state(), Some(Ccs811State::WarmingUp));
+        ccs811.update_state(&conf.ccs811, stored, started, true);
+        assert_eq!(ccs811.air.ccs811_state(), Some(Ccs811State::Suspect));
+
+        // 終了時は保存
+        ccs811.save_runtime(&conf.ccs811, stored, started, &mut saved, true);
+        assert!(saved > started);
+        assert_eq!(runtime::load(&path).unwrap(), stored);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn store_zero_voltage() {
+        let shutdown = Shutdown::new();
+        let pin = FakeOutputPin::new();
+        let ccs811 = ccs811(&shutdown, &pin);
+        assert_eq!(ccs811.air.voltage.measured(), None);
+
+        // ADCの0は未測定と区別する
+        ccs811.store_raw(20, 0);
+        assert_eq!(ccs811.air.voltage.measured(), Some(0.0));
+    }
+
+    #[test]
+    fn env_data_format() {
+        // データシートの例: 48.5 %、23.5 度
+        assert_eq!(encode_env_data(48.5, 23.5), [0x61, 0x00, 0x61, 0x00]);
+        assert_eq!(encode_env_data(50.0, 25.0), [0x64, 0x00, 0x64, 0x00]); // 電源投入時の値
+        assert_eq!(encode_env_data(50.25, -25.0), [0x64, 0x80, 0x00, 0x00]);
+        assert_eq!(encode_env_data(-1.0, -30.0), [0, 0, 0, 0]); // 範囲外は切り詰め
+    }
+
+    #[async_std::test]
+    async fn update_env_data() {
+        let shutdown = Shutdown::new();
+        let pin = FakeOutputPin::new();
+        let fake = FakeI2c::new();
+        let bus = Arc::new(Mutex::new(fake.clone()));
+
+        let mut ccs811 = ccs811(&shutdown, &pin);
+        ccs811.air = Air::new(&Default::default());
+        let mut conf = config::CCS811::default();
+        let mut updated = None;
+
+        // 気温が未測定の場合は書き込まない
+        ccs811.update_env_data(&bus, &conf, &mut updated).await;
+        assert!(fake.writes().is_empty());
+
+        ccs811.air.temps[0]
+            .value
+            .store(23.5f64.to_bits(), Ordering::Relaxed);
+        ccs811.update_env_data(&bus, &conf, &mut updated).await;
+        ccs811.update_env_data(&bus, &conf, &mut updated).await; // 間隔が経過していない
+
+        conf.humidity = 48.5;
+        updated = None;
+        ccs811.update_env_data(&bus, &conf, &mut updated).await;
+
+        // 湿度を測定している場合は設定より優先
+        ccs811.air.humidity.set(40.0);
+        updated = None;
+        ccs811.update_env_data(&bus, &conf, &mut updated).await;
+
+        let data: Vec<(Option<u8>, Vec<u8>)> =
+            fake.writes().into_iter().map(|w| (w.reg, w.data)).collect();
+        assert_eq!(
+            data,
+            vec![
+                (Some(REG_ENV_DATA), vec![0x64, 0x00, 0x61, 0x00]), // 湿度は設定の50 %
+                (Some(REG_ENV_DATA), vec![0x61, 0x00, 0x61, 0x00]),
+                (Some(REG_ENV_DATA), vec![0x50, 0x00, 0x61, 0x00]),
+            ]
+        );
+    }
+}
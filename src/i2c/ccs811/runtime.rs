@@ -0,0 +1,50 @@
+//! CCS811の累積稼働時間の保存
+//!
+//! 稼働時間の秒数を一行で保存する。
+
+use crate::EResult;
+use std::{fs, io, path::Path, time::Duration};
+
+/// 読み込む。ファイルが無い場合は`None`
+pub(super) fn load(path: &Path) -> EResult<Option<Duration>> {
+    let s = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    match s.trim().parse() {
+        Ok(secs) => Ok(Some(Duration::from_secs(secs))),
+        Err(_) => Err(format!("CCS811: invalid runtime file {}", path.display()).into()),
+    }
+}
+
+/// 保存
+///
+/// 書き込み途中で終了しても壊れないよう、一時ファイルに書き込んでから置き換える。
+pub(super) fn save(path: &Path, runtime: Duration) -> EResult<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, format!("{}\n", runtime.as_secs()))?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn save_and_load() {
+        let path = env::temp_dir().join(format!("ccs811_runtime_{}", std::process::id()));
+        assert_eq!(load(&path).unwrap(), None);
+
+        save(&path, Duration::from_secs(172_800)).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "172800\n");
+        assert_eq!(load(&path).unwrap(), Some(Duration::from_secs(172_800)));
+
+        fs::write(&path, "garbage").unwrap();
+        assert!(load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,172 +1,119 @@
-#[allow(unused_imports)]
-use async_std::prelude::*;
-
-use super::Runner;
-use crate::{perror, EResult};
-use async_std::{
-    channel::Receiver,
-    future::timeout,
-    sync::Mutex,
-    task::{self, JoinHandle},
-};
-use rppal::i2c::I2c;
-use std::{
-    marker::PhantomData,
-    sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
-    },
-    time::Duration,
-};
-
-/// 液晶ディスプレイ ST7032
-pub(super) struct ST7032<T> {
-    sig_rx: Receiver<()>,
-    temp: Arc<AtomicU64>,   // 温度
-    bright: Arc<AtomicU64>, // 明るさ
-    _state: PhantomData<T>, // 型状態
+//! 液晶ディスプレイST7032
+//!
+//! 初期化、表示の制御、アイコンRAMのコマンド列と外字は、
+//! Linuxのドライバ[ST7032]と`embedded-hal`版の[eh::ST7032]で共通。
+
+#[cfg(feature = "linux")]
+mod dim;
+#[cfg(feature = "embedded-hal")]
+pub mod eh;
+#[cfg(feature = "linux")]
+mod frame;
+pub mod glyph;
+#[cfg(feature = "linux")]
+pub mod graph;
+#[cfg(feature = "linux")]
+mod linux;
+#[cfg(feature = "linux")]
+mod page;
+
+#[cfg(feature = "linux")]
+pub use linux::{Initialized, Uninit, ST7032};
+
+/// コマンドのコントロールバイト
+///
+/// コマンド列はSMBusのブロック書き込みではなく、コントロールバイトに続けて書き込む。
+/// ブロック書き込みはバイト数を先に送るため、それがコマンドとして実行されてしまう（1はクリア）。
+const REG_SETTING: u8 = 0;
+const REG_DISPLAY: u8 = 0x40;
+
+const CMD_CGRAM: u8 = 0x40; // CGRAMの先頭へ移動（通常命令セット）
+const CMD_HOME: u8 = 0x80; // DDRAMの先頭へ移動
+const CMD_NORMAL: u8 = 0x38; // 通常命令セット（8ビット、2行）
+const CMD_EXTENDED: u8 = 0x39; // 拡張命令セット
+const CMD_ICON: u8 = 0x40; // アイコンRAMの先頭へ移動（拡張命令セット）
+
+/// アイコンRAMのアドレス数
+pub const ICON_SIZE: usize = 16;
+
+/// 表示の制御
+///
+/// 初期化時と[`ST7032::set_control`]で設定する。
+/// 昇圧回路とボルテージフォロワはモジュールの電源電圧に合わせる（3.3Vでは昇圧回路が必要）。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Control {
+    pub contrast: u8,  // 0 - 63
+    pub booster: bool, // 昇圧回路
+    pub follower: u8,  // ボルテージフォロワの増幅率（0 - 7）
+    pub icon: bool,    // アイコンを表示
+    pub display: bool, // 表示（オフでもDDRAMの内容は保持される）
+    pub cursor: bool,  // カーソルを表示
+    pub blink: bool,   // カーソル位置を点滅
 }
 
-pub(super) struct Uninit {}
-pub(super) struct Initialized {}
-
-impl<T> ST7032<T> {
-    const ADDR: u16 = 0x3e;
-    const REG_SETTING: u8 = 0;
-    const REG_DISPLAY: u8 = 0x40;
-    const CONTRASTS: u16 = 32;
-}
-
-impl ST7032<Uninit> {
-    pub(super) fn new(
-        sig_rx: Receiver<()>,
-        temp: Arc<AtomicU64>,
-        bright: Arc<AtomicU64>,
-    ) -> ST7032<Uninit> {
-        ST7032 {
-            sig_rx,
-            temp,
-            bright,
-            _state: PhantomData,
+impl Default for Control {
+    fn default() -> Self {
+        Control {
+            contrast: 32,
+            booster: true,
+            follower: 4,
+            icon: false,
+            display: true,
+            cursor: false,
+            blink: true,
         }
     }
-
-    /// 初期化
-    pub(super) async fn init(self, bus: &Arc<Mutex<I2c>>) -> EResult<ST7032<Initialized>> {
-        let lower = Self::CONTRASTS & 0x0f;
-        let upper = (Self::CONTRASTS & 0x30) >> 4;
-        let v: [u8; 6] = [
-            0x38,
-            0x39,
-            0x14,
-            0x70 | lower as u8,
-            0x54 | upper as u8,
-            0x6c,
-        ];
-
-        {
-            let mut guard = bus.lock().await;
-            guard.set_slave_address(Self::ADDR)?;
-            guard.smbus_block_write(Self::REG_SETTING, &v)?;
-        }
-
-        task::sleep(Duration::from_millis(200)).await;
-
-        let v: [u8; 3] = [0x38, 0x0d, 0x01];
-        {
-            let mut guard = bus.lock().await;
-            guard.set_slave_address(Self::ADDR)?;
-            guard.smbus_block_write(Self::REG_SETTING, &v)?;
-        }
-
-        task::sleep(Duration::from_millis(1)).await;
-
-        Ok(ST7032 {
-            sig_rx: self.sig_rx,
-            temp: self.temp,
-            bright: self.bright,
-            _state: PhantomData,
-        })
-    }
 }
 
-impl ST7032<Initialized> {
-    /// ディスプレイクリア
-    async fn clear(&self, bus: &Arc<Mutex<I2c>>) -> EResult<()> {
-        {
-            let mut guard = bus.lock().await;
-            guard.set_slave_address(Self::ADDR)?;
-            guard.smbus_write_byte(Self::REG_SETTING, 0x01)?;
-        }
-        task::sleep(Duration::from_millis(1)).await;
-        Ok(())
+impl Control {
+    /// コントラスト、アイコン、昇圧回路、ボルテージフォロワの設定コマンド（拡張命令セット）
+    fn power_cmds(&self) -> [u8; 3] {
+        let lower = self.contrast & 0x0f;
+        let upper = (self.contrast & 0x30) >> 4;
+        let icon = if self.icon { 0x08 } else { 0 };
+        let booster = if self.booster { 0x04 } else { 0 };
+        [
+            0x70 | lower,
+            0x50 | icon | booster | upper,
+            0x68 | (self.follower & 0x07), // フォロワは常にオン
+        ]
     }
 
-    /// 改行
-    async fn newline(&self, bus: &Arc<Mutex<I2c>>) -> EResult<()> {
-        {
-            let mut guard = bus.lock().await;
-            guard.set_slave_address(Self::ADDR)?;
-            guard.smbus_write_byte(Self::REG_SETTING, 0xc0)?;
-        }
-        task::sleep(Duration::from_millis(1)).await;
-        Ok(())
+    /// 表示、カーソル、点滅の設定コマンド
+    fn display_cmd(&self) -> u8 {
+        let display = if self.display { 0x04 } else { 0 };
+        let cursor = if self.cursor { 0x02 } else { 0 };
+        let blink = if self.blink { 0x01 } else { 0 };
+        0x08 | display | cursor | blink
     }
 
-    /// 2行表示
-    async fn print(&self, line1: &str, line2: Option<&str>, bus: &Arc<Mutex<I2c>>) -> EResult<()> {
-        self.clear(bus).await?;
-        self.print_line(line1.as_bytes(), bus).await?;
-        self.newline(bus).await?;
-        if let Some(line) = line2 {
-            self.print_line(line.as_bytes(), bus).await?;
+    /// `prev`から変化した設定のコマンド列と、そのバイト数
+    fn update_cmds(&self, prev: &Control) -> ([u8; 6], usize) {
+        let mut cmds = [0; 6];
+        let mut len = 0;
+        if self.power_cmds() != prev.power_cmds() {
+            let [lower, upper, follower] = self.power_cmds();
+            cmds[..5].copy_from_slice(&[CMD_EXTENDED, lower, upper, follower, CMD_NORMAL]);
+            len = 5;
         }
-
-        Ok(())
-    }
-
-    /// 一行表示
-    async fn print_line(&self, line: &[u8], bus: &Arc<Mutex<I2c>>) -> EResult<()> {
-        let mut guard = bus.lock().await;
-        guard.set_slave_address(Self::ADDR)?;
-        for c in line {
-            let c = if *c < 0x06 { 0x20 } else { *c };
-            guard.smbus_write_byte(Self::REG_DISPLAY, c)?;
+        if self.display_cmd() != prev.display_cmd() {
+            cmds[len] = self.display_cmd();
+            len += 1;
         }
-        Ok(())
+        (cmds, len)
     }
 }
 
-impl Runner for ST7032<Initialized> {
-    fn run(self, bus: Arc<Mutex<I2c>>) -> EResult<JoinHandle<()>> {
-        let wsec = Duration::from_secs(1);
-
-        let f = async move {
-            if let Err(e) = self.print("init ...", None, &bus).await {
-                perror!(e);
-                return;
-            }
-
-            loop {
-                // タイムアウトかシグナルでの終了を待つ
-                if timeout(wsec, self.sig_rx.recv()).await.is_ok() {
-                    println!("exiting ST7032 ...");
-                    break;
-                }
-
-                let temp = format!("{:.2} C", f64::from_bits(self.temp.load(Ordering::Relaxed)));
-                let bright = format!(
-                    "{:.2} %",
-                    f64::from_bits(self.bright.load(Ordering::Relaxed))
-                );
-
-                if let Err(e) = self.print(&temp, Some(&bright), &bus).await {
-                    perror!(e);
-                    break;
-                }
-            }
-        };
+/// 初期化コマンド（200ミリ秒待機の前、後）
+fn init_cmds(control: &Control) -> ([u8; 6], [u8; 3]) {
+    let [lower, upper, follower] = control.power_cmds();
+    (
+        [CMD_NORMAL, CMD_EXTENDED, 0x14, lower, upper, follower],
+        [CMD_NORMAL, control.display_cmd(), 0x01],
+    )
+}
 
-        Ok(task::spawn(f))
-    }
+/// アイコンRAMの書き込みコマンドと表示データ（各アドレスの下位5ビット）
+fn icon_cmds(icons: &[u8; ICON_SIZE]) -> ([u8; 2], [u8; ICON_SIZE]) {
+    ([CMD_EXTENDED, CMD_ICON], icons.map(|b| b & 0x1f))
 }
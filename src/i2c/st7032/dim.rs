@@ -0,0 +1,88 @@
+//! 暗い部屋での減光
+//!
+//! MCP3208の明るさが設定の`dark_below`未満になると暗いと判定し、
+//! `dark_below + dark_hysteresis`以上に戻るまで減光する。
+//! 減光中にボタンを押すと、`wake_ms`の間は元の表示に戻す。
+
+use std::time::{Duration, Instant};
+
+/// 減光の判定
+#[derive(Debug, Default)]
+pub(super) struct Dimmer {
+    dark: bool,             // 暗いと判定中
+    dimmed: bool,           // 減光中
+    woken: Option<Instant>, // ボタンで元に戻した時刻
+}
+
+impl Dimmer {
+    /// 明るさを反映し、減光するかを返す（`dark_below`が`None`の場合は減光しない）
+    pub(super) fn update(
+        &mut self,
+        bright: f64,
+        dark_below: Option<f64>,
+        hysteresis: f64,
+        wake: Duration,
+        now: Instant,
+    ) -> bool {
+        self.dark = match dark_below {
+            None => false,
+            Some(below) if self.dark => bright < below + hysteresis,
+            Some(below) => bright < below,
+        };
+        if !self.dark {
+            self.woken = None;
+        }
+        let awake = self.woken.is_some_and(|t| now - t < wake);
+        self.dimmed = self.dark && !awake;
+        self.dimmed
+    }
+
+    /// ボタンの押下で元に戻し、減光中だったかを返す
+    ///
+    /// 暗い間の押下は、元に戻している時間を延長する。
+    pub(super) fn wake(&mut self, now: Instant) -> bool {
+        if self.dark {
+            self.woken = Some(now);
+        }
+        std::mem::take(&mut self.dimmed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dim_in_the_dark() {
+        let t0 = Instant::now();
+        let at = |s| t0 + Duration::from_secs(s);
+        let wake = Duration::from_secs(30);
+        let mut dimmer = Dimmer::default();
+        let mut update = |bright, now| dimmer.update(bright, Some(10.0), 5.0, wake, now);
+
+        assert!(!update(50.0, at(0)));
+        assert!(update(9.5, at(1)));
+        assert!(update(14.0, at(2))); // ヒステリシス
+        assert!(!update(15.0, at(3)));
+        assert!(!update(12.0, at(4)));
+
+        // 無効
+        assert!(!dimmer.update(0.0, None, 5.0, wake, at(5)));
+    }
+
+    #[test]
+    fn wake_with_button() {
+        let t0 = Instant::now();
+        let at = |s| t0 + Duration::from_secs(s);
+        let wake = Duration::from_secs(30);
+        let mut dimmer = Dimmer::default();
+
+        assert!(!dimmer.wake(at(0))); // 明るい間は何もしない
+        assert!(dimmer.update(5.0, Some(10.0), 5.0, wake, at(1)));
+        assert!(dimmer.wake(at(2)));
+        assert!(!dimmer.update(5.0, Some(10.0), 5.0, wake, at(3)));
+        assert!(!dimmer.wake(at(20))); // 延長
+        assert!(!dimmer.update(5.0, Some(10.0), 5.0, wake, at(40)));
+        assert!(dimmer.update(5.0, Some(10.0), 5.0, wake, at(50)));
+    }
+}
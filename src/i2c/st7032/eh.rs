@@ -0,0 +1,343 @@
+//! `embedded-hal`のI2Cで動作するST7032
+//!
+//! 初期化、表示の制御、アイコンRAMのコマンド列はLinuxのドライバと共通。std、allocを使わない。
+//! コマンドと表示データは、それぞれコントロールバイトに続けて一度に書き込む。
+//! 外字もLinuxのドライバと同じく初期化時に[Charset::standard]を登録する。
+
+use super::{
+    glyph::Charset, icon_cmds, init_cmds, Control, CMD_CGRAM, CMD_HOME, CMD_NORMAL, ICON_SIZE,
+    REG_DISPLAY, REG_SETTING,
+};
+use core::fmt;
+
+/// 一度に書き込むバイト数の上限（コントロールバイトを含む）
+const BUF_SIZE: usize = 41;
+
+const CMD_CLEAR: u8 = 0x01;
+const CMD_NEWLINE: u8 = 0xc0; // 2行目の先頭へ移動
+
+/// エラー
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    Bus(E), // I2Cの通信エラー
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::Bus(e)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Bus(e) => write!(f, "ST7032: bus error: {e:?}"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for Error<E> {}
+
+/// 液晶ディスプレイ ST7032
+pub struct ST7032<I2C> {
+    i2c: I2C,
+    addr: u8,
+    charset: Charset,
+    control: Control, // 表示中の制御の設定
+}
+
+impl<I2C> ST7032<I2C> {
+    /// 生成
+    pub fn new(i2c: I2C, addr: u8) -> Self {
+        ST7032 {
+            i2c,
+            addr,
+            charset: Charset::standard(),
+            control: Control::default(),
+        }
+    }
+
+    /// I2Cを返して破棄
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+/// コントロールバイトに続けて、`data`から入るだけ並べる
+fn with_control(
+    control: u8,
+    data: &mut impl Iterator<Item = u8>,
+    buf: &mut [u8; BUF_SIZE],
+) -> usize {
+    buf[0] = control;
+    let mut len = 1;
+    for (b, d) in buf[1..].iter_mut().zip(data) {
+        *b = d;
+        len += 1;
+    }
+    len
+}
+
+impl<I2C: embedded_hal::i2c::I2c> ST7032<I2C> {
+    /// 初期化
+    pub fn init<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        control: Control,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.control = control;
+        let (v1, v2) = init_cmds(&control);
+        self.command(&v1)?;
+        delay.delay_ms(200);
+        self.command(&v2)?;
+        delay.delay_ms(1);
+        self.write_cgram()
+    }
+
+    /// 外字の登録
+    pub fn load_charset(&mut self, charset: Charset) -> Result<(), Error<I2C::Error>> {
+        self.charset = charset;
+        self.write_cgram()
+    }
+
+    fn write_cgram(&mut self) -> Result<(), Error<I2C::Error>> {
+        let charset = self.charset;
+        self.command(&[CMD_CGRAM])?;
+        self.data(charset.cgram().iter().copied())?;
+        self.command(&[CMD_HOME])
+    }
+
+    /// 表示の制御（変化した設定のコマンドのみを書き込む）
+    pub fn set_control(&mut self, control: Control) -> Result<(), Error<I2C::Error>> {
+        let (cmds, len) = control.update_cmds(&self.control);
+        if len > 0 {
+            self.command(&cmds[..len])?;
+        }
+        self.control = control;
+        Ok(())
+    }
+
+    /// コントラスト設定
+    pub fn set_contrast(&mut self, contrast: u8) -> Result<(), Error<I2C::Error>> {
+        self.set_control(Control {
+            contrast,
+            ..self.control
+        })
+    }
+
+    /// アイコンRAMの書き込み
+    pub fn write_icons(&mut self, icons: &[u8; ICON_SIZE]) -> Result<(), Error<I2C::Error>> {
+        let (cmds, data) = icon_cmds(icons);
+        self.command(&cmds)?;
+        self.data(data)?;
+        self.command(&[CMD_NORMAL])
+    }
+
+    /// 2行表示
+    pub fn print<D: embedded_hal::delay::DelayNs>(
+        &mut self,
+        line1: &str,
+        line2: Option<&str>,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        let charset = self.charset;
+        self.command(&[CMD_CLEAR])?;
+        delay.delay_ms(1);
+        self.data(charset.encode(line1))?;
+        self.command(&[CMD_NEWLINE])?;
+        if let Some(line) = line2 {
+            self.data(charset.encode(line))?;
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, cmds: &[u8]) -> Result<(), Error<I2C::Error>> {
+        let mut buf = [0; BUF_SIZE];
+        let len = with_control(REG_SETTING, &mut cmds.iter().copied(), &mut buf);
+        self.i2c.write(self.addr, &buf[..len])?;
+        Ok(())
+    }
+
+    /// 表示データ（長い場合は分割して書き込む）
+    fn data(&mut self, data: impl IntoIterator<Item = u8>) -> Result<(), Error<I2C::Error>> {
+        let mut data = data.into_iter().peekable();
+        let mut buf = [0; BUF_SIZE];
+        while data.peek().is_some() {
+            let len = with_control(REG_DISPLAY, &mut data, &mut buf);
+            self.i2c.write(self.addr, &buf[..len])?;
+        }
+        Ok(())
+    }
+}
+
+impl<I2C: embedded_hal_async::i2c::I2c> ST7032<I2C> {
+    /// 初期化（非同期）
+    pub async fn init_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        control: Control,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.control = control;
+        let (v1, v2) = init_cmds(&control);
+        self.command_async(&v1).await?;
+        delay.delay_ms(200).await;
+        self.command_async(&v2).await?;
+        delay.delay_ms(1).await;
+        self.write_cgram_async().await
+    }
+
+    /// 外字の登録（非同期）
+    pub async fn load_charset_async(&mut self, charset: Charset) -> Result<(), Error<I2C::Error>> {
+        self.charset = charset;
+        self.write_cgram_async().await
+    }
+
+    async fn write_cgram_async(&mut self) -> Result<(), Error<I2C::Error>> {
+        let charset = self.charset;
+        self.command_async(&[CMD_CGRAM]).await?;
+        self.data_async(charset.cgram().iter().copied()).await?;
+        self.command_async(&[CMD_HOME]).await
+    }
+
+    /// 表示の制御（非同期）
+    pub async fn set_control_async(&mut self, control: Control) -> Result<(), Error<I2C::Error>> {
+        let (cmds, len) = control.update_cmds(&self.control);
+        if len > 0 {
+            self.command_async(&cmds[..len]).await?;
+        }
+        self.control = control;
+        Ok(())
+    }
+
+    /// コントラスト設定（非同期）
+    pub async fn set_contrast_async(&mut self, contrast: u8) -> Result<(), Error<I2C::Error>> {
+        self.set_control_async(Control {
+            contrast,
+            ..self.control
+        })
+        .await
+    }
+
+    /// アイコンRAMの書き込み（非同期）
+    pub async fn write_icons_async(
+        &mut self,
+        icons: &[u8; ICON_SIZE],
+    ) -> Result<(), Error<I2C::Error>> {
+        let (cmds, data) = icon_cmds(icons);
+        self.command_async(&cmds).await?;
+        self.data_async(data).await?;
+        self.command_async(&[CMD_NORMAL]).await
+    }
+
+    /// 2行表示（非同期）
+    pub async fn print_async<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        line1: &str,
+        line2: Option<&str>,
+        delay: &mut D,
+    ) -> Result<(), Error<I2C::Error>> {
+        let charset = self.charset;
+        self.command_async(&[CMD_CLEAR]).await?;
+        delay.delay_ms(1).await;
+        self.data_async(charset.encode(line1)).await?;
+        self.command_async(&[CMD_NEWLINE]).await?;
+        if let Some(line) = line2 {
+            self.data_async(charset.encode(line)).await?;
+        }
+        Ok(())
+    }
+
+    async fn command_async(&mut self, cmds: &[u8]) -> Result<(), Error<I2C::Error>> {
+        let mut buf = [0; BUF_SIZE];
+        let len = with_control(REG_SETTING, &mut cmds.iter().copied(), &mut buf);
+        self.i2c.write(self.addr, &buf[..len]).await?;
+        Ok(())
+    }
+
+    async fn data_async(
+        &mut self,
+        data: impl IntoIterator<Item = u8>,
+    ) -> Result<(), Error<I2C::Error>> {
+        let mut data = data.into_iter().peekable();
+        let mut buf = [0; BUF_SIZE];
+        while data.peek().is_some() {
+            let len = with_control(REG_DISPLAY, &mut data, &mut buf);
+            self.i2c.write(self.addr, &buf[..len]).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock, Transaction},
+    };
+
+    const ADDR: u8 = 0x3e;
+
+    fn transactions() -> Vec<Transaction> {
+        let charset = Charset::standard();
+        let cgram = charset.cgram();
+        vec![
+            Transaction::write(ADDR, vec![0, 0x38, 0x39, 0x14, 0x70, 0x56, 0x6c]), // コントラスト32
+            Transaction::write(ADDR, vec![0, 0x38, 0x0d, 0x01]),
+            Transaction::write(ADDR, vec![0, 0x40]), // CGRAMへ
+            Transaction::write(ADDR, [&[0x40], &cgram[..40]].concat()), // 40バイトずつ
+            Transaction::write(ADDR, [&[0x40], &cgram[40..]].concat()),
+            Transaction::write(ADDR, vec![0, 0x80]), // DDRAMへ
+            Transaction::write(ADDR, vec![0, 0x01]), // クリア
+            Transaction::write(ADDR, vec![0x40, b'a', 0x00]),
+            Transaction::write(ADDR, vec![0, 0xc0]), // 改行
+            Transaction::write(ADDR, vec![0x40, b' ', b'c']), // 制御文字は空白
+        ]
+    }
+
+    #[test]
+    fn init_and_print() {
+        let mut display = ST7032::new(Mock::new(&transactions()), ADDR);
+        display.init(Control::default(), &mut NoopDelay).unwrap();
+        display.print("a°", Some("\x01c"), &mut NoopDelay).unwrap();
+        display.release().done();
+    }
+
+    #[async_std::test]
+    async fn init_and_print_async() {
+        let mut display = ST7032::new(Mock::new(&transactions()), ADDR);
+        display
+            .init_async(Control::default(), &mut NoopDelay)
+            .await
+            .unwrap();
+        display
+            .print_async("a°", Some("\x01c"), &mut NoopDelay)
+            .await
+            .unwrap();
+        display.release().done();
+    }
+
+    #[test]
+    fn control_and_icons() {
+        let transactions = [
+            Transaction::write(ADDR, vec![0, 0x39, 0x74, 0x57, 0x6c, 0x38]), // 拡張命令セットで設定
+            Transaction::write(ADDR, vec![0, 0x08]),                         // 表示オフ
+            Transaction::write(ADDR, vec![0, 0x39, 0x40]),                   // アイコンRAMへ
+            Transaction::write(ADDR, [&[0x40], &[0x1f; 16][..]].concat()),
+            Transaction::write(ADDR, vec![0, 0x38]),
+        ];
+        let mut display = ST7032::new(Mock::new(&transactions), ADDR);
+        display.set_contrast(52).unwrap(); // 上位2ビットが変化
+        display.set_contrast(52).unwrap(); // 変化なし
+        display
+            .set_control(Control {
+                contrast: 52,
+                display: false,
+                blink: false,
+                ..Default::default()
+            })
+            .unwrap();
+        display.write_icons(&[0xff; 16]).unwrap();
+        display.release().done();
+    }
+}
@@ -0,0 +1,133 @@
+//! DDRAMの影
+//!
+//! 表示中の2x16文字を保持し、新しい表示内容と異なる文字だけを書き込むバイト列を作る。
+//! 画面をクリアしないためちらつかず、バスを占有する時間も短い。
+//!
+//! 書き込みは1回の転送にまとめる。コントロールバイトのCoビットを立てると1バイトごとに
+//! コントロールバイトが続き、Coビットが0のコントロールバイトの後はすべて同じ種類のバイトになる。
+//! 変化した範囲ごとにDDRAMアドレスを設定し、最後の範囲のみ表示データを続けて書き込む。
+
+/// 1行の文字数
+pub const COLS: usize = 16;
+
+/// 行数
+pub const ROWS: usize = 2;
+
+const CONTROL_COMMAND: u8 = 0x80; // Co = 1、RS = 0
+const CONTROL_DATA: u8 = 0xc0; // Co = 1、RS = 1
+const CONTROL_DATA_LAST: u8 = 0x40; // Co = 0、RS = 1
+const CMD_DDRAM: u8 = 0x80; // DDRAMアドレス設定
+
+/// この数以下の変化していない文字を挟む範囲は、まとめて書き込む
+const MERGE_GAP: usize = 2;
+
+/// 2x16文字の表示内容
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    cells: [[u8; COLS]; ROWS],
+}
+
+impl Frame {
+    /// 空白のみ（クリア直後のDDRAM）
+    pub fn blank() -> Self {
+        Frame {
+            cells: [[b' '; COLS]; ROWS],
+        }
+    }
+
+    /// 各行の表示データから生成（16文字に満たない部分は空白、超える部分は切り捨てる）
+    pub fn new(line1: &[u8], line2: &[u8]) -> Self {
+        let mut frame = Frame::blank();
+        for (row, line) in frame.cells.iter_mut().zip([line1, line2]) {
+            for (cell, c) in row.iter_mut().zip(line) {
+                *cell = *c;
+            }
+        }
+        frame
+    }
+
+    /// `prev`から変化した文字の範囲（行、先頭の列、表示データ）
+    fn changes<'a>(&'a self, prev: &Frame) -> Vec<(usize, usize, &'a [u8])> {
+        let mut spans = Vec::new();
+        for (row, (new, old)) in self.cells.iter().zip(prev.cells.iter()).enumerate() {
+            let mut span: Option<(usize, usize)> = None; // (先頭, 末尾)
+            for col in (0..COLS).filter(|&c| new[c] != old[c]) {
+                span = match span {
+                    Some((start, end)) if col - end - 1 <= MERGE_GAP => Some((start, col)),
+                    Some((start, end)) => {
+                        spans.push((row, start, &new[start..=end]));
+                        Some((col, col))
+                    }
+                    None => Some((col, col)),
+                };
+            }
+            if let Some((start, end)) = span {
+                spans.push((row, start, &new[start..=end]));
+            }
+        }
+        spans
+    }
+
+    /// `prev`から変化した文字を書き込むバイト列（コントロールバイトを含む）
+    ///
+    /// 変化が無い場合は空。
+    pub fn update(&self, prev: &Frame) -> Vec<u8> {
+        let spans = self.changes(prev);
+        let mut buf = Vec::new();
+        for (i, (row, col, data)) in spans.iter().enumerate() {
+            let addr = (row * 0x40 + col) as u8;
+            buf.extend([CONTROL_COMMAND, CMD_DDRAM | addr]);
+            if i + 1 == spans.len() {
+                buf.push(CONTROL_DATA_LAST);
+                buf.extend_from_slice(data);
+            } else {
+                buf.extend(data.iter().flat_map(|c| [CONTROL_DATA, *c]));
+            }
+        }
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_frame() {
+        let frame = Frame::new(b"abc", b"0123456789abcdefXYZ");
+        assert_eq!(&frame.cells[0], b"abc             ");
+        assert_eq!(&frame.cells[1], b"0123456789abcdef"); // 16文字まで
+    }
+
+    #[test]
+    fn update_changed_cells() {
+        let blank = Frame::blank();
+        assert!(blank.update(&blank).is_empty());
+
+        // 1か所のみ
+        let frame = Frame::new(b"23.50", b"");
+        assert_eq!(
+            frame.update(&blank),
+            [&[0x80, 0x80, 0x40][..], b"23.50"].concat()
+        );
+
+        // 離れた範囲はアドレスを設定し直し、最後の範囲以外は1バイトごとにコントロールバイトを付ける
+        let next = Frame::new(b"23.75", b"x");
+        assert_eq!(
+            next.update(&frame),
+            vec![0x80, 0x83, 0xc0, b'7', 0xc0, b'5', 0x80, 0xc0, 0x40, b'x']
+        );
+
+        // 近い範囲はまとめる
+        let next = Frame::new(b"13.60", b"");
+        assert_eq!(
+            next.update(&frame),
+            [&[0x80, 0x80, 0x40][..], b"13.6"].concat()
+        );
+        let next = Frame::new(b"13.50 1", b"");
+        assert_eq!(
+            next.update(&frame),
+            vec![0x80, 0x80, 0xc0, b'1', 0x80, 0x86, 0x40, b'1']
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! CGRAMに登録する外字
+//!
+//! ST7032は5x8ドットの外字を8文字までCGRAMに登録でき、文字コード0〜7で表示する。
+//! [Charset]は外字とそれに対応するUnicode文字の組で、文字列を表示データに変換する。
+
+use core::fmt;
+
+/// 5x8ドットの外字（上の行から順に、各行の下位5ビット）
+pub type Glyph = [u8; 8];
+
+/// 登録できる外字の数
+pub const MAX_GLYPHS: usize = 8;
+
+/// 全点灯のブロック（文字コード0xff）
+pub const FULL_BLOCK: char = '█';
+const FULL_BLOCK_CODE: u8 = 0xff;
+
+/// 度
+pub const DEGREE: Glyph = [0b00110, 0b01001, 0b01001, 0b00110, 0, 0, 0, 0];
+
+/// 上向きの矢印
+pub const ARROW_UP: Glyph = [
+    0b00100, 0b01110, 0b10101, 0b00100, 0b00100, 0b00100, 0b00100, 0,
+];
+
+/// 下向きの矢印
+pub const ARROW_DOWN: Glyph = [
+    0b00100, 0b00100, 0b00100, 0b00100, 0b10101, 0b01110, 0b00100, 0,
+];
+
+/// 左から1〜4列を点灯した横棒の部分ブロック
+pub const BARS: [Glyph; 4] = [bar(1), bar(2), bar(3), bar(4)];
+pub const BAR_CHARS: [char; 4] = ['▏', '▎', '▍', '▌'];
+
+/// 下から1〜7行を点灯したスパークラインの段階
+pub const LEVELS: [Glyph; 7] = [
+    level(1),
+    level(2),
+    level(3),
+    level(4),
+    level(5),
+    level(6),
+    level(7),
+];
+pub const LEVEL_CHARS: [char; 7] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇'];
+
+const fn bar(cols: u32) -> Glyph {
+    [(0x1f << (5 - cols)) & 0x1f; 8]
+}
+
+const fn level(rows: usize) -> Glyph {
+    let mut glyph = [0; 8];
+    let mut i = 8 - rows;
+    while i < 8 {
+        glyph[i] = 0x1f;
+        i += 1;
+    }
+    glyph
+}
+
+/// 組み込みの外字とそのUnicode文字
+pub fn builtin() -> [(char, Glyph); 14] {
+    let mut glyphs = [('°', DEGREE), ('↑', ARROW_UP), ('↓', ARROW_DOWN)]
+        .into_iter()
+        .chain(BAR_CHARS.into_iter().zip(BARS))
+        .chain(LEVEL_CHARS.into_iter().zip(LEVELS));
+    core::array::from_fn(|_| glyphs.next().unwrap())
+}
+
+/// 外字の組が不正
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharsetError {
+    TooMany(usize),  // `MAX_GLYPHS`を超えている
+    Conflict(char),  // CGROMの文字と重なる
+    Duplicate(char), // 同じ文字が複数ある
+}
+
+impl fmt::Display for CharsetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CharsetError::TooMany(n) => write!(f, "ST7032: too many glyphs: {n}"),
+            CharsetError::Conflict(c) => write!(f, "ST7032: glyph '{c}' conflicts with CGROM"),
+            CharsetError::Duplicate(c) => write!(f, "ST7032: duplicate glyph '{c}'"),
+        }
+    }
+}
+
+impl core::error::Error for CharsetError {}
+
+/// 外字の組
+///
+/// allocを使わないよう、[MAX_GLYPHS]文字分の配列の先頭`len`個を使う。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Charset {
+    chars: [char; MAX_GLYPHS],   // 文字コード順
+    glyphs: [Glyph; MAX_GLYPHS], // 文字コード順
+    len: usize,
+}
+
+impl Charset {
+    /// 生成
+    ///
+    /// 外字は先頭から文字コード0、1、...に割り当てる。
+    pub fn new(glyphs: &[(char, Glyph)]) -> Result<Self, CharsetError> {
+        if glyphs.len() > MAX_GLYPHS {
+            return Err(CharsetError::TooMany(glyphs.len()));
+        }
+        for (i, (c, _)) in glyphs.iter().enumerate() {
+            if c.is_ascii() || *c == FULL_BLOCK {
+                return Err(CharsetError::Conflict(*c));
+            }
+            if glyphs[..i].iter().any(|(d, _)| d == c) {
+                return Err(CharsetError::Duplicate(*c));
+            }
+        }
+        Ok(Self::from_slice(glyphs))
+    }
+
+    /// 度、矢印、横棒グラフ
+    pub fn standard() -> Self {
+        Self::from_slice(&builtin()[..7])
+    }
+
+    /// 度、スパークライン
+    pub fn sparkline() -> Self {
+        let builtin = builtin();
+        let mut glyphs = [builtin[0]; 8];
+        glyphs[1..].copy_from_slice(&builtin[7..]);
+        Self::from_slice(&glyphs)
+    }
+
+    /// 確認済みの外字から生成
+    fn from_slice(glyphs: &[(char, Glyph)]) -> Self {
+        let mut charset = Charset {
+            chars: ['\0'; MAX_GLYPHS],
+            glyphs: [[0; 8]; MAX_GLYPHS],
+            len: glyphs.len(),
+        };
+        for (i, (c, glyph)) in glyphs.iter().enumerate() {
+            charset.chars[i] = *c;
+            charset.glyphs[i] = *glyph;
+        }
+        charset
+    }
+
+    /// CGRAMに書き込むデータ（文字コード0から順に8バイトずつ）
+    pub fn cgram(&self) -> &[u8] {
+        self.glyphs[..self.len].as_flattened()
+    }
+
+    /// 表示データに変換
+    ///
+    /// 外字は文字コード0〜7、[FULL_BLOCK]は0xffとし、
+    /// 表示できないASCII以外の文字と制御文字は空白にする。
+    pub fn encode<'a>(&'a self, s: &'a str) -> impl Iterator<Item = u8> + 'a {
+        s.chars().map(|c| {
+            if let Some(i) = self.chars[..self.len].iter().position(|d| *d == c) {
+                i as u8
+            } else if c == FULL_BLOCK {
+                FULL_BLOCK_CODE
+            } else if c.is_ascii_graphic() {
+                c as u8
+            } else {
+                b' '
+            }
+        })
+    }
+}
+
+impl Default for Charset {
+    fn default() -> Self {
+        Charset::standard()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs() {
+        assert_eq!(BARS[0], [0b10000; 8]);
+        assert_eq!(BARS[3], [0b11110; 8]);
+        assert_eq!(LEVELS[0], [0, 0, 0, 0, 0, 0, 0, 0x1f]);
+        assert_eq!(LEVELS[6], [0, 0x1f, 0x1f, 0x1f, 0x1f, 0x1f, 0x1f, 0x1f]);
+        assert_eq!(builtin().len(), 14);
+    }
+
+    #[test]
+    fn encode() {
+        let charset = Charset::standard();
+        let encode = |charset: &Charset, s: &str| charset.encode(s).collect::<Vec<u8>>();
+        assert_eq!(encode(&charset, "23.5°C"), b"23.5\x00C");
+        assert_eq!(
+            encode(&charset, "↑↓██▍\x01é"),
+            [1, 2, 0xff, 0xff, 5, b' ', b' ']
+        );
+        assert_eq!(encode(&Charset::sparkline(), "▁▇"), [1, 7]);
+        assert_eq!(charset.cgram().len(), 7 * 8);
+        assert_eq!(&charset.cgram()[..8], &DEGREE);
+    }
+
+    #[test]
+    fn invalid_charset() {
+        assert!(Charset::new(&builtin()[..8]).is_ok());
+        assert!(Charset::new(&builtin()[..9]).is_err());
+        assert!(Charset::new(&[('°', DEGREE), ('°', ARROW_UP)]).is_err());
+        assert!(Charset::new(&[('C', DEGREE)]).is_err());
+        assert!(Charset::new(&[(FULL_BLOCK, DEGREE)]).is_err());
+    }
+}
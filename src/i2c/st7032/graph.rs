@@ -0,0 +1,91 @@
+//! 外字を使った簡易グラフ
+//!
+//! 横棒グラフは[`Charset::standard`]、スパークラインは[`Charset::sparkline`]の外字で表示する。
+//!
+//! [`Charset::standard`]: super::glyph::Charset::standard
+//! [`Charset::sparkline`]: super::glyph::Charset::sparkline
+
+use super::glyph::{BAR_CHARS, FULL_BLOCK, LEVEL_CHARS};
+
+/// 1文字あたりの横方向のドット数
+const COLUMNS: usize = 5;
+
+/// 横棒グラフ
+///
+/// `min`から`max`までの`value`の位置を、`width`文字の棒の長さで表す。
+/// 端数は1列単位の部分ブロックで表し、残りは空白で埋める。
+pub fn bar(value: f64, min: f64, max: f64, width: usize) -> String {
+    let ratio = if max > min && !value.is_nan() {
+        ((value - min) / (max - min)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let cols = (ratio * (width * COLUMNS) as f64).round() as usize;
+
+    let mut s: String = std::iter::repeat_n(FULL_BLOCK, cols / COLUMNS).collect();
+    if let Some(c) = (cols % COLUMNS).checked_sub(1) {
+        s.push(BAR_CHARS[c]);
+    }
+    format!("{s:<width$}")
+}
+
+/// スパークライン
+///
+/// 直近の`width`個の値を、その最小値から最大値までの8段階の高さで表す。
+/// 新しい値を右端とし、値が足りない左側は空白で埋める。
+pub fn sparkline(values: &[f64], width: usize) -> String {
+    let values = &values[values.len().saturating_sub(width)..];
+    let (min, max) = values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+            (min.min(*v), max.max(*v))
+        });
+
+    let s: String = values
+        .iter()
+        .map(|v| {
+            if !v.is_finite() {
+                ' '
+            } else if max > min {
+                match ((v - min) / (max - min) * 7.0).round() as usize {
+                    7 => FULL_BLOCK,
+                    n => LEVEL_CHARS[n],
+                }
+            } else {
+                LEVEL_CHARS[0]
+            }
+        })
+        .collect();
+    format!("{s:>width$}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bar_graph() {
+        assert_eq!(bar(0.0, 0.0, 100.0, 4), "    ");
+        assert_eq!(bar(50.0, 0.0, 100.0, 4), "██  ");
+        assert_eq!(bar(60.0, 0.0, 100.0, 4), "██▎ "); // 12列
+        assert_eq!(bar(95.0, 0.0, 100.0, 4), "███▌");
+        assert_eq!(bar(150.0, 0.0, 100.0, 4), "████");
+        assert_eq!(bar(-10.0, 0.0, 100.0, 4), "    ");
+        assert_eq!(bar(f64::NAN, 0.0, 100.0, 2), "  ");
+        assert_eq!(bar(1.0, 1.0, 1.0, 2), "  ");
+    }
+
+    #[test]
+    fn sparkline_graph() {
+        assert_eq!(sparkline(&[], 3), "   ");
+        assert_eq!(sparkline(&[400.0, 500.0], 4), "  ▁█");
+        assert_eq!(
+            sparkline(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0], 8),
+            "▁▂▃▄▅▆▇█"
+        );
+        assert_eq!(sparkline(&[9.0, 0.0, 7.0, 3.5], 3), "▁█▅"); // 直近3個
+        assert_eq!(sparkline(&[5.0, 5.0], 2), "▁▁");
+        assert_eq!(sparkline(&[1.0, f64::NAN, 3.0], 3), "▁ █");
+    }
+}
@@ -0,0 +1,883 @@
+//! Linuxの[I2cBus]で動作するST7032
+
+#[allow(unused_imports)]
+use async_std::prelude::*;
+
+use super::{
+    dim::Dimmer,
+    frame::Frame,
+    glyph::Charset,
+    graph, icon_cmds, init_cmds,
+    page::{self, Daily, Pager},
+    Control, CMD_CGRAM, CMD_HOME, CMD_NORMAL, ICON_SIZE, REG_DISPLAY, REG_SETTING,
+};
+use crate::{
+    alarm::Alarms,
+    button::{Button, Press},
+    config::{self, DarkMode, Page, SharedConfig},
+    hal::I2cBus,
+    i2c::Runner,
+    perror,
+    shutdown::Token,
+    Air, Ccs811State, DbState, DbStatus, EResult, Temperature,
+};
+use async_std::{
+    sync::Mutex,
+    task::{self, JoinHandle},
+};
+use futures::{pin_mut, select, FutureExt};
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+impl From<&config::ST7032> for Control {
+    fn from(conf: &config::ST7032) -> Self {
+        Control {
+            contrast: conf.contrast,
+            booster: conf.booster,
+            follower: conf.follower,
+            icon: conf.icon,
+            display: true,
+            cursor: conf.cursor,
+            blink: conf.blink,
+        }
+    }
+}
+
+/// 外字をCGRAMに書き込み、DDRAMへの書き込みに戻す
+fn write_cgram<B: I2cBus>(charset: &Charset, bus: &mut B) -> EResult<()> {
+    bus.smbus_write_byte(REG_SETTING, CMD_CGRAM)?;
+    bus.write(&[&[REG_DISPLAY], charset.cgram()].concat())?;
+    bus.smbus_write_byte(REG_SETTING, CMD_HOME)
+}
+
+/// 液晶ディスプレイ ST7032
+pub struct ST7032<T> {
+    shutdown: Token,
+    config: SharedConfig,
+    addr: u16,
+    air: Air,               // 気温（センサごと）、CO2、TVOC
+    bright: Arc<AtomicU64>, // 明るさ
+    alarms: Alarms,         // 温度アラーム
+    button: Button,         // ページの切り替え
+    db: DbStatus,           // DBへの保存の状況
+    started: Instant,       // 起動時刻
+    slot: usize,            // 表示中の温度センサ
+    pager: Pager,           // 表示中のページ
+    daily: Daily,           // 今日の最小・最大
+    charset: Charset,       // CGRAMの外字
+    frame: Frame,           // 表示中のDDRAM
+    control: Control,       // 表示中の制御の設定
+    dimmer: Dimmer,         // 暗い部屋での減光
+    _state: PhantomData<T>, // 型状態
+}
+
+/// 初期化前
+pub struct Uninit {}
+
+/// 初期化済み
+pub struct Initialized {}
+
+impl ST7032<Uninit> {
+    /// 生成
+    ///
+    /// `air`、`bright`、`db`は周期的な実行時に設定の`pages`のページとして表示する値、
+    /// `started`は稼働時間の起点。
+    /// 温度センサが複数ある場合は、更新ごとに名前と温度を順に表示する。
+    /// アラームの発生中はページに関わらず気温を表示し、明るさの代わりにアラームを表示する。
+    /// 設定の`dark_below`を指定すると、`bright`が暗い間は減光する（アラームの発生中を除く）。
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        shutdown: Token,
+        config: SharedConfig,
+        air: Air,
+        bright: Arc<AtomicU64>,
+        alarms: Alarms,
+        button: Button,
+        db: DbStatus,
+        started: Instant,
+    ) -> ST7032<Uninit> {
+        let addr = config.read().unwrap().st7032.addr;
+        ST7032 {
+            shutdown,
+            config,
+            addr,
+            air,
+            bright,
+            alarms,
+            button,
+            db,
+            started,
+            slot: 0,
+            pager: Pager::new(Instant::now()),
+            daily: Daily::default(),
+            charset: Charset::standard(),
+            frame: Frame::blank(),
+            control: Control::default(),
+            dimmer: Dimmer::default(),
+            _state: PhantomData,
+        }
+    }
+
+    /// 初期化
+    ///
+    /// 標準の外字（[`Charset::standard`]）も登録する。
+    pub async fn init<B: I2cBus>(self, bus: &Arc<Mutex<B>>) -> EResult<ST7032<Initialized>> {
+        let control = Control::from(&self.config.read().unwrap().st7032);
+        let (v1, v2) = init_cmds(&control);
+
+        {
+            let mut guard = bus.lock().await;
+            guard.set_slave_address(self.addr)?;
+            guard.write(&[&[REG_SETTING][..], &v1].concat())?;
+        }
+
+        task::sleep(Duration::from_millis(200)).await;
+
+        {
+            let mut guard = bus.lock().await;
+            guard.set_slave_address(self.addr)?;
+            guard.write(&[&[REG_SETTING][..], &v2].concat())?;
+        }
+
+        task::sleep(Duration::from_millis(1)).await;
+
+        {
+            let mut guard = bus.lock().await;
+            guard.set_slave_address(self.addr)?;
+            write_cgram(&self.charset, &mut *guard)?;
+        }
+
+        Ok(ST7032 {
+            shutdown: self.shutdown,
+            config: self.config,
+            addr: self.addr,
+            air: self.air,
+            bright: self.bright,
+            alarms: self.alarms,
+            button: self.button,
+            db: self.db,
+            started: self.started,
+            slot: self.slot,
+            pager: self.pager,
+            daily: self.daily,
+            charset: self.charset,
+            frame: Frame::blank(), // 初期化コマンドでクリア済み
+            control,
+            dimmer: self.dimmer,
+            _state: PhantomData,
+        })
+    }
+}
+
+impl<T> ST7032<T> {
+    /// 1行目の表示内容
+    ///
+    /// 温度センサが複数ある場合は、呼び出すごとに次のセンサを表示する。
+    /// 未測定の気温は`---`と表示する。
+    fn first_line(&mut self) -> String {
+        let celsius = |t: &Temperature| match t.measured() {
+            Some(c) => format!("{c:.2}"),
+            None => "---".to_string(),
+        };
+        let temps = &self.air.temps;
+        match temps.len() {
+            0 => "0.00°C".to_string(),
+            1 => format!("{}°C", celsius(&temps[0])),
+            n => {
+                let temp = &temps[self.slot % n];
+                self.slot = (self.slot + 1) % n;
+                format!("{:<8.8}{:>6}°C", temp.name, celsius(temp)) // 16文字
+            }
+        }
+    }
+
+    /// 2行目の表示内容
+    ///
+    /// 明るさを数値と横棒グラフで表示する。
+    /// アラームの発生中は優先度の最も高いものを表示する。
+    fn second_line(&self) -> String {
+        match self.alarms.active().first() {
+            Some(kind) => format!("ALARM {kind}"),
+            None => {
+                let bright = f64::from_bits(self.bright.load(Ordering::Relaxed));
+                let bar = graph::bar(bright, 0.0, 100.0, 9);
+                format!("{bright:>5.1}% {bar}") // 16文字
+            }
+        }
+    }
+
+    /// ページの表示内容
+    fn page_lines(&mut self, page: Page) -> (String, String) {
+        match page {
+            Page::Temperature => (self.first_line(), self.second_line()),
+            Page::Co2 => self.co2_lines(),
+            Page::MinMax => self.min_max_lines(),
+            Page::Status => self.status_lines(),
+            Page::Uptime => self.uptime_lines(),
+        }
+    }
+
+    /// CO2とTVOC
+    ///
+    /// CCS811が`Ready`以外の間は2行目の末尾に`*`を表示する。
+    fn co2_lines(&self) -> (String, String) {
+        let line1 = match self.air.co2.load(Ordering::Relaxed) {
+            0 => "CO2   --- ppm".to_string(),
+            co2 => format!("CO2 {co2:>5} ppm"),
+        };
+        let mark = match self.air.ccs811_state() {
+            Some(state) if state != Ccs811State::Ready => " *",
+            _ => "",
+        };
+        let tvoc = self.air.tvoc.load(Ordering::Relaxed);
+        (line1, format!("TVOC{tvoc:>5} ppb{mark}"))
+    }
+
+    /// 今日の気温とCO2の最小・最大
+    fn min_max_lines(&self) -> (String, String) {
+        let line1 = match self.daily.temp {
+            Some((min, max)) => format!("↓{min:>5.1} ↑{max:>5.1}°C"),
+            None => "↓  --- ↑  ---°C".to_string(),
+        };
+        let line2 = match self.daily.co2 {
+            Some((min, max)) => format!("↓{min:>5} ↑{max:>5}ppm"),
+            None => "↓  --- ↑  ---ppm".to_string(),
+        };
+        (line1, line2)
+    }
+
+    /// ネットワークとDBの状況
+    fn status_lines(&self) -> (String, String) {
+        let line1 = match page::local_ip() {
+            Some(ip) => ip.to_string(),
+            None => "no network".to_string(),
+        };
+        let line2 = match (self.db.state(), self.db.last_insert()) {
+            (DbState::Disconnected, _) => "DB off".to_string(),
+            (DbState::Connected, Some(t)) => {
+                let elapsed = SystemTime::now().duration_since(t).unwrap_or_default();
+                format!("DB ok {} ago", page::ago(elapsed))
+            }
+            (DbState::Connected, None) => "DB connected".to_string(),
+            (DbState::Failed, _) => "DB error".to_string(),
+        };
+        (line1, line2)
+    }
+
+    /// プログラムとOSの稼働時間
+    fn uptime_lines(&self) -> (String, String) {
+        let line1 = format!("up  {}", page::duration(self.started.elapsed()));
+        let line2 = match page::system_uptime() {
+            Some(d) => format!("sys {}", page::duration(d)),
+            None => "sys --".to_string(),
+        };
+        (line1, line2)
+    }
+
+    /// 今日の最小・最大に現在の測定値を反映
+    fn update_daily(&mut self) {
+        let temp = self.air.temps.first().and_then(|t| t.measured());
+        let co2 = match self.air.co2.load(Ordering::Relaxed) {
+            0 => None,
+            co2 => Some(co2),
+        };
+        self.daily.update(page::today(), temp, co2);
+    }
+}
+
+impl ST7032<Initialized> {
+    /// 外字の登録
+    ///
+    /// 以降の表示では`charset`の文字を外字に変換する。
+    pub async fn load_charset<B: I2cBus>(
+        &mut self,
+        charset: Charset,
+        bus: &Arc<Mutex<B>>,
+    ) -> EResult<()> {
+        {
+            let mut guard = bus.lock().await;
+            guard.set_slave_address(self.addr)?;
+            write_cgram(&charset, &mut *guard)?;
+        }
+        self.charset = charset;
+        Ok(())
+    }
+
+    /// 表示の制御
+    ///
+    /// 変化した設定のコマンドのみを書き込む。
+    pub async fn set_control<B: I2cBus>(
+        &mut self,
+        control: Control,
+        bus: &Arc<Mutex<B>>,
+    ) -> EResult<()> {
+        let (cmds, len) = control.update_cmds(&self.control);
+        if len > 0 {
+            {
+                let mut guard = bus.lock().await;
+                guard.set_slave_address(self.addr)?;
+                guard.write(&[&[REG_SETTING], &cmds[..len]].concat())?;
+            }
+            task::sleep(Duration::from_millis(1)).await;
+        }
+        self.control = control;
+        Ok(())
+    }
+
+    /// 表示中の制御の設定
+    pub fn control(&self) -> Control {
+        self.control
+    }
+
+    /// アイコンRAMの書き込み
+    ///
+    /// 表示するには[`Control::icon`]も設定する。
+    pub async fn write_icons<B: I2cBus>(
+        &self,
+        icons: &[u8; ICON_SIZE],
+        bus: &Arc<Mutex<B>>,
+    ) -> EResult<()> {
+        let (cmds, data) = icon_cmds(icons);
+        let mut guard = bus.lock().await;
+        guard.set_slave_address(self.addr)?;
+        guard.write(&[&[REG_SETTING][..], &cmds].concat())?;
+        guard.write(&[&[REG_DISPLAY], data.as_slice()].concat())?;
+        guard.smbus_write_byte(REG_SETTING, CMD_NORMAL)
+    }
+
+    /// 2行表示
+    ///
+    /// 表示中の内容から変化した文字のみを、1回の転送で書き込む。
+    /// 画面をクリアしないためちらつかず、バスを占有する時間も短い。
+    pub async fn print<B: I2cBus>(
+        &mut self,
+        line1: &str,
+        line2: Option<&str>,
+        bus: &Arc<Mutex<B>>,
+    ) -> EResult<()> {
+        let line2 = line2.unwrap_or_default();
+        let line1: Vec<u8> = self.charset.encode(line1).collect();
+        let line2: Vec<u8> = self.charset.encode(line2).collect();
+        let frame = Frame::new(&line1, &line2);
+        let buf = frame.update(&self.frame);
+        if !buf.is_empty() {
+            let mut guard = bus.lock().await;
+            guard.set_slave_address(self.addr)?;
+            guard.write(&buf)?;
+        }
+        self.frame = frame;
+        Ok(())
+    }
+}
+
+impl<B: I2cBus> Runner<B> for ST7032<Initialized> {
+    fn run(mut self, bus: Arc<Mutex<B>>) -> EResult<JoinHandle<EResult<()>>> {
+        let f = async move {
+            if let Err(e) = self.print("init ...", None, &bus).await {
+                perror!(e);
+                return Err(e);
+            }
+
+            let mut dimmed = false;
+            let alarm_rx = self.alarms.subscribe();
+            let button_rx = self.button.subscribe();
+
+            loop {
+                let conf = self.config.read().unwrap().st7032.clone();
+                let pages = conf.pages.len();
+
+                // タイムアウトかアラームの変化かボタンの押下まで待つ。シグナルを受信したら終了
+                let (exit, press) = {
+                    let cancelled = self.shutdown.cancelled().fuse();
+                    let alarm = alarm_rx.recv().fuse();
+                    let button = button_rx.recv().fuse();
+                    let wait = task::sleep(config::millis(conf.interval_ms)).fuse();
+                    pin_mut!(cancelled, alarm, button, wait);
+
+                    select!(
+                        _ = cancelled => (true, None),
+                        _ = alarm => (false, None), // すぐに表示を更新
+                        press = button => (false, press.ok()),
+                        _ = wait => (false, None),
+                    )
+                };
+                if exit {
+                    println!("exiting ST7032 ...");
+                    break;
+                }
+
+                // ページの切り替え（減光中の押下は元に戻すのみ）
+                let now = Instant::now();
+                match press.filter(|_| !self.dimmer.wake(now)) {
+                    Some(Press::Short) => self.pager.next(pages, now),
+                    Some(Press::Long) => {
+                        let verb = if self.pager.toggle_pin(now) {
+                            "pinned"
+                        } else {
+                            "unpinned"
+                        };
+                        println!("ST7032: page {verb}");
+                    }
+                    None => {
+                        self.pager
+                            .tick(pages, Duration::from_millis(conf.page_interval_ms), now)
+                    }
+                }
+
+                // 設定の再読み込みと明るさによる表示の制御
+                let bright = f64::from_bits(self.bright.load(Ordering::Relaxed));
+                let dark_below = conf.dark_below.filter(|_| self.alarms.active().is_empty());
+                let wake = Duration::from_millis(conf.wake_ms);
+                let dark = self
+                    .dimmer
+                    .update(bright, dark_below, conf.dark_hysteresis, wake, now);
+                if dark != dimmed {
+                    println!("ST7032: {}", if dark { "dimmed" } else { "restored" });
+                    dimmed = dark;
+                }
+                let mut control = Control::from(&conf);
+                if dimmed {
+                    match conf.dark_mode {
+                        DarkMode::Dim => control.contrast = conf.dim_contrast,
+                        DarkMode::Off => control.display = false,
+                    }
+                }
+                if let Err(e) = self.set_control(control, &bus).await {
+                    perror!(e);
+                    return Err(e);
+                }
+
+                self.update_daily();
+                let page = if self.alarms.active().is_empty() {
+                    conf.pages[self.pager.current(pages)]
+                } else {
+                    Page::Temperature
+                };
+                let (line1, line2) = self.page_lines(page);
+
+                if let Err(e) = self.print(&line1, Some(&line2), &bus).await {
+                    perror!(e);
+                    return Err(e);
+                }
+            }
+
+            Ok(())
+        };
+
+        Ok(task::spawn(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::st7032::glyph;
+    use crate::{
+        alarm::{Event, Kind},
+        hal::fake::{FakeI2c, Write},
+        shutdown::{Shutdown, Stage},
+    };
+    use std::sync::{atomic::AtomicU16, RwLock};
+
+    const ADDR: u16 = 0x3e;
+
+    fn new_display(shutdown: &Shutdown, air: Air, alarms: Alarms) -> ST7032<Uninit> {
+        let token = shutdown.token(Stage::Consumer, "ST7032");
+        ST7032::new(
+            token,
+            Default::default(),
+            air,
+            Default::default(),
+            alarms,
+            Button::new(),
+            DbStatus::default(),
+            Instant::now(),
+        )
+    }
+
+    /// 書き込みをDDRAMの1行目に反映
+    fn apply(row: &mut [u8; 16], writes: Vec<Write>) {
+        for w in writes {
+            let buf: Vec<u8> = w.reg.into_iter().chain(w.data).collect();
+            let (mut i, mut addr) = (0, 0);
+            while i < buf.len() {
+                let control = buf[i];
+                let rest = &buf[i + 1..];
+                let n = if control & 0x80 != 0 {
+                    rest.len().min(1)
+                } else {
+                    rest.len()
+                };
+                for c in &rest[..n] {
+                    if control & 0x40 != 0 {
+                        if let Some(cell) = row.get_mut(addr) {
+                            *cell = *c;
+                        }
+                        addr += 1;
+                    } else if c & 0x80 != 0 {
+                        addr = (c & 0x7f) as usize;
+                    }
+                }
+                i += 1 + n;
+            }
+        }
+    }
+
+    /// 表示の更新を待ち、書き込みを反映した1行目
+    async fn shown(row: &mut [u8; 16], fake: &FakeI2c) -> String {
+        task::sleep(Duration::from_millis(50)).await;
+        apply(row, fake.writes());
+        fake.clear_writes();
+        String::from_utf8(row.to_vec()).unwrap()
+    }
+
+    fn temp(name: &str, celsius: f64) -> Temperature {
+        Temperature {
+            name: name.to_string(),
+            value: Arc::new(AtomicU64::new(celsius.to_bits())),
+        }
+    }
+
+    #[async_std::test]
+    async fn init_and_print() {
+        let shutdown = Shutdown::new();
+        let fake = FakeI2c::new();
+        let bus = Arc::new(Mutex::new(fake.clone()));
+
+        let mut display = new_display(&shutdown, Default::default(), Alarms::new())
+            .init(&bus)
+            .await
+            .unwrap();
+
+        let writes = fake.writes();
+        assert_eq!(writes.len(), 5);
+        assert!(writes.iter().all(|w| w.addr == ADDR));
+        assert_eq!(writes[0].data, vec![0x38, 0x39, 0x14, 0x70, 0x56, 0x6c]); // コントラスト32
+        assert_eq!(writes[1].data, vec![0x38, 0x0d, 0x01]);
+        assert_eq!(writes[2].data, vec![0x40]); // CGRAMへ
+        assert_eq!(writes[3].reg, Some(0x40));
+        assert_eq!(writes[3].data, Charset::standard().cgram());
+        assert_eq!(writes[4].data, vec![0x80]); // DDRAMへ
+
+        // 変化した文字のみを1回で書き込む（制御文字は空白なので変化なし）
+        fake.clear_writes();
+        display.print("a°", Some("\x01c"), &bus).await.unwrap();
+        let writes = fake.writes();
+        assert_eq!(writes.len(), 1);
+        assert_eq!(writes[0].reg, Some(0x80));
+        assert_eq!(
+            writes[0].data,
+            vec![0x80, 0xc0, b'a', 0xc0, 0x00, 0x80, 0xc1, 0x40, b'c'] // 0x00は外字
+        );
+
+        fake.clear_writes();
+        display.print("a°", Some(" c"), &bus).await.unwrap();
+        assert!(fake.writes().is_empty());
+
+        display.print("b°", None, &bus).await.unwrap();
+        let writes = fake.writes();
+        assert_eq!(writes.len(), 1);
+        assert_eq!(
+            writes[0].data,
+            vec![0x80, 0xc0, b'b', 0x80, 0xc1, 0x40, b' ']
+        );
+    }
+
+    #[async_std::test]
+    async fn load_charset() {
+        let shutdown = Shutdown::new();
+        let fake = FakeI2c::new();
+        let bus = Arc::new(Mutex::new(fake.clone()));
+        let mut display = new_display(&shutdown, Default::default(), Alarms::new())
+            .init(&bus)
+            .await
+            .unwrap();
+
+        fake.clear_writes();
+        display
+            .load_charset(Charset::sparkline(), &bus)
+            .await
+            .unwrap();
+        let writes = fake.writes();
+        assert_eq!(writes.len(), 3);
+        assert_eq!(writes[1].data.len(), 8 * 8);
+        assert_eq!(&writes[1].data[8..16], &glyph::LEVELS[0]);
+
+        fake.clear_writes();
+        display.print("▁█↑", None, &bus).await.unwrap();
+        let writes = fake.writes();
+        assert_eq!(writes.len(), 1);
+        assert_eq!(writes[0].data, vec![0x80, 0x40, 1, 0xff]); // 登録していない外字は空白
+    }
+
+    #[test]
+    fn show_alarm() {
+        let shutdown = Shutdown::new();
+        let alarms = Alarms::new();
+        let display = new_display(&shutdown, Default::default(), alarms.clone());
+        display.bright.store(50.0f64.to_bits(), Ordering::Relaxed);
+        assert_eq!(display.second_line(), " 50.0% ████▍    ");
+
+        for (kind, temp) in [(Kind::High, 31.0), (Kind::Critical, 41.0)] {
+            alarms.publish(Event {
+                sensor: "living".to_string(),
+                kind,
+                raised: true,
+                temp,
+            });
+        }
+        assert_eq!(display.second_line(), "ALARM CRIT");
+    }
+
+    #[test]
+    fn rotate_sensors() {
+        let shutdown = Shutdown::new();
+        let air = Air {
+            temps: vec![temp("living", 23.5), temp("bedroom-north", -5.25)],
+            ..Default::default()
+        };
+        let mut display = new_display(&shutdown, air, Alarms::new());
+
+        assert_eq!(display.first_line(), "living   23.50°C");
+        assert_eq!(display.first_line(), "bedroom- -5.25°C"); // 名前は8文字まで
+        assert_eq!(display.first_line(), "living   23.50°C");
+
+        display.air.temps.truncate(1);
+        assert_eq!(display.first_line(), "23.50°C");
+
+        // 0度は測定済み
+        display.air.temps[0] = temp("living", 0.0);
+        assert_eq!(display.first_line(), "0.00°C");
+        display.air.temps[0] = Temperature::new("living".to_string());
+        assert_eq!(display.first_line(), "---°C");
+    }
+
+    #[test]
+    fn page_contents() {
+        let shutdown = Shutdown::new();
+        let air = Air {
+            temps: vec![temp("living", 21.0)],
+            co2: Arc::new(AtomicU16::new(850)),
+            tvoc: Arc::new(AtomicU16::new(12)),
+            ..Default::default()
+        };
+        let mut display = new_display(&shutdown, air.clone(), Alarms::new());
+
+        assert_eq!(
+            display.page_lines(Page::Co2),
+            ("CO2   850 ppm".to_string(), "TVOC   12 ppb".to_string())
+        );
+        air.set_ccs811_state(Ccs811State::WarmingUp);
+        assert_eq!(display.page_lines(Page::Co2).1, "TVOC   12 ppb *");
+
+        assert_eq!(
+            display.page_lines(Page::MinMax),
+            (
+                "↓  --- ↑  ---°C".to_string(),
+                "↓  --- ↑  ---ppm".to_string()
+            )
+        );
+        display.update_daily();
+        air.temps[0]
+            .value
+            .store(18.25f64.to_bits(), Ordering::Relaxed);
+        air.co2.store(1234, Ordering::Relaxed);
+        display.update_daily();
+        assert_eq!(
+            display.page_lines(Page::MinMax),
+            (
+                "↓ 18.2 ↑ 21.0°C".to_string(),
+                "↓  850 ↑ 1234ppm".to_string()
+            )
+        );
+
+        assert_eq!(display.page_lines(Page::Status).1, "DB off");
+        display.db.set_state(DbState::Connected);
+        display.db.set_inserted();
+        assert_eq!(display.page_lines(Page::Status).1, "DB ok 0s ago");
+        display.db.set_state(DbState::Failed);
+        assert_eq!(display.page_lines(Page::Status).1, "DB error");
+
+        assert_eq!(display.page_lines(Page::Uptime).0, "up  00:00:00");
+    }
+
+    #[async_std::test]
+    async fn switch_pages_with_button() {
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Consumer, "ST7032");
+        let fake = FakeI2c::new();
+        let bus = Arc::new(Mutex::new(fake.clone()));
+
+        let mut conf = config::Config::default();
+        conf.st7032.interval_ms = 60 * 1000; // ボタンでのみ更新
+        conf.st7032.pages = vec![Page::Co2, Page::Uptime];
+        let button = Button::new();
+        let display = ST7032::new(
+            token,
+            Arc::new(RwLock::new(conf)),
+            Default::default(),
+            Default::default(),
+            Alarms::new(),
+            button.clone(),
+            DbStatus::default(),
+            Instant::now(),
+        )
+        .init(&bus)
+        .await
+        .unwrap();
+        fake.clear_writes();
+        let hdl = display.run(bus).unwrap();
+
+        // 表示中の1行目
+        let mut row = [b' '; 16];
+
+        assert_eq!(shown(&mut row, &fake).await, "init ...        ");
+        button.publish(Press::Short);
+        assert_eq!(shown(&mut row, &fake).await, "up  00:00:00    ");
+        button.publish(Press::Short);
+        assert_eq!(shown(&mut row, &fake).await, "CO2   --- ppm   ");
+
+        // 長押しで固定しても、短押しでは切り替わる
+        button.publish(Press::Long);
+        assert_eq!(shown(&mut row, &fake).await, "CO2   --- ppm   ");
+        button.publish(Press::Short);
+        assert_eq!(shown(&mut row, &fake).await, "up  00:00:00    ");
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn control_and_icons() {
+        let shutdown = Shutdown::new();
+        let fake = FakeI2c::new();
+        let bus = Arc::new(Mutex::new(fake.clone()));
+        let mut display = new_display(&shutdown, Default::default(), Alarms::new())
+            .init(&bus)
+            .await
+            .unwrap();
+        fake.clear_writes();
+
+        // 変化した設定のみ
+        let control = Control {
+            cursor: true,
+            ..display.control()
+        };
+        display.set_control(control, &bus).await.unwrap();
+        display.set_control(control, &bus).await.unwrap();
+        let control = Control {
+            contrast: 10,
+            booster: false,
+            follower: 2,
+            icon: true,
+            ..control
+        };
+        display.set_control(control, &bus).await.unwrap();
+        let writes = fake.writes();
+        assert_eq!(writes.len(), 2);
+        assert_eq!(writes[0].data, vec![0x0f]);
+        assert_eq!(writes[1].data, vec![0x39, 0x7a, 0x58, 0x6a, 0x38]);
+
+        fake.clear_writes();
+        let mut icons = [0; ICON_SIZE];
+        icons[15] = 0xff;
+        display.write_icons(&icons, &bus).await.unwrap();
+        let writes = fake.writes();
+        assert_eq!(writes[0].data, vec![0x39, 0x40]); // アイコンRAMへ
+        assert_eq!(writes[1].reg, Some(0x40));
+        assert_eq!(writes[1].data[15], 0x1f);
+        assert_eq!(writes[2].data, vec![0x38]);
+    }
+
+    #[async_std::test]
+    async fn set_one_command() {
+        let shutdown = Shutdown::new();
+        let fake = FakeI2c::new();
+        let bus = Arc::new(Mutex::new(fake.clone()));
+        let mut display = new_display(&shutdown, Default::default(), Alarms::new())
+            .init(&bus)
+            .await
+            .unwrap();
+        fake.clear_writes();
+
+        // コントロールバイトとコマンドのみ（バイト数の1はクリアとして実行されてしまう）
+        let control = Control {
+            display: false,
+            ..display.control()
+        };
+        display.set_control(control, &bus).await.unwrap();
+        let writes = fake.writes();
+        assert_eq!(writes.len(), 1);
+        assert_eq!(writes[0].reg, Some(0x00));
+        assert_eq!(writes[0].data, vec![0x09]);
+    }
+
+    #[async_std::test]
+    async fn dim_in_the_dark() {
+        let shutdown = Shutdown::new();
+        let token = shutdown.token(Stage::Consumer, "ST7032");
+        let fake = FakeI2c::new();
+        let bus = Arc::new(Mutex::new(fake.clone()));
+
+        let mut conf = config::Config::default();
+        conf.st7032.interval_ms = 60 * 1000; // ボタンでのみ更新
+        conf.st7032.pages = vec![Page::Co2, Page::MinMax];
+        conf.st7032.dark_below = Some(10.0);
+        let bright = Arc::new(AtomicU64::new(5.0f64.to_bits()));
+        let button = Button::new();
+        let display = ST7032::new(
+            token,
+            Arc::new(RwLock::new(conf)),
+            Default::default(),
+            bright.clone(),
+            Alarms::new(),
+            button.clone(),
+            DbStatus::default(),
+            Instant::now(),
+        )
+        .init(&bus)
+        .await
+        .unwrap();
+        let hdl = display.run(bus).unwrap();
+
+        // 表示中の1行目と、書き込まれたコマンド
+        let mut row = [b' '; 16];
+        let commands = |fake: &FakeI2c| -> Vec<Vec<u8>> {
+            let writes = fake.writes();
+            writes
+                .into_iter()
+                .filter(|w| w.reg == Some(0))
+                .map(|w| w.data)
+                .collect()
+        };
+        task::sleep(Duration::from_millis(50)).await;
+        fake.clear_writes();
+
+        button.publish(Press::Short);
+        task::sleep(Duration::from_millis(50)).await;
+        assert_eq!(commands(&fake), vec![vec![0x39, 0x78, 0x54, 0x6c, 0x38]]); // コントラスト8
+        assert_eq!(shown(&mut row, &fake).await, "\x02  --- \x01  ---\x00C "); // 外字は文字コード
+
+        // 減光中の押下は元に戻すのみ
+        button.publish(Press::Short);
+        task::sleep(Duration::from_millis(50)).await;
+        assert_eq!(commands(&fake), vec![vec![0x39, 0x70, 0x56, 0x6c, 0x38]]);
+        fake.clear_writes();
+
+        // 明るくなると、以降の押下はページを切り替える
+        bright.store(50.0f64.to_bits(), Ordering::Relaxed);
+        button.publish(Press::Short);
+        task::sleep(Duration::from_millis(50)).await;
+        assert!(commands(&fake).is_empty());
+        assert_eq!(shown(&mut row, &fake).await, "CO2   --- ppm   ");
+
+        shutdown.run(Duration::from_secs(1)).await.unwrap();
+        hdl.await.unwrap();
+    }
+}
@@ -0,0 +1,200 @@
+//! ディスプレイのページ
+//!
+//! 設定の`pages`を順に表示し、`page_interval_ms`ごとに次のページへ切り替える。
+//! 物理スイッチの短押しで次のページへ進み、長押しで現在のページに固定する（もう一度長押しで解除）。
+
+use chrono::{Datelike, Local};
+use std::{
+    fs,
+    net::{IpAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// ページの切り替え
+#[derive(Debug)]
+pub(super) struct Pager {
+    index: usize,   // 表示中のページ（`pages`の位置）
+    pinned: bool,   // 自動で切り替えない
+    since: Instant, // 表示し始めた時刻
+}
+
+impl Pager {
+    pub(super) fn new(now: Instant) -> Self {
+        Pager {
+            index: 0,
+            pinned: false,
+            since: now,
+        }
+    }
+
+    /// 表示中のページの位置（設定の再読み込みで`pages`が短くなった場合は先頭に戻る）
+    pub(super) fn current(&mut self, len: usize) -> usize {
+        if self.index >= len {
+            self.index = 0;
+        }
+        self.index
+    }
+
+    /// 次のページへ
+    pub(super) fn next(&mut self, len: usize, now: Instant) {
+        self.index = (self.current(len) + 1) % len.max(1);
+        self.since = now;
+    }
+
+    /// 固定・解除を切り替え、固定したかを返す
+    pub(super) fn toggle_pin(&mut self, now: Instant) -> bool {
+        self.pinned = !self.pinned;
+        self.since = now;
+        self.pinned
+    }
+
+    /// `interval`が経過していれば次のページへ（固定中と`interval`が0の場合は切り替えない）
+    pub(super) fn tick(&mut self, len: usize, interval: Duration, now: Instant) {
+        if !self.pinned && !interval.is_zero() && now - self.since >= interval {
+            self.next(len, now);
+        }
+    }
+}
+
+/// 今日の最小・最大
+#[derive(Debug, Default)]
+pub(super) struct Daily {
+    day: Option<i32>,                    // 日付（西暦1年1月1日からの日数）
+    pub(super) temp: Option<(f64, f64)>, // 気温
+    pub(super) co2: Option<(u16, u16)>,  // CO2
+}
+
+impl Daily {
+    /// 測定値を反映（日付が変わるとリセット）
+    pub(super) fn update(&mut self, day: i32, temp: Option<f64>, co2: Option<u16>) {
+        if self.day != Some(day) {
+            *self = Daily {
+                day: Some(day),
+                ..Default::default()
+            };
+        }
+        if let Some(t) = temp {
+            self.temp = Some(widen(self.temp, t));
+        }
+        if let Some(c) = co2 {
+            self.co2 = Some(widen(self.co2, c));
+        }
+    }
+}
+
+fn widen<T: PartialOrd + Copy>(range: Option<(T, T)>, v: T) -> (T, T) {
+    match range {
+        Some((min, max)) => (if v < min { v } else { min }, if v > max { v } else { max }),
+        None => (v, v),
+    }
+}
+
+/// 今日の日付（ローカル時刻）
+pub(super) fn today() -> i32 {
+    Local::now().num_days_from_ce()
+}
+
+/// 稼働時間（例: `1d 02:03:04`）
+pub(super) fn duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (days, h, m, s) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("{days}d {h:02}:{m:02}:{s:02}")
+    } else {
+        format!("{h:02}:{m:02}:{s:02}")
+    }
+}
+
+/// 経過時間の概数（例: `12s`、`5m`、`3h`、`2d`）
+pub(super) fn ago(d: Duration) -> String {
+    match d.as_secs() {
+        s @ 0..=59 => format!("{s}s"),
+        s @ 60..=3599 => format!("{}m", s / 60),
+        s @ 3600..=86399 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
+
+/// 外部への経路があるネットワークインターフェースのIPアドレス
+///
+/// UDPソケットの接続先を設定して経路を選ぶだけで、パケットは送信しない。
+pub(super) fn local_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).ok()?;
+    socket.connect(("192.0.2.1", 80)).ok()?; // TEST-NET-1
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_unspecified()).then_some(ip)
+}
+
+/// OSの稼働時間（`/proc/uptime`）
+pub(super) fn system_uptime() -> Option<Duration> {
+    let s = fs::read_to_string("/proc/uptime").ok()?;
+    let secs: f64 = s.split_whitespace().next()?.parse().ok()?;
+    Some(Duration::from_secs_f64(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_and_pin() {
+        let t0 = Instant::now();
+        let at = |s| t0 + Duration::from_secs(s);
+        let interval = Duration::from_secs(5);
+        let mut pager = Pager::new(t0);
+
+        pager.tick(3, interval, at(4));
+        assert_eq!(pager.current(3), 0);
+        pager.tick(3, interval, at(5));
+        assert_eq!(pager.current(3), 1);
+
+        // 短押しで次へ進むと、そこから数え直す
+        pager.next(3, at(7));
+        assert_eq!(pager.current(3), 2);
+        pager.tick(3, interval, at(11));
+        assert_eq!(pager.current(3), 2);
+        pager.tick(3, interval, at(12));
+        assert_eq!(pager.current(3), 0);
+
+        // 固定中は切り替えない
+        assert!(pager.toggle_pin(at(13)));
+        pager.tick(3, interval, at(60));
+        assert_eq!(pager.current(3), 0);
+        assert!(!pager.toggle_pin(at(61)));
+        pager.tick(3, interval, at(66));
+        assert_eq!(pager.current(3), 1);
+
+        // ページが減った場合は先頭へ
+        pager.next(3, at(67));
+        assert_eq!(pager.current(2), 0);
+        pager.tick(1, Duration::ZERO, at(100));
+        assert_eq!(pager.current(1), 0);
+    }
+
+    #[test]
+    fn daily_min_max() {
+        let mut daily = Daily::default();
+        daily.update(1, Some(20.5), None);
+        daily.update(1, Some(18.0), Some(600));
+        daily.update(1, Some(22.25), Some(450));
+        assert_eq!(daily.temp, Some((18.0, 22.25)));
+        assert_eq!(daily.co2, Some((450, 600)));
+
+        daily.update(2, None, Some(500)); // 日付が変わるとリセット
+        assert_eq!(daily.temp, None);
+        assert_eq!(daily.co2, Some((500, 500)));
+    }
+
+    #[test]
+    fn format_durations() {
+        assert_eq!(
+            duration(Duration::from_secs(3 * 3600 + 4 * 60 + 5)),
+            "03:04:05"
+        );
+        assert_eq!(duration(Duration::from_secs(2 * 86400 + 59)), "2d 00:00:59");
+        assert_eq!(ago(Duration::from_secs(12)), "12s");
+        assert_eq!(ago(Duration::from_secs(300)), "5m");
+        assert_eq!(ago(Duration::from_secs(3 * 3600 + 1)), "3h");
+        assert_eq!(ago(Duration::from_secs(2 * 86400)), "2d");
+    }
+}
@@ -0,0 +1,360 @@
+//! Raspberry Pi 4のセンサ、ディスプレイをasync/awaitで操作するライブラリ
+//!
+//! - 温度センサ [`i2c::adt7410::ADT7410`]
+//! - 二酸化炭素・総揮発性有機化合物センサ [`i2c::ccs811::CCS811`]
+//! - 液晶ディスプレイ [`i2c::st7032::ST7032`]
+//! - ADコンバータ [`spi::mcp3208::MCP3208`]
+//!
+//! 各ドライバは[hal]のトレイトに対してジェネリックで、生成、初期化、一度だけの読み込み、
+//! [i2c::Runner]・[spi::Runner]による周期的な実行を行える。
+//! 測定値は[Air]と明るさの共有変数を介して共有され、`db`でPostgreSQLに保存される。
+//! 温度アラームは[alarm::Alarms]、CO2濃度の段階の変化は[co2::Bands]、
+//! 物理スイッチの短押し・長押しは[button::Button]で配信される。
+//!
+//! 各ドライバ、DB、シミュレーションモードはcargoのfeatureで個別に無効にできる。
+//! `linux`を無効にすると、各ドライバのレジスタの扱いと`eh`モジュールのみのno_stdのクレートになる。
+
+#![cfg_attr(not(any(test, feature = "linux")), no_std)]
+
+#[cfg(feature = "postgres")]
+#[macro_use]
+extern crate diesel;
+
+#[cfg(feature = "linux")]
+pub mod alarm;
+#[cfg(feature = "linux")]
+pub mod broadcast;
+#[cfg(feature = "linux")]
+pub mod button;
+pub mod co2;
+#[cfg(feature = "linux")]
+pub mod config;
+#[cfg(feature = "postgres")]
+pub mod db;
+#[cfg(feature = "linux")]
+pub mod gpio;
+#[cfg(feature = "linux")]
+pub mod hal;
+#[cfg(any(feature = "adt7410", feature = "ccs811", feature = "st7032"))]
+pub mod i2c;
+#[cfg(feature = "postgres")]
+#[allow(non_local_definitions)]
+mod schema;
+#[cfg(feature = "linux")]
+pub mod shutdown;
+#[cfg(feature = "linux")]
+pub mod signal;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "mcp3208")]
+pub mod spi;
+#[cfg(feature = "linux")]
+pub mod supervisor;
+
+use core::fmt;
+#[cfg(feature = "linux")]
+use std::{
+    sync::{
+        atomic::{AtomicU16, AtomicU64, AtomicU8, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "linux")]
+pub type EResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+#[cfg(feature = "linux")]
+#[macro_export]
+macro_rules! perror {
+    ($($args: expr),*) => {
+        {
+            eprint!("error: file: {}, line: {}", file!(), line!());
+            $( eprint!(", {}: {}", stringify!($args), $args); )*
+            eprintln!(""); // to get a new line at the end
+        }
+    }
+}
+
+/// 空気の状態
+///
+/// 各センサのタスクが書き込み、ディスプレイとDBのタスクが読み出す。
+/// 気温、湿度、電圧は`f64::to_bits`で格納し、未測定の間はNaN（0は測定値の0と区別できないため）。
+#[cfg(feature = "linux")]
+#[derive(Clone, Debug, Default)]
+pub struct Air {
+    pub temps: Vec<Temperature>, // 気温（ADT7410ごと、設定の順）
+    pub humidity: Measured,      // 湿度（%）。湿度を測定するタスクが書き込む
+    pub co2: Arc<AtomicU16>,     // 二酸化炭素濃度
+    pub tvoc: Arc<AtomicU16>,    // 総揮発性有機化合物
+    pub current: Arc<AtomicU16>, // CCS811のセンサの電流（μA）、0は未測定
+    pub voltage: Measured,       // CCS811のセンサの電圧（V）
+    pub ccs811_versions: Arc<Mutex<Option<Ccs811Versions>>>, // 初期化時に読み込んだバージョン
+    pub ccs811_state: Arc<AtomicU8>, // CCS811の状態（`Ccs811State`）、0は未初期化
+    pub ccs811_counters: Arc<Mutex<Ccs811Counters>>, // CCS811のエラーと復旧の回数
+}
+
+#[cfg(feature = "linux")]
+impl Air {
+    /// ADT7410の設定にあるセンサごとに気温を用意する
+    pub fn new(adt7410: &config::ADT7410) -> Self {
+        let temps = adt7410
+            .sensors()
+            .into_iter()
+            .map(|s| Temperature::new(s.name))
+            .collect();
+
+        Air {
+            temps,
+            humidity: Default::default(),
+            co2: Default::default(),
+            tvoc: Default::default(),
+            current: Default::default(),
+            voltage: Default::default(),
+            ccs811_versions: Default::default(),
+            ccs811_state: Default::default(),
+            ccs811_counters: Default::default(),
+        }
+    }
+
+    /// 名前で気温を探す。`None`の場合は先頭のセンサ
+    pub fn find_temp(&self, name: Option<&str>) -> Option<&Temperature> {
+        match name {
+            Some(name) => self.temps.iter().find(|t| t.name == name),
+            None => self.temps.first(),
+        }
+    }
+
+    /// CCS811の状態（初期化前は`None`）
+    ///
+    /// `Ready`以外の間に読み込んだCO2とTVOCは不正確。
+    pub fn ccs811_state(&self) -> Option<Ccs811State> {
+        match self.ccs811_state.load(Ordering::Relaxed) {
+            1 => Some(Ccs811State::BurnIn),
+            2 => Some(Ccs811State::WarmingUp),
+            3 => Some(Ccs811State::Ready),
+            4 => Some(Ccs811State::Suspect),
+            _ => None,
+        }
+    }
+
+    /// CCS811の状態を格納
+    pub fn set_ccs811_state(&self, state: Ccs811State) {
+        let v = match state {
+            Ccs811State::BurnIn => 1,
+            Ccs811State::WarmingUp => 2,
+            Ccs811State::Ready => 3,
+            Ccs811State::Suspect => 4,
+        };
+        self.ccs811_state.store(v, Ordering::Relaxed);
+    }
+}
+
+/// 未測定をNaNで表す測定値
+///
+/// `f64::to_bits`で格納し、`Clone`で共有する。
+#[cfg(feature = "linux")]
+#[derive(Clone, Debug)]
+pub struct Measured(Arc<AtomicU64>);
+
+#[cfg(feature = "linux")]
+impl Default for Measured {
+    fn default() -> Self {
+        Measured(Arc::new(AtomicU64::new(f64::NAN.to_bits())))
+    }
+}
+
+#[cfg(feature = "linux")]
+impl Measured {
+    /// 値（未測定の場合はNaN）
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    /// 測定済みの値（未測定の場合は`None`）
+    pub fn measured(&self) -> Option<f64> {
+        Some(self.get()).filter(|v| !v.is_nan())
+    }
+
+    /// 値を格納
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// センサ一つの気温
+#[cfg(feature = "linux")]
+#[derive(Clone, Debug)]
+pub struct Temperature {
+    pub name: String,          // 設置場所
+    pub value: Arc<AtomicU64>, // 気温、NaNは未測定
+}
+
+#[cfg(feature = "linux")]
+impl Default for Temperature {
+    fn default() -> Self {
+        Temperature::new(String::new())
+    }
+}
+
+#[cfg(feature = "linux")]
+impl Temperature {
+    /// 未測定の気温
+    pub fn new(name: String) -> Self {
+        Temperature {
+            name,
+            value: Arc::new(AtomicU64::new(f64::NAN.to_bits())),
+        }
+    }
+
+    /// 気温（未測定の場合はNaN）
+    pub fn celsius(&self) -> f64 {
+        f64::from_bits(self.value.load(Ordering::Relaxed))
+    }
+
+    /// 測定済みの気温（未測定の場合は`None`）
+    pub fn measured(&self) -> Option<f64> {
+        Some(self.celsius()).filter(|c| !c.is_nan())
+    }
+}
+
+/// CCS811の状態
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ccs811State {
+    BurnIn,    // 累積の稼働時間が`burn_in_h`未満
+    WarmingUp, // 起動から`warmup_min`未満
+    Ready,     // 測定値を使える
+    Suspect,   // 直近のサンプルの多くが範囲外で、センサの異常が疑われる
+}
+
+impl fmt::Display for Ccs811State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Ccs811State::BurnIn => "burn-in",
+            Ccs811State::WarmingUp => "warming-up",
+            Ccs811State::Ready => "ready",
+            Ccs811State::Suspect => "suspect",
+        };
+        f.write_str(s)
+    }
+}
+
+/// CCS811のエラーと復旧の回数
+///
+/// エラーはERROR_IDのビットごとに数える。
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ccs811Counters {
+    pub heater_supply: u64,     // HEATER_SUPPLY
+    pub heater_fault: u64,      // HEATER_FAULT
+    pub mox_resistance: u64,    // MOX_RESISTANCE
+    pub meas_mode_invalid: u64, // MEAS_MODE_INVALID
+    pub read_reg_invalid: u64,  // READ_REG_INVALID
+    pub msg_invalid: u64,       // MSG_INVALID
+    pub rejected_co2_low: u64,  // `co2_min`未満で棄却したサンプル
+    pub rejected_co2_high: u64, // `co2_max`より大きく棄却したサンプル
+    pub rejected_tvoc: u64,     // `tvoc_max`より大きく棄却したサンプル
+    pub bus: u64,               // I2Cの通信エラー
+    pub recoveries: u64,        // 初期化し直して復旧した回数
+    pub failed_recoveries: u64, // 復旧に失敗した回数
+}
+
+/// CCS811のハードウェアとファームウェアのバージョン
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ccs811Versions {
+    pub hw: u8,          // HW_VERSION（上位4ビットは常に1）
+    pub boot: FwVersion, // FW_BOOT_VERSION
+    pub app: FwVersion,  // FW_APP_VERSION
+}
+
+impl fmt::Display for Ccs811Versions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "HW 0x{:02x}, boot {}, app {}",
+            self.hw, self.boot, self.app
+        )
+    }
+}
+
+/// ファームウェアのバージョン
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FwVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub trivial: u8,
+}
+
+impl FwVersion {
+    /// レジスタの値から変換
+    ///
+    /// 1バイト目の上位4ビットがmajor、下位4ビットがminor、2バイト目がtrivial。
+    pub fn from_bytes(buf: [u8; 2]) -> Self {
+        FwVersion {
+            major: buf[0] >> 4,
+            minor: buf[0] & 0x0f,
+            trivial: buf[1],
+        }
+    }
+}
+
+impl fmt::Display for FwVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.trivial)
+    }
+}
+
+/// DBへの保存の状況
+///
+/// DBのタスクが書き込み、ディスプレイのタスクが読み出す。
+#[cfg(feature = "linux")]
+#[derive(Clone, Debug, Default)]
+pub struct DbStatus {
+    state: Arc<AtomicU8>,        // `DbState`、0は未接続
+    last_insert: Arc<AtomicU64>, // 最後に保存したUNIX時刻（秒）、0は未保存
+}
+
+#[cfg(feature = "linux")]
+impl DbStatus {
+    /// 接続の状態
+    pub fn state(&self) -> DbState {
+        match self.state.load(Ordering::Relaxed) {
+            1 => DbState::Connected,
+            2 => DbState::Failed,
+            _ => DbState::Disconnected,
+        }
+    }
+
+    /// 接続の状態を格納
+    pub fn set_state(&self, state: DbState) {
+        let v = match state {
+            DbState::Disconnected => 0,
+            DbState::Connected => 1,
+            DbState::Failed => 2,
+        };
+        self.state.store(v, Ordering::Relaxed);
+    }
+
+    /// 最後に保存した時刻（未保存の場合は`None`）
+    pub fn last_insert(&self) -> Option<SystemTime> {
+        match self.last_insert.load(Ordering::Relaxed) {
+            0 => None,
+            secs => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+        }
+    }
+
+    /// 保存した時刻として現在時刻を格納
+    pub fn set_inserted(&self) {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.last_insert.store(secs, Ordering::Relaxed);
+    }
+}
+
+/// DBの接続の状態
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DbState {
+    Disconnected, // 未接続（`DATABASE_URL`が無いか、postgresのfeatureが無効）
+    Connected,    // 接続済み
+    Failed,       // 接続か保存に失敗
+}
@@ -1,67 +1,182 @@
-#[macro_use]
-extern crate diesel;
-
-mod db;
-mod gpio;
-mod i2c;
-mod schema;
-mod signal;
-mod spi;
-
 #[allow(unused_imports)]
 use async_std::prelude::*;
 
-use std::sync::{
-    atomic::{AtomicU16, AtomicU64},
-    Arc,
+#[cfg(feature = "postgres")]
+use rpi_async::db;
+#[cfg(any(feature = "adt7410", feature = "ccs811", feature = "st7032"))]
+use rpi_async::i2c;
+#[cfg(feature = "ccs811")]
+use rpi_async::i2c::ccs811::firmware;
+#[cfg(feature = "sim")]
+use rpi_async::sim;
+#[cfg(feature = "mcp3208")]
+use rpi_async::spi;
+use rpi_async::{
+    alarm::Alarms,
+    button::Button,
+    co2::Bands,
+    config::{self, SharedConfig},
+    gpio,
+    hal::{self, Hardware},
+    shutdown::Shutdown,
+    signal,
+    supervisor::Supervisor,
+    Air, DbStatus, EResult,
 };
+use std::{
+    env,
+    sync::{atomic::AtomicU64, Arc, RwLock},
+};
+#[cfg(feature = "ccs811")]
+use std::{path::Path, time::Duration};
 
-pub type EResult<T> = Result<T, Box<dyn std::error::Error>>;
+/// 各タスクを起動
+async fn start<H: Hardware>(
+    hw: &H,
+    shutdown: &Shutdown,
+    supervisor: &Supervisor,
+    config: &SharedConfig,
+    air: Air,
+    bright: Arc<AtomicU64>,
+) -> EResult<()> {
+    let alarms = Alarms::new(); // 温度アラーム
+    let bands = Bands::new(); // CO2濃度の段階
+    let button = Button::new(); // 物理スイッチ
+    let db_status = DbStatus::default(); // DBへの保存の状況
+    let ccs811_pins = gpio::run(
+        hw,
+        shutdown,
+        supervisor,
+        config,
+        air.temps.clone(),
+        alarms.clone(),
+        button.clone(),
+    )
+    .await?; // LEDタスクを起動
 
-#[macro_export]
-macro_rules! perror {
-    ($($args: expr),*) => {
-        {
-            eprint!("error: file: {}, line: {}", file!(), line!());
-            $( eprint!(", {}: {}", stringify!($args), $args); )*
-            eprintln!(""); // to get a new line at the end
-        }
-    }
-}
+    #[cfg(feature = "mcp3208")]
+    spi::run(hw, shutdown, supervisor, config.clone(), bright.clone()).await?; // SPIタスクを起動
+
+    #[cfg(any(feature = "adt7410", feature = "ccs811", feature = "st7032"))]
+    i2c::run(
+        hw,
+        shutdown,
+        supervisor,
+        config.clone(),
+        ccs811_pins,
+        air.clone(),
+        bright.clone(),
+        alarms.clone(),
+        bands,
+        button,
+        db_status.clone(),
+    )
+    .await?; // I2Cタスクを起動
+    #[cfg(not(any(feature = "adt7410", feature = "ccs811", feature = "st7032")))]
+    let _ = (ccs811_pins, bands, button);
 
-#[derive(Clone, Debug)]
-pub struct Air {
-    pub temp: Arc<AtomicU64>, // 気温
-    pub co2: Arc<AtomicU16>,  // 二酸化炭素濃度
-    pub tvoc: Arc<AtomicU16>, // 総揮発性有機化合物
+    #[cfg(feature = "postgres")]
+    let _ = db::run(shutdown, config.clone(), air, bright, alarms, db_status);
+    #[cfg(not(feature = "postgres"))]
+    let _ = (air, bright, alarms, db_status);
+
+    Ok(())
 }
 
-impl Air {
-    fn new() -> Self {
-        Air {
-            temp: Default::default(),
-            co2: Default::default(),
-            tvoc: Default::default(),
-        }
+/// CCS811のファームウェアを更新
+///
+/// `dry_run`の場合はセンサの代わりに[firmware::DryRun]に書き込む。
+#[cfg(feature = "ccs811")]
+async fn update_ccs811(config: &SharedConfig, path: &Path, dry_run: bool) -> EResult<()> {
+    let conf = config.read().unwrap().clone();
+    let image = firmware::load(path)?;
+    let progress = firmware::print_progress();
+
+    if dry_run {
+        let mut bus = firmware::DryRun::new();
+        let delays = firmware::Delays::NONE;
+        return firmware::update(&mut bus, conf.ccs811.addr, &image, &delays, progress).await;
     }
+
+    let rpi = hal::Rpi::new()?;
+    let mut bus = rpi.i2c()?;
+    let mut wake = rpi.output_pin(conf.gpio.ccs811_wake_pin)?;
+
+    // 更新中はnWAKEをLowのままにする
+    wake.set_low();
+    async_std::task::sleep(Duration::from_micros(100)).await;
+    let delays = firmware::Delays::default();
+    let result = firmware::update(&mut bus, conf.ccs811.addr, &image, &delays, progress).await;
+    wake.set_high();
+    result
 }
 
 #[async_std::main]
 async fn main() -> EResult<()> {
     let bright = Arc::new(AtomicU64::new(0)); // 明るさ
-    let air = Air::new();
 
-    let (sig_rx, sig_hdl) = signal::run().await?; // シグナルハンドラを起動
-    let (led_hdl, ccs811_pin) = gpio::run(sig_rx.clone()).await?; // LEDタスクを起動
-    let spi_hdl = spi::run(sig_rx.clone(), bright.clone()).await?; // SPIタスクを起動
-    let i2c_hdl = i2c::run(sig_rx, ccs811_pin, air.clone(), bright.clone()).await?; // I2Cタスクを起動
-    let _ = db::run(air, bright);
+    let shutdown = Shutdown::new();
+
+    // 設定ファイルを読み込み
+    let config_path = config::path();
+    let config = Arc::new(RwLock::new(config::load(&config_path)?));
+    let air = Air::new(&config.read().unwrap().adt7410); // 温度センサごとの気温
+
+    // CCS811のファームウェアを更新して終了
+    #[cfg(feature = "ccs811")]
+    {
+        let args: Vec<String> = env::args().collect();
+        if let Some(i) = args.iter().position(|arg| arg == "--ccs811-firmware") {
+            let path = args.get(i + 1).ok_or("--ccs811-firmware requires a file")?;
+            let dry_run = args.iter().any(|arg| arg == "--dry-run");
+            return update_ccs811(&config, Path::new(path), dry_run).await;
+        }
+    }
+
+    // 異常終了したタスクを再起動
+    let supervisor = Supervisor::new(config.clone());
+
+    let sig_hdl = signal::run(config.clone(), config_path).await?; // シグナルハンドラを起動
+
+    if env::args().any(|arg| arg == "--simulate") {
+        // 模擬デバイスで起動
+        #[cfg(feature = "sim")]
+        {
+            let sim = sim::Sim::new(config.clone());
+            sim::run(&shutdown, &sim);
+            start(&sim, &shutdown, &supervisor, &config, air, bright).await?;
+        }
+        #[cfg(not(feature = "sim"))]
+        return Err("built without the sim feature".into());
+    } else {
+        let rpi = hal::Rpi::new()?;
+        start(&rpi, &shutdown, &supervisor, &config, air, bright).await?;
+    }
+
+    sig_hdl.await; // 終了シグナルを待機
 
     // graceful shutdown
-    i2c_hdl.await; // I2Cタスクの終了を待機
-    spi_hdl.await; // SPIタスクの終了を待機
-    led_hdl.await; // LEDタスクの終了を待機
-    sig_hdl.await; // シグナルハンドラの終了を待機
+    let deadline = config.read().unwrap().shutdown.deadline();
+    if let Err(running) = shutdown.run(deadline).await {
+        eprintln!(
+            "error: shutdown deadline ({} s) exceeded, still running: {}",
+            deadline.as_secs(),
+            running.join(", ")
+        );
+        std::process::exit(1);
+    }
+
+    println!("all tasks stopped");
+
+    for (name, stats) in supervisor.stats() {
+        if stats.restarts > 0 || stats.gave_up {
+            println!(
+                "{name}: restarted {} times, last error: {}",
+                stats.restarts,
+                stats.last_error.unwrap_or_default()
+            );
+        }
+    }
 
     Ok(())
 }
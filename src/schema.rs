@@ -1,3 +1,23 @@
+table! {
+    alarms (id) {
+        id -> Int4,
+        datetime -> Timestamptz,
+        kind -> Text,
+        raised -> Bool,
+        temperature -> Nullable<Float4>,
+        sensor -> Nullable<Text>,
+    }
+}
+
+table! {
+    ccs811_versions (datetime) {
+        datetime -> Timestamptz,
+        hw_version -> Int4,
+        fw_boot_version -> Text,
+        fw_app_version -> Text,
+    }
+}
+
 table! {
     data (datetime) {
         datetime -> Timestamptz,
@@ -5,5 +25,18 @@ table! {
         brightness -> Nullable<Float4>,
         co2 -> Nullable<Int4>,
         tvoc -> Nullable<Int4>,
+        current -> Nullable<Int4>,
+        voltage -> Nullable<Float4>,
+        ccs811_ready -> Nullable<Bool>,
+    }
+}
+
+table! {
+    temperatures (datetime, sensor) {
+        datetime -> Timestamptz,
+        sensor -> Text,
+        temperature -> Nullable<Float4>,
     }
 }
+
+allow_tables_to_appear_in_same_query!(alarms, ccs811_versions, data, temperatures);
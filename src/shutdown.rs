@@ -0,0 +1,150 @@
+#[allow(unused_imports)]
+use async_std::prelude::*;
+
+use async_std::{
+    channel::{self, Receiver, Sender},
+    future::timeout,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// 終了順序
+///
+/// 値を生成するタスクを先に終了させ、表示・保存を行うタスクは最後に終了させる。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Producer = 0, // センサ、GPIO
+    Consumer = 1, // ディスプレイ、DB
+}
+
+type Running = Arc<Mutex<BTreeMap<&'static str, usize>>>;
+
+struct StageCtl {
+    cancel_tx: Option<Sender<()>>, // dropすると全Tokenへ終了が通知される
+    cancel_rx: Receiver<()>,
+    done_tx: Option<Sender<()>>, // 全Tokenがdropされるとdone_rxがcloseされる
+    done_rx: Receiver<()>,
+}
+
+impl StageCtl {
+    fn new() -> Self {
+        let (cancel_tx, cancel_rx) = channel::bounded(1);
+        let (done_tx, done_rx) = channel::bounded(1);
+        StageCtl {
+            cancel_tx: Some(cancel_tx),
+            cancel_rx,
+            done_tx: Some(done_tx),
+            done_rx,
+        }
+    }
+}
+
+/// 終了処理の管理
+pub struct Shutdown {
+    stages: [StageCtl; 2],
+    running: Running,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Shutdown {
+            stages: [StageCtl::new(), StageCtl::new()],
+            running: Default::default(),
+        }
+    }
+
+    /// タスク用のトークンを発行
+    ///
+    /// トークンが全てdropされるまで、そのタスクは実行中とみなされる。
+    pub fn token(&self, stage: Stage, name: &'static str) -> Token {
+        let ctl = &self.stages[stage as usize];
+        *self.running.lock().unwrap().entry(name).or_default() += 1;
+        Token {
+            name,
+            cancel_rx: ctl.cancel_rx.clone(),
+            _done_tx: ctl.done_tx.clone(),
+            running: self.running.clone(),
+        }
+    }
+
+    /// 実行中のタスク名
+    pub fn running(&self) -> Vec<&'static str> {
+        self.running.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Producer、Consumerの順に終了を通知し、各段のタスク終了を待つ
+    ///
+    /// deadlineまでに終了しなかった場合は、実行中のタスク名を返す。
+    pub async fn run(mut self, deadline: Duration) -> Result<(), Vec<&'static str>> {
+        let start = Instant::now();
+
+        for stage in [Stage::Producer, Stage::Consumer] {
+            println!("shutting down {:?} tasks ...", stage);
+
+            let ctl = &mut self.stages[stage as usize];
+            ctl.cancel_tx.take();
+            ctl.done_tx.take();
+
+            let remaining = deadline.saturating_sub(start.elapsed());
+            if timeout(remaining, ctl.done_rx.recv()).await.is_err() {
+                return Err(self.running());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// 終了通知を受け取るトークン
+pub struct Token {
+    name: &'static str,
+    cancel_rx: Receiver<()>,
+    _done_tx: Option<Sender<()>>,
+    running: Running,
+}
+
+impl Token {
+    /// 終了が通知されるまで待機
+    pub async fn cancelled(&self) {
+        // 送信されることはないため、closeされるとErrが返る
+        let _ = self.cancel_rx.recv().await;
+    }
+
+    /// 終了が通知されたか
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel_rx.is_closed()
+    }
+}
+
+impl Clone for Token {
+    fn clone(&self) -> Self {
+        *self.running.lock().unwrap().entry(self.name).or_default() += 1;
+        Token {
+            name: self.name,
+            cancel_rx: self.cancel_rx.clone(),
+            _done_tx: self._done_tx.clone(),
+            running: self.running.clone(),
+        }
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        let mut running = self.running.lock().unwrap();
+        if let Some(n) = running.get_mut(self.name) {
+            *n -= 1;
+            if *n == 0 {
+                running.remove(self.name);
+            }
+        }
+    }
+}
@@ -1,32 +1,29 @@
 #[allow(unused_imports)]
 use async_std::prelude::*;
 
-use super::{perror, EResult};
-use async_std::{
-    channel::{self, Receiver},
-    task::{self, JoinHandle},
-};
+use super::EResult;
+use crate::config::{self, SharedConfig};
+use async_std::task::{self, JoinHandle};
 use signal_hook::consts::signal::*;
 use signal_hook_async_std::Signals;
+use std::path::PathBuf;
 
-const CHANNEL_SIZE: usize = 32;
-
-pub async fn run() -> EResult<(Receiver<()>, JoinHandle<()>)> {
-    let mut signals = Signals::new(&[SIGHUP, SIGTERM, SIGINT, SIGQUIT])?;
-    let (tx, rx) = channel::bounded(CHANNEL_SIZE);
+/// シグナルハンドラを起動
+///
+/// SIGHUPを受け取ると設定ファイルを再読み込みし、
+/// 終了シグナルを受け取るとJoinHandleが完了する。
+pub async fn run(config: SharedConfig, path: PathBuf) -> EResult<JoinHandle<()>> {
+    let mut signals = Signals::new([SIGHUP, SIGTERM, SIGINT, SIGQUIT])?;
 
     let f = async move {
         while let Some(signal) = signals.next().await {
             match signal {
                 SIGHUP => {
                     // Reload configuration
-                    // Reopen the log file
+                    config::reload(&config, &path);
                 }
                 SIGTERM | SIGINT | SIGQUIT => {
                     // Shutdown the system;
-                    if let Err(e) = tx.send(()).await {
-                        perror!(e);
-                    }
                     println!();
                     println!("exiting signal handler ...");
                     return;
@@ -38,5 +35,5 @@ pub async fn run() -> EResult<(Receiver<()>, JoinHandle<()>)> {
 
     println!("initialized signal handler");
 
-    Ok((rx, task::spawn(f)))
+    Ok(task::spawn(f))
 }
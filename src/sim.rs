@@ -0,0 +1,983 @@
+//! シミュレーションモード
+//!
+//! Raspberry Pi無しで全体を動作させるための模擬デバイス。
+//! `--simulate`を付けて起動すると、実機の代わりにこれらのデバイスが用いられる。
+
+#[allow(unused_imports)]
+use async_std::prelude::*;
+
+use crate::{
+    config::{self, SharedConfig},
+    hal::{Hardware, I2cBus, InputPin, OutputPin, SpiBus},
+    shutdown::{Shutdown, Stage},
+    EResult,
+};
+use async_std::{future::timeout, task};
+use rppal::gpio::{Level, Trigger};
+use std::{
+    f64::consts::PI,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+const TEMP_BASE: f64 = 22.0; // 気温の平均
+const TEMP_AMPLITUDE: f64 = 4.0; // 気温の振幅
+const TEMP_PERIOD: f64 = 600.0; // 気温の周期（秒）
+const TEMP_OFFSET: f64 = -1.5; // ADT7410のアドレスごとの気温の差
+
+const LIGHT_BASE: f64 = 50.0; // 明るさの平均（%）
+const LIGHT_AMPLITUDE: f64 = 40.0; // 明るさの振幅
+const LIGHT_PERIOD: f64 = 300.0; // 明るさの周期（秒）
+
+const CO2_OUTDOOR: f64 = 420.0; // 外気のCO2濃度（ppm）
+const CO2_PER_PERSON: f64 = 1.5; // 一人あたりのCO2増加量（ppm/秒）
+const VENTILATION: f64 = 120.0; // 換気の時定数（秒）
+const TVOC_PER_PPM: f64 = 0.3; // CO2の増加分に対するTVOC（ppb/ppm）
+const OCCUPANCY: [u32; 6] = [0, 1, 3, 5, 2, 0]; // 在室人数の推移
+const OCCUPANCY_STEP: f64 = 120.0; // 在室人数が変化する間隔（秒）
+const CCS811_FAULT_AT: f64 = 90.0; // CCS811のヒーターが一度だけ異常になる時刻（秒）
+
+const BUTTON_PERIOD: Duration = Duration::from_secs(15); // ボタンを押す間隔
+const BUTTON_PRESS: Duration = Duration::from_secs(1); // ボタンを押している時間
+const BUTTON_LONG_PRESS: Duration = Duration::from_secs(3); // 4回に1回は長押し
+
+const RENDER_INTERVAL: Duration = Duration::from_millis(200);
+const RENDER_QUIET: Duration = Duration::from_millis(50); // 書き込み途中の表示を避ける
+
+/// 模擬デバイス一式
+#[derive(Clone)]
+pub struct Sim {
+    start: Instant,
+    config: SharedConfig,
+    adt7410: Arc<Mutex<Vec<ADT7410>>>, // 0x48 - 0x4b
+    ccs811: Arc<Mutex<CCS811>>,
+    st7032: Arc<Mutex<ST7032>>,
+}
+
+impl Sim {
+    pub fn new(config: SharedConfig) -> Self {
+        let start = Instant::now();
+        Sim {
+            start,
+            config,
+            adt7410: Arc::new(Mutex::new(
+                (0..4)
+                    .map(|i| ADT7410::new(TEMP_OFFSET * i as f64))
+                    .collect(),
+            )),
+            ccs811: Arc::new(Mutex::new(CCS811::new())),
+            st7032: Arc::new(Mutex::new(ST7032::new())),
+        }
+    }
+
+    /// 起動からの経過秒数
+    fn elapsed(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+}
+
+/// 仮想ディスプレイの内容を端末に表示するタスクを起動
+pub fn run(shutdown: &Shutdown, sim: &Sim) {
+    let token = shutdown.token(Stage::Consumer, "ST7032 (sim)");
+    let st7032 = sim.st7032.clone();
+
+    task::spawn(async move {
+        loop {
+            if timeout(RENDER_INTERVAL, token.cancelled()).await.is_ok() {
+                break;
+            }
+
+            let mut display = st7032.lock().unwrap();
+            if display.dirty && display.changed.elapsed() >= RENDER_QUIET {
+                display.render();
+                display.dirty = false;
+            }
+        }
+    });
+
+    println!("initialized simulator");
+}
+
+impl Hardware for Sim {
+    type I2c = SimI2c;
+    type Spi = SimSpi;
+    type Input = SimInputPin;
+    type Output = SimOutputPin;
+
+    fn i2c(&self) -> EResult<SimI2c> {
+        Ok(SimI2c {
+            sim: self.clone(),
+            addr: 0,
+        })
+    }
+
+    fn spi(&self, _clock: u32) -> EResult<SimSpi> {
+        Ok(SimSpi { sim: self.clone() })
+    }
+
+    fn input_pin(&self, pin: u8, trigger: Trigger) -> EResult<SimInputPin> {
+        Ok(SimInputPin::new(self, pin, trigger))
+    }
+
+    fn input_pin_pullup(&self, pin: u8, trigger: Trigger) -> EResult<SimInputPin> {
+        Ok(SimInputPin::new(self, pin, trigger))
+    }
+
+    fn output_pin(&self, pin: u8) -> EResult<SimOutputPin> {
+        let pins = self.config.read().unwrap().gpio.clone();
+        Ok(SimOutputPin {
+            pin,
+            led: pin == pins.led_pin,
+            ccs811_reset: (Some(pin) == pins.ccs811_reset_pin).then(|| self.ccs811.clone()),
+            level: None,
+        })
+    }
+}
+
+/// 模擬I2Cバス
+///
+/// スレーブアドレスに応じて各模擬デバイスに振り分ける。
+pub struct SimI2c {
+    sim: Sim,
+    addr: u16,
+}
+
+enum Device {
+    ADT7410(usize), // 0x48からの番号
+    CCS811,
+    ST7032,
+}
+
+impl SimI2c {
+    fn device(&self) -> EResult<Device> {
+        let config = self.sim.config.read().unwrap();
+        if config.adt7410.sensors().iter().any(|s| s.addr == self.addr) {
+            Ok(Device::ADT7410((self.addr - 0x48) as usize))
+        } else if self.addr == config.ccs811.addr {
+            Ok(Device::CCS811)
+        } else if self.addr == config.st7032.addr {
+            Ok(Device::ST7032)
+        } else {
+            Err(format!("simulator: no I2C device at 0x{:02x}", self.addr).into())
+        }
+    }
+
+    fn read_reg(&mut self, reg: u8, buf: &mut [u8]) -> EResult<()> {
+        match self.device()? {
+            Device::ADT7410(i) => {
+                let now = self.sim.elapsed();
+                self.sim.adt7410.lock().unwrap()[i].read(reg, buf, now)
+            }
+            Device::CCS811 => {
+                let now = self.sim.elapsed();
+                self.sim.ccs811.lock().unwrap().read(reg, buf, now)
+            }
+            Device::ST7032 => Err("simulator: ST7032 is write only".into()),
+        }
+    }
+
+    fn write_reg(&mut self, reg: Option<u8>, data: &[u8]) -> EResult<()> {
+        match self.device()? {
+            Device::ADT7410(i) => {
+                let now = self.sim.elapsed();
+                self.sim.adt7410.lock().unwrap()[i].write(reg, data, now)
+            }
+            Device::CCS811 => {
+                let now = self.sim.elapsed();
+                self.sim.ccs811.lock().unwrap().write(reg, data, now);
+                Ok(())
+            }
+            Device::ST7032 => {
+                let control = reg.ok_or("simulator: invalid ST7032 control byte")?;
+                self.sim.st7032.lock().unwrap().write(control, data)
+            }
+        }
+    }
+}
+
+impl I2cBus for SimI2c {
+    fn set_slave_address(&mut self, addr: u16) -> EResult<()> {
+        self.addr = addr;
+        Ok(())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> EResult<()> {
+        match buf.split_first() {
+            Some((reg, data)) => self.write_reg(Some(*reg), data),
+            None => Ok(()),
+        }
+    }
+
+    fn smbus_send_byte(&mut self, value: u8) -> EResult<()> {
+        self.write_reg(None, &[value])
+    }
+
+    fn smbus_read_byte(&mut self, reg: u8) -> EResult<u8> {
+        let mut buf = [0; 1];
+        self.read_reg(reg, &mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn smbus_write_byte(&mut self, reg: u8, value: u8) -> EResult<()> {
+        self.write_reg(Some(reg), &[value])
+    }
+
+    fn smbus_read_word(&mut self, reg: u8) -> EResult<u16> {
+        // SMBusのワードは最初に受信したバイトが下位
+        let mut buf = [0; 2];
+        self.read_reg(reg, &mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn smbus_block_write(&mut self, reg: u8, buf: &[u8]) -> EResult<()> {
+        // 実機と同じくバイト数も送る
+        self.write_reg(Some(reg), &[&[buf.len() as u8][..], buf].concat())
+    }
+
+    fn block_read(&mut self, reg: u8, buf: &mut [u8]) -> EResult<()> {
+        self.read_reg(reg, buf)
+    }
+}
+
+/// 気温の曲線
+fn temperature(t: f64) -> f64 {
+    TEMP_BASE + TEMP_AMPLITUDE * (2.0 * PI * t / TEMP_PERIOD).sin()
+}
+
+/// 明るさの曲線（%）
+fn light(t: f64) -> f64 {
+    LIGHT_BASE + LIGHT_AMPLITUDE * (2.0 * PI * t / LIGHT_PERIOD).sin()
+}
+
+/// 在室人数
+fn occupancy(t: f64) -> u32 {
+    let step = (t / OCCUPANCY_STEP) as usize;
+    OCCUPANCY[step % OCCUPANCY.len()]
+}
+
+/// 模擬ADT7410
+///
+/// 気温の曲線を、コンフィギュレーションレジスタで設定された分解能で返す。
+/// ワンショットとシャットダウンでは、最後に変換した気温を返し続ける。
+/// INTとCTはコンパレータモード、アクティブLowとして振る舞う。
+struct ADT7410 {
+    offset: f64, // 気温の曲線との差
+    config: u8,
+    held: Option<f64>, // 変換を止めている間の気温
+    t_high: i16,       // 1/128度単位
+    t_low: i16,
+    t_crit: i16,
+    t_hyst: u8, // 度
+    int_active: bool,
+    ct_active: bool,
+}
+
+impl ADT7410 {
+    const REG_TEMP: u8 = 0;
+    const REG_CONFIG: u8 = 3;
+    const REG_T_HIGH: u8 = 4;
+    const REG_T_LOW: u8 = 6;
+    const REG_T_CRIT: u8 = 8;
+    const REG_T_HYST: u8 = 0x0a;
+    const REG_ID: u8 = 0x0b;
+
+    const ID: u8 = 0xcb;
+    const CONFIG_RESOLUTION: u8 = 0b1000_0000;
+    const CONFIG_MODE: u8 = 0b0110_0000;
+    const MODE_ONE_SHOT: u8 = 0b0010_0000;
+    const MODE_SHUTDOWN: u8 = 0b0110_0000;
+
+    fn new(offset: f64) -> Self {
+        // 電源投入時の値
+        ADT7410 {
+            offset,
+            config: 0,
+            held: None,
+            t_high: 64 * 128,
+            t_low: 10 * 128,
+            t_crit: 147 * 128,
+            t_hyst: 5,
+            int_active: false,
+            ct_active: false,
+        }
+    }
+
+    /// INTとCTがアクティブか
+    fn outputs(&mut self, now: f64) -> (bool, bool) {
+        let t = temperature(now) + self.offset;
+        let (high, low, crit) = (
+            self.t_high as f64 / 128.0,
+            self.t_low as f64 / 128.0,
+            self.t_crit as f64 / 128.0,
+        );
+        let hyst = self.t_hyst as f64;
+
+        self.int_active = if self.int_active {
+            t > high - hyst || t < low + hyst
+        } else {
+            t > high || t < low
+        };
+        self.ct_active = if self.ct_active {
+            t > crit - hyst
+        } else {
+            t > crit
+        };
+
+        (self.int_active, self.ct_active)
+    }
+
+    fn read(&self, reg: u8, buf: &mut [u8], now: f64) -> EResult<()> {
+        let data = match reg {
+            Self::REG_TEMP => {
+                // 2の補数、上位バイトから送信
+                let t = self.held.unwrap_or_else(|| temperature(now) + self.offset);
+                let code = if self.config & Self::CONFIG_RESOLUTION != 0 {
+                    (t * 128.0) as i16 // 16ビット、1/128度単位
+                } else {
+                    ((t * 16.0) as i16) << 3 // 13ビット、1/16度単位
+                };
+                code.to_be_bytes().to_vec()
+            }
+            Self::REG_CONFIG => vec![self.config],
+            Self::REG_T_HIGH => self.t_high.to_be_bytes().to_vec(),
+            Self::REG_T_LOW => self.t_low.to_be_bytes().to_vec(),
+            Self::REG_T_CRIT => self.t_crit.to_be_bytes().to_vec(),
+            Self::REG_T_HYST => vec![self.t_hyst],
+            Self::REG_ID => vec![Self::ID],
+            _ => return Err(format!("simulator: ADT7410 register 0x{reg:02x}").into()),
+        };
+
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = data.get(i).copied().unwrap_or(0);
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, reg: Option<u8>, data: &[u8], now: f64) -> EResult<()> {
+        let word = |data: &[u8]| -> EResult<i16> {
+            match data {
+                [msb, lsb] => Ok(i16::from_be_bytes([*msb, *lsb])),
+                _ => Err("simulator: ADT7410 setpoints are 16-bit".into()),
+            }
+        };
+
+        match (reg, data.first()) {
+            (Some(Self::REG_CONFIG), Some(c)) => {
+                self.config = *c;
+                self.held = match *c & Self::CONFIG_MODE {
+                    Self::MODE_ONE_SHOT => Some(temperature(now) + self.offset), // 一度だけ変換
+                    Self::MODE_SHUTDOWN => self.held.or(Some(temperature(now) + self.offset)),
+                    _ => None,
+                };
+            }
+            (Some(Self::REG_T_HIGH), _) => self.t_high = word(data)?,
+            (Some(Self::REG_T_LOW), _) => self.t_low = word(data)?,
+            (Some(Self::REG_T_CRIT), _) => self.t_crit = word(data)?,
+            (Some(Self::REG_T_HYST), Some(h)) => self.t_hyst = *h & 0x0f,
+            _ => return Err("simulator: invalid ADT7410 write".into()),
+        }
+        Ok(())
+    }
+}
+
+/// 模擬CCS811
+///
+/// 在室人数に応じてCO2が増加し、換気によって外気の濃度に近づく。
+/// MEAS_MODEを書き込んだ時刻から、DRIVE_MODEの周期で新しいデータを用意する。
+/// 閾値の割り込みでは、最後に読み込んだ時からCO2の段階が変わった場合のみnINTをアクティブにする。
+/// 起動から`CCS811_FAULT_AT`秒後に一度だけHEATER_FAULTとなり、リセットするまでERRORを返す。
+struct CCS811 {
+    fw_start: bool,
+    fault: bool,   // HEATER_FAULTの発生中
+    faulted: bool, // HEATER_FAULTが発生済み
+    mode: u8,
+    mode_set: f64,        // MEAS_MODEを書き込んだ時刻
+    thresholds: [u16; 2], // 低から中、中から高
+    hysteresis: u16,
+    read_band: usize, // 最後に読み込んだ時のCO2の段階
+    baseline: u16,
+    co2: f64,
+    updated: f64,   // 最後にモデルを更新した時刻
+    last_read: f64, // 最後にデータを読み込んだ時刻
+}
+
+impl CCS811 {
+    const HW_ID: u8 = 0x81;
+    const HW_VERSION: u8 = 0x12;
+    const FW_BOOT_VERSION: [u8; 2] = [0x10, 0x00]; // 1.0.0
+    const FW_APP_VERSION: [u8; 2] = [0x20, 0x00]; // 2.0.0
+    const STATUS_FW_START: u8 = 0b1000_0000;
+    const STATUS_APP_VALID: u8 = 0b0001_0000;
+    const STATUS_DATA_READY: u8 = 0b0000_1000;
+    const STATUS_ERROR: u8 = 0b0000_0001;
+    const ERROR_HEATER_FAULT: u8 = 0b0001_0000;
+    const SW_RESET: [u8; 4] = [0x11, 0xe5, 0x72, 0x8a];
+    const MODE_INT_DATARDY: u8 = 0b0000_1000;
+    const MODE_INT_THRESH: u8 = 0b0000_0100;
+
+    const REG_STATUS: u8 = 0;
+    const REG_MEAS_MODE: u8 = 1;
+    const REG_ALG_RESULT_DATA: u8 = 2;
+    const REG_RAW_DATA: u8 = 3;
+    const REG_THRESHOLDS: u8 = 0x10;
+    const REG_BASELINE: u8 = 0x11;
+    const REG_HW_ID: u8 = 0x20;
+    const REG_HW_VERSION: u8 = 0x21;
+    const REG_FW_BOOT_VERSION: u8 = 0x23;
+    const REG_FW_APP_VERSION: u8 = 0x24;
+    const REG_ERROR_ID: u8 = 0xe0;
+    const REG_APP_START: u8 = 0xf4;
+    const REG_SW_RESET: u8 = 0xff;
+
+    fn new() -> Self {
+        CCS811 {
+            fw_start: false,
+            fault: false,
+            faulted: false,
+            mode: 0,
+            mode_set: 0.0,
+            thresholds: [1500, 2500],
+            hysteresis: 50,
+            read_band: 0,
+            baseline: 0x8a3c,
+            co2: CO2_OUTDOOR,
+            updated: 0.0,
+            last_read: 0.0,
+        }
+    }
+
+    fn update(&mut self, now: f64) {
+        let dt = now - self.updated;
+        if dt <= 0.0 {
+            return;
+        }
+
+        let people = occupancy(now) as f64;
+        self.co2 += (people * CO2_PER_PERSON - (self.co2 - CO2_OUTDOOR) / VENTILATION) * dt;
+        self.updated = now;
+
+        if self.fw_start && !self.faulted && now >= CCS811_FAULT_AT {
+            self.fault = true;
+            self.faulted = true;
+        }
+    }
+
+    /// SW_RESET、nRESETでブートモードに戻る
+    fn reset(&mut self) {
+        self.fw_start = false;
+        self.fault = false;
+        self.mode = 0;
+    }
+
+    /// ERROR_IDの値
+    fn error_id(&self) -> u8 {
+        if self.fault {
+            Self::ERROR_HEATER_FAULT
+        } else {
+            0
+        }
+    }
+
+    /// DRIVE_MODEの測定周期（秒）
+    fn period(&self) -> Option<f64> {
+        match (self.mode >> 4) & 0b111 {
+            1 => Some(1.0),
+            2 => Some(10.0),
+            3 => Some(60.0),
+            4 => Some(0.25),
+            _ => None,
+        }
+    }
+
+    /// 最後に読み込んでから新しいデータがあるか
+    fn data_ready(&self, now: f64) -> bool {
+        let Some(period) = self.period().filter(|_| self.fw_start) else {
+            return false;
+        };
+        let samples = ((now - self.mode_set) / period).floor();
+        samples >= 1.0 && self.mode_set + samples * period > self.last_read
+    }
+
+    /// CO2の段階（0: 低、1: 中、2: 高）
+    ///
+    /// 最後に読み込んだ段階より下がるのは、閾値からヒステリシス分下回った時。
+    fn band(&self) -> usize {
+        self.thresholds
+            .iter()
+            .enumerate()
+            .filter(|(i, t)| {
+                let hyst = if *i < self.read_band {
+                    self.hysteresis
+                } else {
+                    0
+                };
+                self.co2 >= t.saturating_sub(hyst) as f64
+            })
+            .count()
+    }
+
+    /// nINTがアクティブか
+    fn interrupt(&mut self, now: f64) -> bool {
+        if self.mode & Self::MODE_INT_DATARDY == 0 || !self.data_ready(now) {
+            return false;
+        }
+        if self.mode & Self::MODE_INT_THRESH != 0 {
+            self.update(now);
+            return self.band() != self.read_band;
+        }
+        true
+    }
+
+    /// RAW_DATAの値（電流は20 μA固定）
+    ///
+    /// CO2が多いほど抵抗が下がり、電圧が下がる。
+    fn raw_data(&self) -> [u8; 2] {
+        let current = 20u16;
+        let adc = (600.0 - (self.co2 - CO2_OUTDOOR) / 10.0).clamp(0.0, 1023.0) as u16;
+        ((current << 10) | adc).to_be_bytes()
+    }
+
+    fn status(&self, now: f64) -> u8 {
+        let mut status = Self::STATUS_APP_VALID;
+        if self.fault {
+            status |= Self::STATUS_ERROR;
+        }
+        if self.fw_start {
+            status |= Self::STATUS_FW_START;
+            if self.data_ready(now) {
+                status |= Self::STATUS_DATA_READY;
+            }
+        }
+        status
+    }
+
+    fn read(&mut self, reg: u8, buf: &mut [u8], now: f64) -> EResult<()> {
+        self.update(now);
+
+        let data = match reg {
+            Self::REG_STATUS => vec![self.status(now)],
+            Self::REG_MEAS_MODE => vec![self.mode],
+            Self::REG_BASELINE => self.baseline.to_be_bytes().to_vec(),
+            Self::REG_HW_ID => vec![Self::HW_ID],
+            Self::REG_HW_VERSION => vec![Self::HW_VERSION],
+            Self::REG_FW_BOOT_VERSION => Self::FW_BOOT_VERSION.to_vec(),
+            Self::REG_FW_APP_VERSION => Self::FW_APP_VERSION.to_vec(),
+            Self::REG_ERROR_ID => vec![self.error_id()],
+            Self::REG_ALG_RESULT_DATA => {
+                let status = self.status(now);
+                self.last_read = now;
+                self.read_band = self.band();
+
+                let co2 = self.co2 as u16;
+                let tvoc = ((self.co2 - CO2_OUTDOOR).max(0.0) * TVOC_PER_PPM) as u16;
+                let [c0, c1] = co2.to_be_bytes();
+                let [t0, t1] = tvoc.to_be_bytes();
+                let [r0, r1] = self.raw_data();
+                vec![c0, c1, t0, t1, status, self.error_id(), r0, r1]
+            }
+            Self::REG_RAW_DATA => {
+                self.last_read = now;
+                self.raw_data().to_vec()
+            }
+            _ => return Err(format!("simulator: CCS811 register 0x{reg:02x}").into()),
+        };
+
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = data.get(i).copied().unwrap_or(0);
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, reg: Option<u8>, data: &[u8], now: f64) {
+        match reg {
+            None if data == [Self::REG_APP_START] => self.fw_start = true,
+            Some(Self::REG_SW_RESET) if data == Self::SW_RESET => self.reset(),
+            Some(Self::REG_MEAS_MODE) => {
+                self.mode = data.first().copied().unwrap_or(0);
+                self.mode_set = now;
+                self.last_read = now;
+            }
+            Some(Self::REG_THRESHOLDS) if data.len() == 5 => {
+                self.thresholds = [
+                    u16::from_be_bytes([data[0], data[1]]),
+                    u16::from_be_bytes([data[2], data[3]]),
+                ];
+                self.hysteresis = data[4] as u16;
+            }
+            Some(Self::REG_BASELINE) if data.len() == 2 => {
+                self.baseline = u16::from_be_bytes([data[0], data[1]])
+            }
+            _ => (), // ENV_DATAなどは測定値に影響しない
+        }
+    }
+}
+
+/// 仮想ST7032
+///
+/// 2x16文字のDDRAM、外字のCGRAM、アイコンRAMを保持し、端末に表示する。
+/// 表示がオフの間は空白を表示し、コントラストと点灯しているアイコンの数を枠の下に表示する。
+struct ST7032 {
+    ddram: [[u8; 16]; 2],
+    cgram: [[u8; 8]; 8],
+    icons: [u8; 16],
+    cursor: (usize, usize), // (行, 列)
+    cgram_addr: Option<u8>, // CGRAMへの書き込み中のアドレス
+    icon_addr: Option<u8>,  // アイコンRAMへの書き込み中のアドレス
+    extended: bool,         // 拡張命令セット
+    contrast: u8,
+    icon: bool,    // アイコンを表示
+    display: bool, // 表示
+    dirty: bool,
+    changed: Instant, // 最後に書き込まれた時刻
+}
+
+impl ST7032 {
+    fn new() -> Self {
+        ST7032 {
+            ddram: [[b' '; 16]; 2],
+            cgram: [[0; 8]; 8],
+            icons: [0; 16],
+            cursor: (0, 0),
+            cgram_addr: None,
+            icon_addr: None,
+            extended: false,
+            contrast: 0,
+            icon: false,
+            display: false,
+            dirty: true,
+            changed: Instant::now(),
+        }
+    }
+
+    /// コントロールバイトに続くコマンドと表示データ
+    ///
+    /// Coビットが1の場合は1バイトごとに次のコントロールバイトが続き、
+    /// 0の場合は残りがすべてRSビットの種類のバイトとなる。
+    fn write(&mut self, control: u8, data: &[u8]) -> EResult<()> {
+        let (mut control, mut data) = (control, data);
+        loop {
+            if control & 0x3f != 0 {
+                return Err("simulator: invalid ST7032 control byte".into());
+            }
+            let rs = control & 0x40 != 0;
+            let mut put = |c: u8| if rs { self.data(c) } else { self.command(c) };
+
+            if control & 0x80 == 0 {
+                data.iter().for_each(|c| put(*c));
+                return Ok(());
+            }
+            let Some((c, rest)) = data.split_first() else {
+                return Ok(());
+            };
+            put(*c);
+            let Some((next, rest)) = rest.split_first() else {
+                return Ok(());
+            };
+            (control, data) = (*next, rest);
+        }
+    }
+
+    fn command(&mut self, c: u8) {
+        let (extended, contrast, icon, display) =
+            (self.extended, self.contrast, self.icon, self.display);
+        if c == 0x01 {
+            // クリア
+            self.ddram = [[b' '; 16]; 2];
+            self.cursor = (0, 0);
+            (self.cgram_addr, self.icon_addr) = (None, None);
+            self.dirty = true;
+            self.changed = Instant::now();
+        } else if c & 0x80 != 0 {
+            // DDRAMアドレス設定
+            let addr = c & 0x7f;
+            self.cursor = (if addr >= 0x40 { 1 } else { 0 }, (addr & 0x3f) as usize);
+            (self.cgram_addr, self.icon_addr) = (None, None);
+        } else if c & 0xe0 == 0x20 {
+            // ファンクションセット（IS）
+            self.extended = c & 0x01 != 0;
+        } else if c & 0xc0 == 0x40 && !extended {
+            // CGRAMアドレス設定
+            (self.cgram_addr, self.icon_addr) = (Some(c & 0x3f), None);
+        } else if c & 0xf0 == 0x40 {
+            // アイコンRAMアドレス設定
+            (self.cgram_addr, self.icon_addr) = (None, Some(c & 0x0f));
+        } else if c & 0xf0 == 0x50 {
+            // アイコン、昇圧回路、コントラスト（上位2ビット）
+            self.icon = c & 0x08 != 0;
+            self.contrast = (self.contrast & 0x0f) | ((c & 0x03) << 4);
+        } else if c & 0xf0 == 0x70 && extended {
+            // コントラスト（下位4ビット）
+            self.contrast = (self.contrast & 0x30) | (c & 0x0f);
+        } else if c & 0xf8 == 0x08 {
+            // 表示、カーソル、点滅
+            self.display = c & 0x04 != 0;
+        } else if c & 0xfe == 0x02 {
+            // カーソルを先頭へ
+            self.cursor = (0, 0);
+            (self.cgram_addr, self.icon_addr) = (None, None);
+        }
+
+        if (contrast, icon, display) != (self.contrast, self.icon, self.display) {
+            self.dirty = true;
+            self.changed = Instant::now();
+        }
+    }
+
+    fn data(&mut self, c: u8) {
+        if let Some(addr) = self.cgram_addr {
+            self.cgram[(addr >> 3) as usize][(addr & 0x07) as usize] = c & 0x1f;
+            self.cgram_addr = Some((addr + 1) & 0x3f);
+            self.dirty = true;
+            return;
+        }
+        if let Some(addr) = self.icon_addr {
+            self.icons[addr as usize] = c & 0x1f;
+            self.icon_addr = Some((addr + 1) & 0x0f);
+            self.dirty = true;
+            return;
+        }
+
+        let (row, col) = self.cursor;
+        if col < 16 {
+            self.ddram[row][col] = c;
+            self.dirty = true;
+            self.changed = Instant::now();
+        }
+        self.cursor = (row, col + 1);
+    }
+
+    /// 外字に対応する文字（組み込みの外字以外は`*`）
+    fn glyph(&self, code: u8) -> char {
+        #[cfg(feature = "st7032")]
+        {
+            let glyph = &self.cgram[code as usize & 0x07];
+            if let Some((c, _)) = crate::i2c::st7032::glyph::builtin()
+                .into_iter()
+                .find(|(_, g)| g == glyph)
+            {
+                return c;
+            }
+        }
+        #[cfg(not(feature = "st7032"))]
+        let _ = code;
+        '*'
+    }
+
+    fn render(&self) {
+        let line = |row: &[u8; 16]| -> String {
+            if !self.display {
+                return " ".repeat(16);
+            }
+            row.iter()
+                .map(|c| match c {
+                    0x00..=0x07 => self.glyph(*c),
+                    0xff => '█',
+                    c if c.is_ascii_graphic() => *c as char,
+                    _ => ' ',
+                })
+                .collect()
+        };
+
+        println!("ST7032 +----------------+");
+        println!("       |{}|", line(&self.ddram[0]));
+        println!("       |{}|", line(&self.ddram[1]));
+        let mut status = format!("contrast {}", self.contrast);
+        if self.icon {
+            let lit: u32 = self.icons.iter().map(|b| b.count_ones()).sum();
+            status += &format!(", icons {lit}");
+        }
+        println!("       +----------------+ {status}");
+    }
+}
+
+/// 模擬MCP3208
+///
+/// チャネル0は明るさの曲線、その他のチャネルは0を返す。
+pub struct SimSpi {
+    sim: Sim,
+}
+
+impl SpiBus for SimSpi {
+    fn transfer(&mut self, read_buf: &mut [u8], write_buf: &[u8]) -> EResult<usize> {
+        // スタートビットの後、D2、D1、D0の順にチャネルを指定
+        let d2 = write_buf.first().copied().unwrap_or(0) & 0b1;
+        let d1d0 = write_buf.get(1).copied().unwrap_or(0) >> 6;
+        let channel = (d2 << 2) | d1d0;
+
+        let val = if channel == 0 {
+            (light(self.sim.elapsed()) / 100.0 * 4095.0) as u16
+        } else {
+            0
+        };
+
+        let data = [0, (val >> 8) as u8 & 0x0f, val as u8];
+        for (i, b) in read_buf.iter_mut().enumerate() {
+            *b = data.get(i).copied().unwrap_or(0);
+        }
+
+        Ok(read_buf.len())
+    }
+}
+
+/// 入力ピンに接続された模擬デバイス
+enum Input {
+    Button,            // 一定間隔で押される
+    ADT7410Int(usize), // 模擬ADT7410（0x48からの番号）のINT
+    ADT7410Ct(usize),  // 模擬ADT7410（0x48からの番号）のCT
+    CCS811Int,         // 模擬CCS811のnINT
+}
+
+/// 模擬入力ピン
+///
+/// ピン番号に応じて、ボタンかADT7410のINT、CT、CCS811のnINTとして振る舞う。
+pub struct SimInputPin {
+    sim: Sim,
+    pin: u8,
+    input: Input,
+    trigger: Trigger, // poll_interruptで通知する変化
+    level: Level,
+}
+
+impl SimInputPin {
+    fn new(sim: &Sim, pin: u8, trigger: Trigger) -> Self {
+        let config = sim.config.read().unwrap().clone();
+        let sensors = config.alarm_sensors();
+        let index = |s: &config::Sensor| (s.addr - 0x48) as usize;
+        let input = if let Some(s) = sensors.iter().find(|s| s.int_pin == Some(pin)) {
+            Input::ADT7410Int(index(s))
+        } else if let Some(s) = sensors.iter().find(|s| s.ct_pin == Some(pin)) {
+            Input::ADT7410Ct(index(s))
+        } else if Some(pin) == config.gpio.ccs811_int_pin {
+            Input::CCS811Int
+        } else {
+            Input::Button
+        };
+
+        let mut p = SimInputPin {
+            sim: sim.clone(),
+            pin,
+            input,
+            trigger,
+            level: Level::Low,
+        };
+        p.level = p.read();
+        p
+    }
+
+    fn button_at(t: Duration) -> Level {
+        let n = t.as_millis() / BUTTON_PERIOD.as_millis();
+        let press = if n % 4 == 3 {
+            BUTTON_LONG_PRESS
+        } else {
+            BUTTON_PRESS
+        };
+        let phase = t.as_millis() % BUTTON_PERIOD.as_millis();
+        if phase >= (BUTTON_PERIOD - press).as_millis() {
+            Level::High
+        } else {
+            Level::Low
+        }
+    }
+}
+
+impl InputPin for SimInputPin {
+    fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    fn read(&self) -> Level {
+        match self.input {
+            Input::Button => Self::button_at(self.sim.start.elapsed()),
+            Input::CCS811Int => {
+                let now = self.sim.elapsed();
+                if self.sim.ccs811.lock().unwrap().interrupt(now) {
+                    Level::Low // アクティブLow
+                } else {
+                    Level::High
+                }
+            }
+            Input::ADT7410Int(i) | Input::ADT7410Ct(i) => {
+                let now = self.sim.elapsed();
+                let (int, ct) = self.sim.adt7410.lock().unwrap()[i].outputs(now);
+                let active = match self.input {
+                    Input::ADT7410Int(_) => int,
+                    _ => ct,
+                };
+                if active {
+                    Level::Low // アクティブLow
+                } else {
+                    Level::High
+                }
+            }
+        }
+    }
+
+    fn poll_interrupt(
+        &mut self,
+        _reset: bool,
+        timeout: Option<Duration>,
+    ) -> EResult<Option<Level>> {
+        // 実機と同様にブロックし、triggerのレベルの変化を待つ
+        let step = Duration::from_millis(10);
+        let deadline = timeout.map(|t| Instant::now() + t);
+
+        loop {
+            let level = self.read();
+            if level != self.level {
+                self.level = level;
+                let notify = match self.trigger {
+                    Trigger::Disabled => false,
+                    Trigger::RisingEdge => level == Level::High,
+                    Trigger::FallingEdge => level == Level::Low,
+                    Trigger::Both => true,
+                };
+                if notify {
+                    return Ok(Some(level));
+                }
+            }
+
+            if let Some(d) = deadline {
+                if Instant::now() >= d {
+                    return Ok(None);
+                }
+            }
+
+            thread::sleep(step);
+        }
+    }
+}
+
+/// 模擬出力ピン
+///
+/// LEDの場合は変化を表示する。
+pub struct SimOutputPin {
+    pin: u8,
+    led: bool,
+    ccs811_reset: Option<Arc<Mutex<CCS811>>>, // nRESETに接続した模擬CCS811
+    level: Option<Level>,
+}
+
+impl SimOutputPin {
+    fn set(&mut self, level: Level) {
+        if self.led && self.level != Some(level) {
+            println!("LED(GPIO {}): {level}", self.pin);
+        }
+        if let (Some(ccs811), Level::Low) = (&self.ccs811_reset, level) {
+            ccs811.lock().unwrap().reset();
+        }
+        self.level = Some(level);
+    }
+}
+
+impl OutputPin for SimOutputPin {
+    fn set_high(&mut self) {
+        self.set(Level::High);
+    }
+
+    fn set_low(&mut self) {
+        self.set(Level::Low);
+    }
+}
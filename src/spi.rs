@@ -1,18 +1,48 @@
+#[cfg(feature = "linux")]
 #[allow(unused_imports)]
 use async_std::prelude::*;
 
+#[cfg(feature = "linux")]
 use super::EResult;
-use async_std::{channel::Receiver, task::JoinHandle};
+#[cfg(feature = "linux")]
+use crate::{
+    config::SharedConfig,
+    hal::Hardware,
+    shutdown::{Shutdown, Stage},
+    supervisor::{self, Supervisor},
+};
+#[cfg(feature = "linux")]
+use async_std::task::JoinHandle;
+#[cfg(feature = "linux")]
 use std::sync::{atomic::AtomicU64, Arc};
 
-mod mcp3208;
+pub mod mcp3208;
 
-trait Runner {
-    fn run(self) -> EResult<JoinHandle<()>>;
+/// SPIデバイスを周期的に実行するタスク
+#[cfg(feature = "linux")]
+pub trait Runner<S> {
+    /// タスクを起動。終了が要求されるとJoinHandleが完了する
+    fn run(self, spi: S) -> EResult<JoinHandle<EResult<()>>>;
 }
 
-pub async fn run(sig_rx: Receiver<()>, bright: Arc<AtomicU64>) -> EResult<JoinHandle<()>> {
-    let hdl = mcp3208::MCP3208::new(sig_rx, bright).run()?;
+/// SPIのタスクを監視付きで起動
+#[cfg(feature = "linux")]
+pub async fn run<H: Hardware>(
+    hw: &H,
+    shutdown: &Shutdown,
+    supervisor: &Supervisor,
+    config: SharedConfig,
+    bright: Arc<AtomicU64>,
+) -> EResult<()> {
+    let token = shutdown.token(Stage::Producer, "MCP3208");
+    let hw = hw.clone();
+    supervisor.spawn("MCP3208", token, move |token| {
+        let clock = config.read().unwrap().mcp3208.clock;
+        let hdl = hw
+            .spi(clock)
+            .and_then(|spi| mcp3208::MCP3208::new(token, config.clone(), bright.clone()).run(spi));
+        supervisor::join(hdl)
+    });
     println!("initialized SPI");
-    Ok(hdl)
+    Ok(())
 }
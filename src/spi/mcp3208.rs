@@ -1,22 +1,16 @@
-#[allow(unused_imports)]
-use async_std::prelude::*;
+//! ADコンバータMCP3208
+//!
+//! コマンドと受信データの変換は、Linuxのドライバ[MCP3208]と`embedded-hal`版の[eh::MCP3208]で共通。
 
-use super::Runner;
-use crate::{perror, EResult};
-use async_std::{
-    channel::Receiver,
-    future::timeout,
-    task::{self, JoinHandle},
-};
 use bitflags::bitflags;
-use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
-use std::{
-    sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
-    },
-    time::Duration,
-};
+
+#[cfg(feature = "embedded-hal")]
+pub mod eh;
+#[cfg(feature = "linux")]
+mod linux;
+
+#[cfg(feature = "linux")]
+pub use linux::MCP3208;
 
 bitflags! {
     struct MCP3208_0: u8 {
@@ -31,50 +25,14 @@ bitflags! {
     }
 }
 
-pub(super) struct MCP3208 {
-    sig_rx: Receiver<()>,
-    bright: Arc<AtomicU64>,
-}
-
-impl MCP3208 {
-    const CLOCK: u32 = 1000 * 1000; // 1 MHz
-
-    pub(super) fn new(sig_rx: Receiver<()>, bright: Arc<AtomicU64>) -> Self {
-        MCP3208 { sig_rx, bright }
-    }
+/// チャネル0を読み込むコマンド
+fn command() -> [u8; 3] {
+    [(MCP3208_0::START | MCP3208_0::SGL).bits, 0, 0]
 }
 
-impl Runner for MCP3208 {
-    fn run(self) -> EResult<JoinHandle<()>> {
-        let s = Spi::new(Bus::Spi0, SlaveSelect::Ss0, Self::CLOCK, Mode::Mode0)?;
-
-        let f = async move {
-            let wsec = Duration::from_secs(1);
-            let mut read_buf: [u8; 3] = [0; 3];
-            let write_buf: [u8; 3] = [(MCP3208_0::START | MCP3208_0::SGL).bits, 0, 0];
-
-            loop {
-                // タイムアウトかシグナルでの終了を待つ
-                if timeout(wsec, self.sig_rx.recv()).await.is_ok() {
-                    println!("exiting MCP3208 ...");
-                    break;
-                }
-
-                // MCP3208から読み込み
-                match s.transfer(&mut read_buf, &write_buf) {
-                    Ok(_size) => {
-                        let val = ((read_buf[1] & 0b00001111) as u16) << 8 | read_buf[0] as u16;
-                        let per = val as f64 / 4096.0 as f64 * 100.0;
-                        self.bright.store(per.to_bits(), Ordering::Relaxed); // 共有変数に保存
-                        println!("MCP3208(0): {:.2} %", per);
-                    }
-                    Err(e) => {
-                        perror!(e);
-                    }
-                }
-            }
-        };
-
-        Ok(task::spawn(f))
-    }
+/// 受信したデータを百分率に変換
+fn decode(read_buf: &[u8; 3]) -> f64 {
+    // 2バイト目の下位4ビットが上位、3バイト目が下位8ビット
+    let val = ((read_buf[1] & 0b00001111) as u16) << 8 | read_buf[2] as u16;
+    val as f64 / 4096.0 * 100.0
 }
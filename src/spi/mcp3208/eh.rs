@@ -0,0 +1,93 @@
+//! `embedded-hal`のSPIで動作するMCP3208
+//!
+//! コマンドと受信データの変換はLinuxのドライバと共通。std、allocを使わない。
+
+use super::{command, decode};
+use core::fmt;
+
+/// エラー
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    Bus(E), // SPIの通信エラー
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::Bus(e)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Bus(e) => write!(f, "MCP3208: bus error: {e:?}"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for Error<E> {}
+
+/// ADコンバータMCP3208
+pub struct MCP3208<SPI> {
+    spi: SPI,
+}
+
+impl<SPI> MCP3208<SPI> {
+    /// 生成
+    ///
+    /// `spi`はチップセレクトを含むデバイスで、モード0を設定しておく。
+    pub fn new(spi: SPI) -> Self {
+        MCP3208 { spi }
+    }
+
+    /// SPIを返して破棄
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+}
+
+impl<SPI: embedded_hal::spi::SpiDevice> MCP3208<SPI> {
+    /// チャネル0の値を一度読み込み、百分率で返す
+    pub fn read(&mut self) -> Result<f64, Error<SPI::Error>> {
+        let mut read_buf = [0; 3];
+        self.spi.transfer(&mut read_buf, &command())?;
+        Ok(decode(&read_buf))
+    }
+}
+
+impl<SPI: embedded_hal_async::spi::SpiDevice> MCP3208<SPI> {
+    /// チャネル0の値を一度読み込み、百分率で返す（非同期）
+    pub async fn read_async(&mut self) -> Result<f64, Error<SPI::Error>> {
+        let mut read_buf = [0; 3];
+        self.spi.transfer(&mut read_buf, &command()).await?;
+        Ok(decode(&read_buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal_mock::eh1::spi::{Mock, Transaction};
+
+    fn transactions() -> Vec<Transaction<u8>> {
+        vec![
+            Transaction::transaction_start(),
+            Transaction::transfer(vec![0b0000_0110, 0, 0], vec![0xff, 0b1110_1000, 0x00]), // 0x800
+            Transaction::transaction_end(),
+        ]
+    }
+
+    #[test]
+    fn read_channel0() {
+        let mut mcp3208 = MCP3208::new(Mock::new(&transactions()));
+        assert_eq!(mcp3208.read().unwrap(), 50.0);
+        mcp3208.release().done();
+    }
+
+    #[async_std::test]
+    async fn read_channel0_async() {
+        let mut mcp3208 = MCP3208::new(Mock::new(&transactions()));
+        assert_eq!(mcp3208.read_async().await.unwrap(), 50.0);
+        mcp3208.release().done();
+    }
+}
@@ -0,0 +1,105 @@
+//! Linuxの[SpiBus]で動作するMCP3208
+
+#[allow(unused_imports)]
+use async_std::prelude::*;
+
+use super::{command, decode};
+use crate::{
+    config::{self, SharedConfig},
+    hal::SpiBus,
+    perror,
+    shutdown::Token,
+    spi::Runner,
+    EResult,
+};
+use async_std::{
+    future::timeout,
+    task::{self, JoinHandle},
+};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// ADコンバータMCP3208
+pub struct MCP3208 {
+    shutdown: Token,
+    config: SharedConfig,
+    bright: Arc<AtomicU64>,
+}
+
+impl MCP3208 {
+    /// 生成
+    ///
+    /// 読み込んだチャネル0の値は百分率で`bright`に`f64::to_bits`で格納される。
+    pub fn new(shutdown: Token, config: SharedConfig, bright: Arc<AtomicU64>) -> Self {
+        MCP3208 {
+            shutdown,
+            config,
+            bright,
+        }
+    }
+
+    /// チャネル0の値を一度読み込み、百分率で返す
+    pub fn read<S: SpiBus>(spi: &mut S) -> EResult<f64> {
+        let mut read_buf: [u8; 3] = [0; 3];
+        spi.transfer(&mut read_buf, &command())?;
+        Ok(decode(&read_buf))
+    }
+}
+
+impl<S: SpiBus> Runner<S> for MCP3208 {
+    fn run(self, mut spi: S) -> EResult<JoinHandle<EResult<()>>> {
+        let f = async move {
+            loop {
+                let wsec = config::millis(self.config.read().unwrap().mcp3208.interval_ms);
+
+                // タイムアウトかシグナルでの終了を待つ
+                if timeout(wsec, self.shutdown.cancelled()).await.is_ok() {
+                    println!("exiting MCP3208 ...");
+                    break;
+                }
+
+                // MCP3208から読み込み
+                match Self::read(&mut spi) {
+                    Ok(per) => {
+                        self.bright.store(per.to_bits(), Ordering::Relaxed); // 共有変数に保存
+                        println!("MCP3208(0): {:.2} %", per);
+                    }
+                    Err(e) => {
+                        perror!(e);
+                    }
+                }
+            }
+
+            Ok(())
+        };
+
+        Ok(task::spawn(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::fake::FakeSpi;
+
+    #[test]
+    fn read_channel0() {
+        let mut spi = FakeSpi::new();
+        spi.push_response(&[0xff, 0b1110_1000, 0x00]); // 0x800
+
+        let per = MCP3208::read(&mut spi).unwrap();
+        assert_eq!(per, 50.0);
+        assert_eq!(spi.writes(), vec![vec![0b0000_0110, 0, 0]]);
+    }
+
+    #[test]
+    fn decode_low_byte() {
+        // 下位8ビットは3バイト目（1バイト目はnullビットの前で不定）
+        assert_eq!(decode(&[0x00, 0b0000_0000, 0xff]), 255.0 / 4096.0 * 100.0);
+        assert_eq!(decode(&[0xff, 0b0000_0001, 0x00]), 256.0 / 4096.0 * 100.0);
+        assert_eq!(decode(&[0xff, 0b1111_1111, 0xff]), 4095.0 / 4096.0 * 100.0);
+        // 上位4ビットは無視
+    }
+}
@@ -0,0 +1,128 @@
+#[allow(unused_imports)]
+use async_std::prelude::*;
+
+use crate::{
+    config::{self, SharedConfig},
+    shutdown::Token,
+    EResult,
+};
+use async_std::{
+    future::timeout,
+    task::{self, JoinHandle},
+};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// タスクごとの再起動の記録
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    pub restarts: u32,              // 再起動回数
+    pub last_error: Option<String>, // 最後のエラー
+    pub gave_up: bool,              // 再起動回数の上限に達した
+}
+
+/// タスクの監視
+///
+/// 終了が要求されていないのにタスクが終了した場合、指数バックオフで再起動する。
+#[derive(Clone)]
+pub struct Supervisor {
+    config: SharedConfig,
+    stats: Arc<Mutex<BTreeMap<&'static str, Stats>>>,
+}
+
+impl Supervisor {
+    pub fn new(config: SharedConfig) -> Self {
+        Supervisor {
+            config,
+            stats: Default::default(),
+        }
+    }
+
+    /// 再起動の記録
+    pub fn stats(&self) -> Vec<(&'static str, Stats)> {
+        let stats = self.stats.lock().unwrap();
+        stats.iter().map(|(k, v)| (*k, v.clone())).collect()
+    }
+
+    /// 監視対象のタスクを起動
+    ///
+    /// `f`はタスクを起動するたびに呼び出され、`token`の複製を受け取る。
+    pub fn spawn<F, Fut>(&self, name: &'static str, token: Token, mut f: F) -> JoinHandle<()>
+    where
+        F: FnMut(Token) -> Fut + Send + 'static,
+        Fut: Future<Output = EResult<()>> + Send + 'static,
+    {
+        let config = self.config.clone();
+        let stats = self.stats.clone();
+        stats.lock().unwrap().entry(name).or_default();
+
+        task::spawn(async move {
+            let mut failures = 0; // 連続して失敗した回数
+
+            loop {
+                let start = Instant::now();
+                let result = f(token.clone()).await;
+
+                if token.is_cancelled() {
+                    // 要求された終了
+                    return;
+                }
+
+                let conf = config.read().unwrap().supervisor.clone();
+
+                // 十分な時間動作していた場合は連続失敗とみなさない
+                if start.elapsed() >= config::millis(conf.max_backoff_ms) {
+                    failures = 0;
+                }
+
+                let err = match result {
+                    Ok(()) => "exited unexpectedly".to_string(),
+                    Err(e) => e.to_string(),
+                };
+                eprintln!("supervisor: {name} stopped: {err}");
+
+                {
+                    let mut stats = stats.lock().unwrap();
+                    let s = stats.entry(name).or_default();
+                    s.last_error = Some(err);
+
+                    if failures >= conf.max_restarts {
+                        eprintln!("supervisor: {name} failed {failures} times, giving up");
+                        s.gave_up = true;
+                        return;
+                    }
+
+                    s.restarts += 1;
+                }
+
+                let wait = backoff(
+                    config::millis(conf.initial_backoff_ms),
+                    config::millis(conf.max_backoff_ms),
+                    failures,
+                );
+                failures += 1;
+
+                println!("supervisor: restarting {name} in {} ms", wait.as_millis());
+
+                // 待機中の終了要求
+                if timeout(wait, token.cancelled()).await.is_ok() {
+                    return;
+                }
+            }
+        })
+    }
+}
+
+/// 指数バックオフの待ち時間
+fn backoff(initial: Duration, max: Duration, failures: u32) -> Duration {
+    initial.saturating_mul(1 << failures.min(16)).min(max)
+}
+
+/// `Runner::run`の結果を、監視対象として待機可能なFutureに変換
+pub async fn join(hdl: EResult<JoinHandle<EResult<()>>>) -> EResult<()> {
+    hdl?.await
+}